@@ -0,0 +1,118 @@
+// Opaque and cutout chunks: lit the same way StandardMaterial does it, except that UVs
+// that run on past the face's tile (on greedy meshed quads) wrap back into it.
+
+#import bevy_pbr::mesh_view_bindings
+#import bevy_pbr::mesh_bindings
+#import bevy_pbr::pbr_types
+
+#import bevy_pbr::utils
+#import bevy_pbr::clustered_forward
+#import bevy_pbr::lighting
+#import bevy_pbr::pbr_ambient
+#import bevy_pbr::shadows
+#import bevy_pbr::fog
+#import bevy_pbr::pbr_functions
+#import bevy_pbr::mesh_functions
+
+struct ChunkMaterial {
+    reflectance: f32,
+    perceptual_roughness: f32,
+    alpha_cutoff: f32,
+    flags: u32,
+    sky_dimming: f32,
+    light_falloff: f32,
+};
+
+@group(1) @binding(0)
+var<uniform> chunk: ChunkMaterial;
+@group(1) @binding(1)
+var chunk_texture: texture_2d<f32>;
+@group(1) @binding(2)
+var chunk_sampler: sampler;
+
+struct Vertex {
+    @location(0) position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) uv: vec2<f32>,
+    @location(4) color: vec4<f32>,
+    @location(7) tile: vec4<f32>,
+    @location(8) light: vec2<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) world_position: vec4<f32>,
+    @location(1) world_normal: vec3<f32>,
+    @location(2) uv: vec2<f32>,
+    @location(3) color: vec4<f32>,
+    @location(4) tile: vec4<f32>,
+};
+
+struct FragmentInput {
+    @builtin(front_facing) is_front: bool,
+    @builtin(position) frag_coord: vec4<f32>,
+    @location(0) world_position: vec4<f32>,
+    @location(1) world_normal: vec3<f32>,
+    @location(2) uv: vec2<f32>,
+    @location(3) color: vec4<f32>,
+    @location(4) tile: vec4<f32>,
+};
+
+// The tile is (min, size) in the atlas. UVs inside it are left alone, so single faces
+// sample exactly where they would with StandardMaterial, and anything past it repeats
+// the tile once per block.
+fn tile_uv(uv: vec2<f32>, tile: vec4<f32>) -> vec2<f32> {
+    let local = (uv - tile.xy) / tile.zw;
+    let inside = local >= vec2<f32>(0.0) & local <= vec2<f32>(1.0);
+    return tile.xy + select(fract(local), local, inside) * tile.zw;
+}
+
+// How much a face's light darkens it: whichever of its block and sky light is brighter,
+// with the sky taken down by the time of day. Both come in as levels below MAX_LIGHT, so
+// the brighter one is the smaller.
+fn brightness(light: vec2<f32>) -> f32 {
+    return pow(chunk.light_falloff, min(light.x, light.y + chunk.sky_dimming));
+}
+
+@vertex
+fn vertex(vertex: Vertex) -> VertexOutput {
+    var out: VertexOutput;
+    out.world_position = mesh_position_local_to_world(mesh.model, vec4<f32>(vertex.position, 1.0));
+    out.world_normal = mesh_normal_local_to_world(vertex.normal);
+    out.clip_position = mesh_position_world_to_clip(out.world_position);
+    out.uv = vertex.uv;
+    out.color = vec4<f32>(vertex.color.rgb * brightness(vertex.light), vertex.color.a);
+    out.tile = vertex.tile;
+    return out;
+}
+
+@fragment
+fn fragment(in: FragmentInput) -> @location(0) vec4<f32> {
+    let texel = textureSample(chunk_texture, chunk_sampler, tile_uv(in.uv, in.tile));
+
+    var pbr_input = pbr_input_new();
+    pbr_input.material.base_color = in.color * texel;
+    pbr_input.material.reflectance = chunk.reflectance;
+    pbr_input.material.perceptual_roughness = chunk.perceptual_roughness;
+    pbr_input.material.alpha_cutoff = chunk.alpha_cutoff;
+    pbr_input.material.flags = chunk.flags;
+    pbr_input.frag_coord = in.frag_coord;
+    pbr_input.world_position = in.world_position;
+    pbr_input.world_normal = prepare_world_normal(in.world_normal, false, in.is_front);
+    pbr_input.is_orthographic = view.projection[3].w == 1.0;
+    pbr_input.N = pbr_input.world_normal;
+    pbr_input.V = calculate_view(in.world_position, pbr_input.is_orthographic);
+    pbr_input.flags = mesh.flags;
+
+    var output_color = pbr(pbr_input);
+
+    if fog.mode != FOG_MODE_OFF {
+        output_color = apply_fog(output_color, in.world_position.xyz, view.world_position.xyz);
+    }
+
+#ifdef TONEMAP_IN_SHADER
+    output_color = tone_mapping(output_color);
+#endif
+
+    return output_color;
+}
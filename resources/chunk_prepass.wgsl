@@ -0,0 +1,98 @@
+// Chunks in the depth prepass and shadow maps: the same as Bevy's prepass, except that
+// cutout texels are left out using the wrapped tile UVs.
+
+#import bevy_pbr::prepass_bindings
+#import bevy_pbr::mesh_functions
+
+struct ChunkMaterial {
+    reflectance: f32,
+    perceptual_roughness: f32,
+    alpha_cutoff: f32,
+    flags: u32,
+    sky_dimming: f32,
+    light_falloff: f32,
+};
+
+@group(1) @binding(0)
+var<uniform> chunk: ChunkMaterial;
+@group(1) @binding(1)
+var chunk_texture: texture_2d<f32>;
+@group(1) @binding(2)
+var chunk_sampler: sampler;
+
+struct Vertex {
+    @location(0) position: vec3<f32>,
+#ifdef VERTEX_UVS
+    @location(1) uv: vec2<f32>,
+#endif
+#ifdef NORMAL_PREPASS
+    @location(2) normal: vec3<f32>,
+#endif
+    @location(7) tile: vec4<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+#ifdef VERTEX_UVS
+    @location(0) uv: vec2<f32>,
+#endif
+#ifdef NORMAL_PREPASS
+    @location(1) world_normal: vec3<f32>,
+#endif
+    @location(2) tile: vec4<f32>,
+};
+
+// Same as in chunk.wgsl.
+fn tile_uv(uv: vec2<f32>, tile: vec4<f32>) -> vec2<f32> {
+    let local = (uv - tile.xy) / tile.zw;
+    let inside = local >= vec2<f32>(0.0) & local <= vec2<f32>(1.0);
+    return tile.xy + select(fract(local), local, inside) * tile.zw;
+}
+
+@vertex
+fn vertex(vertex: Vertex) -> VertexOutput {
+    var out: VertexOutput;
+
+    out.clip_position = mesh_position_local_to_clip(mesh.model, vec4<f32>(vertex.position, 1.0));
+#ifdef DEPTH_CLAMP_ORTHO
+    out.clip_position.z = min(out.clip_position.z, 1.0);
+#endif
+#ifdef VERTEX_UVS
+    out.uv = vertex.uv;
+#endif
+#ifdef NORMAL_PREPASS
+    out.world_normal = mesh_normal_local_to_world(vertex.normal);
+#endif
+    out.tile = vertex.tile;
+    return out;
+}
+
+struct FragmentInput {
+#ifdef VERTEX_UVS
+    @location(0) uv: vec2<f32>,
+#endif
+#ifdef NORMAL_PREPASS
+    @location(1) world_normal: vec3<f32>,
+#endif
+    @location(2) tile: vec4<f32>,
+};
+
+#ifdef NORMAL_PREPASS
+@fragment
+fn fragment(in: FragmentInput) -> @location(0) vec4<f32> {
+#else
+@fragment
+fn fragment(in: FragmentInput) {
+#endif
+#ifdef ALPHA_MASK
+#ifdef VERTEX_UVS
+    let color = textureSample(chunk_texture, chunk_sampler, tile_uv(in.uv, in.tile));
+    if color.a < chunk.alpha_cutoff {
+        discard;
+    }
+#endif
+#endif
+#ifdef NORMAL_PREPASS
+    return vec4<f32>(in.world_normal * 0.5 + vec3<f32>(0.5), 1.0);
+#endif
+}
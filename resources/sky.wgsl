@@ -0,0 +1,45 @@
+// Sky: a gradient from the horizon up to the zenith, with the sun drawn in as a disc.
+
+#import bevy_pbr::mesh_view_bindings
+
+#ifdef TONEMAP_IN_SHADER
+#import bevy_core_pipeline::tonemapping
+#endif
+
+struct SkyMaterial {
+    horizon: vec4<f32>,
+    zenith: vec4<f32>,
+    sun_color: vec4<f32>,
+    sun_direction: vec3<f32>,
+    sun_size: f32,
+};
+
+@group(1) @binding(0)
+var<uniform> sky: SkyMaterial;
+
+struct FragmentInput {
+    #import bevy_pbr::mesh_vertex_output
+};
+
+@fragment
+fn fragment(in: FragmentInput) -> @location(0) vec4<f32> {
+    // The dome follows the camera, so this is the direction being looked in.
+    let direction = normalize(in.world_position.xyz - view.world_position.xyz);
+
+    // Most of the change happens low down, like a real sky.
+    let height = sqrt(clamp(direction.y, 0.0, 1.0));
+    var color = mix(sky.horizon, sky.zenith, height);
+
+    // A hard edged disc with a soft glow around it.
+    let facing = dot(direction, normalize(sky.sun_direction));
+    let disc = smoothstep(cos(sky.sun_size) - 0.0005, cos(sky.sun_size), facing);
+    let glow = pow(max(facing, 0.0), 64.0) * 0.3;
+    color = mix(color, sky.sun_color, clamp(disc + glow, 0.0, 1.0) * sky.sun_color.a);
+    color.a = 1.0;
+
+#ifdef TONEMAP_IN_SHADER
+    color = tone_mapping(color);
+#endif
+
+    return color;
+}
@@ -0,0 +1,145 @@
+// Water: the top faces bob up and down and their normals follow the waves, everything
+// else is lit the same way StandardMaterial does it.
+
+#import bevy_pbr::mesh_view_bindings
+#import bevy_pbr::mesh_bindings
+#import bevy_pbr::pbr_types
+
+#import bevy_pbr::utils
+#import bevy_pbr::clustered_forward
+#import bevy_pbr::lighting
+#import bevy_pbr::pbr_ambient
+#import bevy_pbr::shadows
+#import bevy_pbr::fog
+#import bevy_pbr::pbr_functions
+#import bevy_pbr::mesh_functions
+
+struct WaterMaterial {
+    color: vec4<f32>,
+    wave_height: f32,
+    wave_speed: f32,
+    sky_dimming: f32,
+    light_falloff: f32,
+};
+
+@group(1) @binding(0)
+var<uniform> water: WaterMaterial;
+@group(1) @binding(1)
+var water_texture: texture_2d<f32>;
+@group(1) @binding(2)
+var water_sampler: sampler;
+
+struct Vertex {
+    @location(0) position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) uv: vec2<f32>,
+    @location(4) color: vec4<f32>,
+    @location(7) tile: vec4<f32>,
+    @location(8) light: vec2<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) world_position: vec4<f32>,
+    @location(1) world_normal: vec3<f32>,
+    @location(2) uv: vec2<f32>,
+    @location(3) color: vec4<f32>,
+    @location(4) tile: vec4<f32>,
+};
+
+struct FragmentInput {
+    @builtin(front_facing) is_front: bool,
+    @builtin(position) frag_coord: vec4<f32>,
+    @location(0) world_position: vec4<f32>,
+    @location(1) world_normal: vec3<f32>,
+    @location(2) uv: vec2<f32>,
+    @location(3) color: vec4<f32>,
+    @location(4) tile: vec4<f32>,
+};
+
+// Same as in chunk.wgsl, greedy meshed water repeats its tile once per block.
+fn tile_uv(uv: vec2<f32>, tile: vec4<f32>) -> vec2<f32> {
+    let local = (uv - tile.xy) / tile.zw;
+    let inside = local >= vec2<f32>(0.0) & local <= vec2<f32>(1.0);
+    return tile.xy + select(fract(local), local, inside) * tile.zw;
+}
+
+// Same as in chunk.wgsl.
+fn brightness(light: vec2<f32>) -> f32 {
+    return pow(water.light_falloff, min(light.x, light.y + water.sky_dimming));
+}
+
+// Two sine waves crossing at an angle, so the pattern doesn't look like stripes.
+fn wave_phase(xz: vec2<f32>) -> vec2<f32> {
+    let t = globals.time * water.wave_speed;
+    return vec2<f32>(xz.x * 0.8 + t, xz.y * 0.6 + t * 1.3);
+}
+
+// How far the surface is pushed down, 0 to wave_height.
+fn wave_depth(xz: vec2<f32>) -> f32 {
+    let phase = wave_phase(xz);
+    return (sin(phase.x) + sin(phase.y) + 2.0) * 0.25 * water.wave_height;
+}
+
+// The surface normal, tilted along the slope of the waves.
+fn wave_normal(xz: vec2<f32>) -> vec3<f32> {
+    let phase = wave_phase(xz);
+    let dx = cos(phase.x) * 0.8 * 0.25 * water.wave_height;
+    let dz = cos(phase.y) * 0.6 * 0.25 * water.wave_height;
+    return normalize(vec3<f32>(dx, 1.0, dz));
+}
+
+@vertex
+fn vertex(vertex: Vertex) -> VertexOutput {
+    var out: VertexOutput;
+
+    out.world_position = mesh_position_local_to_world(mesh.model, vec4<f32>(vertex.position, 1.0));
+    out.world_normal = mesh_normal_local_to_world(vertex.normal);
+
+    // Only dip the surface, raising it would leave a gap above the sides.
+    if out.world_normal.y > 0.5 {
+        out.world_position.y -= wave_depth(out.world_position.xz);
+    }
+
+    out.clip_position = mesh_position_world_to_clip(out.world_position);
+    out.uv = vertex.uv;
+    out.color = vec4<f32>(vertex.color.rgb * brightness(vertex.light), vertex.color.a);
+    out.tile = vertex.tile;
+    return out;
+}
+
+@fragment
+fn fragment(in: FragmentInput) -> @location(0) vec4<f32> {
+    let base_color = water.color * in.color * textureSample(water_texture, water_sampler, tile_uv(in.uv, in.tile));
+
+    // Worked out per pixel, so greedy meshed quads ripple as well.
+    var normal = normalize(in.world_normal);
+    if normal.y > 0.5 {
+        normal = wave_normal(in.world_position.xz);
+    }
+
+    var pbr_input = pbr_input_new();
+    pbr_input.material.base_color = base_color;
+    pbr_input.material.reflectance = 0.2;
+    pbr_input.material.perceptual_roughness = 0.1;
+    pbr_input.material.flags = STANDARD_MATERIAL_FLAGS_ALPHA_MODE_BLEND;
+    pbr_input.frag_coord = in.frag_coord;
+    pbr_input.world_position = in.world_position;
+    pbr_input.world_normal = prepare_world_normal(normal, false, in.is_front);
+    pbr_input.is_orthographic = view.projection[3].w == 1.0;
+    pbr_input.N = pbr_input.world_normal;
+    pbr_input.V = calculate_view(in.world_position, pbr_input.is_orthographic);
+    pbr_input.flags = mesh.flags;
+
+    var output_color = pbr(pbr_input);
+
+    if fog.mode != FOG_MODE_OFF {
+        output_color = apply_fog(output_color, in.world_position.xyz, view.world_position.xyz);
+    }
+
+#ifdef TONEMAP_IN_SHADER
+    output_color = tone_mapping(output_color);
+#endif
+
+    return output_color;
+}
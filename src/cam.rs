@@ -1,9 +1,41 @@
 use bevy::ecs::event::{Events, ManualEventReader};
-use bevy::input::mouse::MouseMotion;
+use bevy::input::mouse::{MouseMotion, MouseWheel};
 use bevy::prelude::*;
-use bevy::render::camera::ScalingMode;
 use bevy::window::{CursorGrabMode, PrimaryWindow};
 
+use crate::controls::{Action, Actions};
+use crate::edit::raycast;
+use crate::gamepad::GamepadInput;
+use crate::health::Health;
+use crate::menu::GameState;
+use crate::mode::GameMode;
+use crate::world::{BlockType, Map};
+
+// The player's bounding box, relative to their feet. The camera sits at eye height.
+pub const PLAYER_HALF_WIDTH: f32 = 0.3;
+pub const PLAYER_HEIGHT: f32 = 1.8;
+pub const PLAYER_EYE_HEIGHT: f32 = 1.6;
+const SKIN: f32 = 0.001; // Gap left between the player and a block after a collision
+const WALK_SPEED: f32 = 4.5;
+const JUMP_SPEED: f32 = 8.0;
+pub const GRAVITY: f32 = 25.0;
+const TERMINAL_VELOCITY: f32 = 50.0;
+const SWIM_SPEED: f32 = 2.5; // Across the water, instead of `WALK_SPEED`
+const SWIM_UP_SPEED: f32 = 4.0; // While holding jump
+const SWIM_GRAVITY: f32 = 6.0; // Instead of `GRAVITY`
+const BUOYANCY: f32 = 8.0; // Upwards, while the eyes are under, so a player floats up to the surface
+const WATER_DRAG: f32 = 2.0; // Share of vertical speed lost per second in water
+const FLY_SPEED_STEP: f32 = 1.2; // Multiplier per notch of the scroll wheel
+const MIN_FLY_SPEED: f32 = 1.0;
+const MAX_FLY_SPEED: f32 = 200.0;
+const SPRINT_MULTIPLIER: f32 = 1.6; // Applied to walking and flying speed
+const SPRINT_FOV: f32 = 10.0; // Degrees added to the field of view while sprinting
+const FOV_RATE: f32 = 8.0; // How quickly the field of view follows, per second
+const SPAWN_COLUMN: IVec2 = IVec2::ZERO; // Where the player starts, before finding land. Islands are centred on it
+const SPAWN_SEARCH_RADIUS: i32 = 256; // In blocks, how far to look for land around it
+const SPAWN_SEARCH_STEP: i32 = 4; // Blocks between the columns tried
+const CAMERA_CLEARANCE: f32 = 0.2; // Kept between a third person camera and the block behind it
+
 /// Keeps track of mouse motion events, pitch, and yaw
 #[derive(Resource, Default)]
 struct InputState {
@@ -12,52 +44,139 @@ struct InputState {
     yaw: f32,
 }
 
-/// Mouse sensitivity and movement speed
-
+/// Mouse sensitivity, flying speed and field of view, read every frame
 #[derive(Resource)]
-pub struct MovementSettings {
+pub struct CameraConfig {
     pub sensitivity: f32,
-    pub speed: f32,
+    pub fly_speed: f32,             // Blocks per second
+    pub fov: f32,                   // Vertical, in degrees
+    pub third_person_distance: f32, // Blocks behind the player's eyes
 }
 
-impl Default for MovementSettings {
+impl Default for CameraConfig {
     fn default() -> Self {
         Self {
             sensitivity: 0.00012,
-            speed: 12.,
+            fly_speed: 12.,
+            fov: 45.,
+            third_person_distance: 4.,
         }
     }
 }
 
+/// Whether the camera looks out of the player's eyes or from behind them, toggled with V
+#[derive(Resource, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum CameraView {
+    #[default]
+    FirstPerson,
+    ThirdPerson,
+}
+
 /// A marker component used in queries when you want flycams and not other cameras
 #[derive(Component)]
 pub struct FlyCam;
 
-/// Grabs/ungrabs mouse cursor
-fn toggle_grab_cursor(window: &mut Window) {
-    match window.cursor.grab_mode {
-        CursorGrabMode::None => {
-            window.cursor.grab_mode = CursorGrabMode::Confined;
-            window.cursor.visible = false;
-        }
-        _ => {
-            window.cursor.grab_mode = CursorGrabMode::None;
-            window.cursor.visible = true;
-        }
+/// The player's body, only shown in third person
+#[derive(Component)]
+struct PlayerBody;
+
+/// The player, whose transform is at their eyes and faces where they look. The camera
+/// follows it. When walking, the player falls and moves with `velocity` instead of
+/// flying.
+#[derive(Component, Default)]
+pub struct Player {
+    pub velocity: Vec3,
+    pub grounded: bool,
+    pub walking: bool,
+    pub sprinting: bool,    // Holding the sprint key while moving
+    pub swimming: bool,     // Walking into water, up to the waist
+    pub fall_distance: f32, // Blocks fallen since last on the ground or in water
+}
+
+/// Where the player first stood in this world, and comes back to when they die
+#[derive(Resource)]
+pub struct SpawnPoint(pub Vec3);
+
+/// Sent when a walking player hits the ground or water after falling
+pub struct Landed {
+    pub distance: f32, // In blocks
+    pub in_water: bool,
+}
+
+/// Grabs or releases the mouse cursor
+pub fn set_cursor_grab(window: &mut Window, grab: bool) {
+    if grab {
+        window.cursor.grab_mode = CursorGrabMode::Confined;
+        window.cursor.visible = false;
+    } else {
+        window.cursor.grab_mode = CursorGrabMode::None;
+        window.cursor.visible = true;
     }
 }
 
 /// Grabs the cursor when game first starts
 fn initial_grab_cursor(mut primary_window: Query<&mut Window, With<PrimaryWindow>>) {
     if let Ok(mut window) = primary_window.get_single_mut() {
-        toggle_grab_cursor(&mut window);
+        set_cursor_grab(&mut window, true);
     } else {
         warn!("Primary window not found for `initial_grab_cursor`!");
     }
 }
 
-/// Spawns the `Camera3dBundle` to be controlled
-fn setup_player(mut commands: Commands) {
+/// Where the player's eyes start: standing on the ground at `SPAWN_COLUMN`, or on the
+/// nearest land to it if that's sea. Searches outwards in square rings.
+fn spawn_point(map: &Map) -> Vec3 {
+    let land = (0..=SPAWN_SEARCH_RADIUS / SPAWN_SEARCH_STEP)
+        .flat_map(|ring| {
+            (-ring..=ring)
+                .flat_map(move |x| (-ring..=ring).map(move |z| IVec2::new(x, z)))
+                .filter(move |offset| offset.x.abs() == ring || offset.y.abs() == ring)
+        })
+        .map(|offset| SPAWN_COLUMN + offset * SPAWN_SEARCH_STEP)
+        .find(|column| !map.is_ocean(column.x, column.y))
+        .unwrap_or(SPAWN_COLUMN);
+
+    let ground = map.surface_height(land.x, land.y);
+    // Middle of the block, feet on top of it.
+    Vec3::new(
+        land.x as f32 + 0.5,
+        (ground + 1) as f32 + PLAYER_EYE_HEIGHT,
+        land.y as f32 + 0.5,
+    )
+}
+
+/// Spawns the player standing on the terrain, with their body and the `Camera3dBundle`
+/// that follows them
+fn setup_player(
+    mut commands: Commands,
+    map: Res<Map>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let spawn = spawn_point(&map);
+    let transform = Transform::from_translation(spawn);
+    commands.insert_resource(SpawnPoint(spawn));
+    commands.spawn((
+        SpatialBundle::from_transform(transform),
+        Player::default(),
+        Health::default(),
+    ));
+    commands.spawn((
+        PbrBundle {
+            mesh: meshes.add(
+                shape::Box::new(
+                    PLAYER_HALF_WIDTH * 2.0,
+                    PLAYER_HEIGHT,
+                    PLAYER_HALF_WIDTH * 2.0,
+                )
+                .into(),
+            ),
+            material: materials.add(Color::rgb(0.25, 0.45, 0.75).into()),
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+        PlayerBody,
+    ));
     commands.spawn((
         Camera3dBundle {
             // projection: OrthographicProjection {
@@ -66,62 +185,264 @@ fn setup_player(mut commands: Commands) {
             //     ..default()
             // }
             // .into(),
-            transform: Transform::from_xyz(255.0, 10.0, 255.0).looking_at(Vec3::ZERO, Vec3::Y),
+            transform,
             ..Default::default()
         },
         FlyCam,
     ));
 }
 
-/// Handles keyboard input and movement
+/// Handles keyboard and gamepad input and movement
 fn player_move(
-    keys: Res<Input<KeyCode>>,
+    actions: Actions,
+    gamepad: Res<GamepadInput>,
     time: Res<Time>,
     primary_window: Query<&Window, With<PrimaryWindow>>,
-    settings: Res<MovementSettings>,
-    mut query: Query<&mut Transform, With<FlyCam>>,
+    settings: Res<CameraConfig>,
+    mut query: Query<(&mut Transform, &mut Player)>,
 ) {
     if let Ok(window) = primary_window.get_single() {
-        for mut transform in query.iter_mut() {
+        for (mut transform, mut player) in query.iter_mut() {
+            if player.walking {
+                continue;
+            }
+
             let mut velocity = Vec3::ZERO;
             let local_z = transform.local_z();
             let forward = -Vec3::new(local_z.x, 0., local_z.z);
             let right = Vec3::new(local_z.z, 0., -local_z.x);
 
-            for key in keys.get_pressed() {
-                match window.cursor.grab_mode {
-                    CursorGrabMode::None => (),
-                    _ => match key {
-                        KeyCode::W => velocity += forward,
-                        KeyCode::S => velocity -= forward,
-                        KeyCode::A => velocity -= right,
-                        KeyCode::D => velocity += right,
-                        KeyCode::Space => velocity += Vec3::Y,
-                        KeyCode::LShift => velocity -= Vec3::Y,
-                        _ => (),
-                    },
+            if window.cursor.grab_mode != CursorGrabMode::None {
+                for (action, direction) in [
+                    (Action::MoveForward, forward),
+                    (Action::MoveBack, -forward),
+                    (Action::MoveLeft, -right),
+                    (Action::MoveRight, right),
+                    (Action::Jump, Vec3::Y),
+                    (Action::Descend, -Vec3::Y),
+                ] {
+                    if actions.pressed(action) {
+                        velocity += direction;
+                    }
                 }
             }
 
+            // The sticks are analog, so they're added after the keys are normalized.
             velocity = velocity.normalize_or_zero();
+            if window.cursor.grab_mode != CursorGrabMode::None {
+                velocity += forward * gamepad.movement.y
+                    + right * gamepad.movement.x
+                    + Vec3::Y * gamepad.rise;
+                velocity = velocity.clamp_length_max(1.0);
+            }
 
-            transform.translation += velocity * time.delta_seconds() * settings.speed
+            player.sprinting = velocity != Vec3::ZERO && is_sprinting(&actions, &gamepad, window);
+            let speed = if player.sprinting {
+                settings.fly_speed * SPRINT_MULTIPLIER
+            } else {
+                settings.fly_speed
+            };
+
+            transform.translation += velocity * time.delta_seconds() * speed
         }
     } else {
         warn!("Primary window not found for `player_move`!");
     }
 }
 
-/// Handles looking around if cursor is locked
+/// Whether the sprint key or the left stick button is held, with the cursor grabbed
+fn is_sprinting(actions: &Actions, gamepad: &GamepadInput, window: &Window) -> bool {
+    window.cursor.grab_mode != CursorGrabMode::None
+        && (actions.pressed(Action::Sprint) || gamepad.sprint)
+}
+
+/// Switches between flying and walking when G is pressed, in creative mode
+fn toggle_walking(actions: Actions, mode: Res<GameMode>, mut query: Query<&mut Player>) {
+    if *mode == GameMode::Creative && actions.just_pressed(Action::ToggleWalking) {
+        for mut player in query.iter_mut() {
+            player.walking = !player.walking;
+            player.velocity = Vec3::ZERO;
+            player.grounded = false;
+            player.fall_distance = 0.0;
+        }
+    }
+}
+
+/// Moves walking players: WASD or the left stick on the ground plane, gravity, and
+/// Space or A to jump
+fn player_walk(
+    actions: Actions,
+    gamepad: Res<GamepadInput>,
+    time: Res<Time>,
+    primary_window: Query<&Window, With<PrimaryWindow>>,
+    map: Res<Map>,
+    mut landed: EventWriter<Landed>,
+    mut query: Query<(&mut Transform, &mut Player)>,
+) {
+    let Ok(window) = primary_window.get_single() else {
+        warn!("Primary window not found for `player_walk`!");
+        return;
+    };
+    let grabbed = window.cursor.grab_mode != CursorGrabMode::None;
+    let delta = time.delta_seconds();
+
+    for (mut transform, mut player) in query.iter_mut() {
+        if !player.walking {
+            continue;
+        }
+
+        let local_z = transform.local_z();
+        let forward = -Vec3::new(local_z.x, 0., local_z.z).normalize_or_zero();
+        let right = Vec3::new(local_z.z, 0., -local_z.x).normalize_or_zero();
+
+        let mut direction = Vec3::ZERO;
+        if grabbed {
+            for (action, step) in [
+                (Action::MoveForward, forward),
+                (Action::MoveBack, -forward),
+                (Action::MoveLeft, -right),
+                (Action::MoveRight, right),
+            ] {
+                if actions.pressed(action) {
+                    direction += step;
+                }
+            }
+        }
+        let mut direction = direction.normalize_or_zero();
+        if grabbed {
+            direction += forward * gamepad.movement.y + right * gamepad.movement.x;
+        }
+        let waist = transform.translation - Vec3::Y * (PLAYER_EYE_HEIGHT - PLAYER_HEIGHT / 2.0);
+        player.swimming = in_water(&map, waist);
+        player.sprinting = direction != Vec3::ZERO && is_sprinting(&actions, &gamepad, window);
+        let speed = if player.swimming {
+            SWIM_SPEED
+        } else {
+            WALK_SPEED
+        };
+        let speed = if player.sprinting {
+            speed * SPRINT_MULTIPLIER
+        } else {
+            speed
+        };
+        let direction = direction.clamp_length_max(1.0) * speed;
+        player.velocity.x = direction.x;
+        player.velocity.z = direction.z;
+
+        let jump = grabbed && (actions.pressed(Action::Jump) || gamepad.rise > 0.0);
+        if player.swimming {
+            let buoyancy = if in_water(&map, transform.translation) {
+                BUOYANCY
+            } else {
+                0.0
+            };
+            player.velocity.y += (buoyancy - SWIM_GRAVITY) * delta;
+            if jump {
+                player.velocity.y = player.velocity.y.max(SWIM_UP_SPEED);
+            }
+            player.velocity.y *= (-WATER_DRAG * delta).exp();
+        } else {
+            if player.grounded && jump {
+                player.velocity.y = JUMP_SPEED;
+            }
+            player.velocity.y = (player.velocity.y - GRAVITY * delta).max(-TERMINAL_VELOCITY);
+        }
+
+        let height = transform.translation.y;
+        let Player {
+            velocity, grounded, ..
+        } = player.as_mut();
+        *grounded = move_and_collide(&map, &mut transform.translation, velocity, delta);
+
+        if player.grounded || player.swimming {
+            if player.fall_distance > 0.0 {
+                landed.send(Landed {
+                    distance: player.fall_distance,
+                    in_water: player.swimming,
+                });
+            }
+            player.fall_distance = 0.0;
+        } else {
+            player.fall_distance += (height - transform.translation.y).max(0.0);
+        }
+    }
+}
+
+/// Whether the block at `pos` is water
+fn in_water(map: &Map, pos: Vec3) -> bool {
+    map.get_block(pos.floor().as_ivec3()) == Some(BlockType::Water)
+}
+
+/// The player's bounding box when their eyes are at `eye`.
+pub fn player_aabb(eye: Vec3) -> (Vec3, Vec3) {
+    let feet = eye - Vec3::Y * PLAYER_EYE_HEIGHT;
+    (
+        feet - Vec3::new(PLAYER_HALF_WIDTH, 0.0, PLAYER_HALF_WIDTH),
+        feet + Vec3::new(PLAYER_HALF_WIDTH, PLAYER_HEIGHT, PLAYER_HALF_WIDTH),
+    )
+}
+
+/// Whether any solid block overlaps the box. Water doesn't count.
+pub fn collides(map: &Map, min: Vec3, max: Vec3) -> bool {
+    let (min, max) = (min.floor().as_ivec3(), max.ceil().as_ivec3() - IVec3::ONE);
+    for x in min.x..=max.x {
+        for y in min.y..=max.y {
+            for z in min.z..=max.z {
+                if map.is_solid(IVec3::new(x, y, z)) {
+                    return true;
+                }
+            }
+        }
+    }
+    false
+}
+
+/// Moves the player one axis at a time, so they slide along walls, stopping at solid
+/// blocks. Steps are kept under half a block so fast falls can't skip through the
+/// ground. Returns whether the player landed on something.
+fn move_and_collide(map: &Map, eye: &mut Vec3, velocity: &mut Vec3, delta: f32) -> bool {
+    let motion = *velocity * delta;
+    let steps = (motion.abs().max_element() / 0.5).ceil().max(1.0);
+    let step = motion / steps;
+    let mut grounded = false;
+
+    for _ in 0..steps as u32 {
+        for axis in [1, 0, 2] {
+            if step[axis] == 0.0 || velocity[axis] == 0.0 {
+                continue;
+            }
+
+            eye[axis] += step[axis];
+            let (min, max) = player_aabb(*eye);
+            if !collides(map, min, max) {
+                continue;
+            }
+
+            // Back out to the face of the block that was hit.
+            if step[axis] > 0.0 {
+                eye[axis] -= max[axis] - max[axis].floor() + SKIN;
+            } else {
+                eye[axis] += min[axis].ceil() - min[axis] + SKIN;
+                grounded |= axis == 1;
+            }
+            velocity[axis] = 0.0;
+        }
+    }
+
+    grounded
+}
+
+/// Handles looking around with the mouse or right stick if cursor is locked
 fn player_look(
-    settings: Res<MovementSettings>,
+    settings: Res<CameraConfig>,
+    gamepad: Res<GamepadInput>,
     primary_window: Query<&Window, With<PrimaryWindow>>,
     mut state: ResMut<InputState>,
     motion: Res<Events<MouseMotion>>,
-    mut query: Query<&mut Transform, With<FlyCam>>,
+    mut query: Query<&mut Transform, With<Player>>,
 ) {
     if let Ok(window) = primary_window.get_single() {
-        let mut delta_state = state.as_mut();
+        let delta_state = state.as_mut();
         for mut transform in query.iter_mut() {
             for ev in delta_state.reader_motion.iter(&motion) {
                 match window.cursor.grab_mode {
@@ -142,22 +463,122 @@ fn player_look(
                 transform.rotation = Quat::from_axis_angle(Vec3::Y, delta_state.yaw)
                     * Quat::from_axis_angle(Vec3::X, delta_state.pitch);
             }
+
+            // The right stick turns at a steady rate while it's held, not per event.
+            if gamepad.look != Vec2::ZERO && window.cursor.grab_mode != CursorGrabMode::None {
+                delta_state.yaw -= gamepad.look.x;
+                delta_state.pitch = (delta_state.pitch + gamepad.look.y).clamp(-1.54, 1.54);
+                transform.rotation = Quat::from_axis_angle(Vec3::Y, delta_state.yaw)
+                    * Quat::from_axis_angle(Vec3::X, delta_state.pitch);
+            }
         }
     } else {
         warn!("Primary window not found for `player_look`!");
     }
 }
 
-fn cursor_grab(
-    keys: Res<Input<KeyCode>>,
-    mut primary_window: Query<&mut Window, With<PrimaryWindow>>,
+/// Scrolling while holding the speed key (Alt) and flying changes the flying speed
+fn adjust_fly_speed(
+    actions: Actions,
+    mut scroll: EventReader<MouseWheel>,
+    mut config: ResMut<CameraConfig>,
+    players: Query<&Player>,
 ) {
-    if let Ok(mut window) = primary_window.get_single_mut() {
-        if keys.just_pressed(KeyCode::Escape) {
-            toggle_grab_cursor(&mut window);
+    let notches: f32 = scroll.iter().map(|event| event.y.signum()).sum();
+    let flying = players.iter().all(|player| !player.walking);
+    if notches == 0.0 || !flying || !actions.pressed(Action::AdjustFlySpeed) {
+        return;
+    }
+
+    config.fly_speed =
+        (config.fly_speed * FLY_SPEED_STEP.powf(notches)).clamp(MIN_FLY_SPEED, MAX_FLY_SPEED);
+    info!("Fly speed: {:.1}", config.fly_speed);
+}
+
+fn toggle_view(actions: Actions, mut view: ResMut<CameraView>) {
+    if actions.just_pressed(Action::ToggleView) {
+        *view = match *view {
+            CameraView::FirstPerson => CameraView::ThirdPerson,
+            CameraView::ThirdPerson => CameraView::FirstPerson,
+        };
+    }
+}
+
+/// Puts the camera at the player's eyes, or behind them in third person, looking the
+/// same way. In third person the camera is pulled in when a block is behind the player
+/// so it doesn't end up inside the terrain, and the player's body is shown.
+#[allow(clippy::type_complexity)]
+fn follow_player(
+    view: Res<CameraView>,
+    config: Res<CameraConfig>,
+    map: Res<Map>,
+    players: Query<&Transform, (With<Player>, Without<FlyCam>, Without<PlayerBody>)>,
+    mut cameras: Query<&mut Transform, (With<FlyCam>, Without<PlayerBody>)>,
+    mut bodies: Query<(&mut Transform, &mut Visibility), With<PlayerBody>>,
+) {
+    let Ok(player) = players.get_single() else {
+        return;
+    };
+
+    for mut camera in cameras.iter_mut() {
+        camera.rotation = player.rotation;
+        camera.translation = match *view {
+            CameraView::FirstPerson => player.translation,
+            CameraView::ThirdPerson => {
+                let back = player.back();
+                let reach = config.third_person_distance;
+                let distance = raycast(&map, player.translation, back, reach)
+                    .map_or(reach, |hit| (hit.distance - CAMERA_CLEARANCE).max(0.0));
+                player.translation + back * distance
+            }
+        };
+    }
+
+    for (mut body, mut visibility) in bodies.iter_mut() {
+        // Turns with the player, but doesn't lean when they look up or down.
+        let (yaw, _, _) = player.rotation.to_euler(EulerRot::YXZ);
+        body.translation = player.translation - Vec3::Y * (PLAYER_EYE_HEIGHT - PLAYER_HEIGHT / 2.0);
+        body.rotation = Quat::from_rotation_y(yaw);
+        *visibility = match *view {
+            CameraView::FirstPerson => Visibility::Hidden,
+            CameraView::ThirdPerson => Visibility::Visible,
+        };
+    }
+}
+
+/// Eases the camera's field of view towards the one in `CameraConfig`, widened a
+/// little while sprinting
+fn update_fov(
+    time: Res<Time>,
+    config: Res<CameraConfig>,
+    players: Query<&Player>,
+    mut query: Query<&mut Projection, With<FlyCam>>,
+) {
+    let blend = 1.0 - (-FOV_RATE * time.delta_seconds()).exp();
+    let sprinting = players.iter().any(|player| player.sprinting);
+    for mut projection in query.iter_mut() {
+        let target = if sprinting {
+            config.fov + SPRINT_FOV
+        } else {
+            config.fov
+        }
+        .to_radians();
+
+        // Only touch the projection when it has to move, so it isn't marked changed
+        // every frame.
+        let Projection::Perspective(current) = projection.as_ref() else {
+            continue;
+        };
+        if current.fov == target {
+            continue;
+        }
+        let mut fov = current.fov + (target - current.fov) * blend;
+        if (fov - target).abs() < 1e-4 {
+            fov = target;
+        }
+        if let Projection::Perspective(perspective) = projection.as_mut() {
+            perspective.fov = fov;
         }
-    } else {
-        warn!("Primary window not found for `cursor_grab`!");
     }
 }
 
@@ -166,24 +587,52 @@ pub struct PlayerPlugin;
 impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<InputState>()
-            .init_resource::<MovementSettings>()
+            .init_resource::<CameraConfig>()
+            .init_resource::<CameraView>()
+            .add_event::<Landed>()
             .add_system(setup_player.on_startup())
             .add_system(initial_grab_cursor.on_startup())
-            .add_system(player_move)
-            .add_system(player_look)
-            .add_system(cursor_grab);
+            .add_system(toggle_view.run_if(in_state(GameState::Playing)))
+            .add_system(toggle_walking.run_if(in_state(GameState::Playing)))
+            .add_system(
+                player_walk
+                    .after(toggle_walking)
+                    .run_if(in_state(GameState::Playing)),
+            )
+            .add_system(adjust_fly_speed.run_if(in_state(GameState::Playing)))
+            .add_system(
+                player_move
+                    .after(adjust_fly_speed)
+                    .run_if(in_state(GameState::Playing)),
+            )
+            .add_system(player_look.run_if(in_state(GameState::Playing)))
+            .add_system(
+                follow_player
+                    .after(toggle_view)
+                    .after(player_move)
+                    .after(player_walk)
+                    .after(player_look),
+            )
+            .add_system(update_fov.after(player_move).after(player_walk));
     }
 }
 
-/// Same as [`PlayerPlugin`] but does not spawn a camera
+/// Same as [`PlayerPlugin`] but does not spawn the player or a camera
+#[allow(dead_code)]
 pub struct NoCameraPlayerPlugin;
 impl Plugin for NoCameraPlayerPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<InputState>()
-            .init_resource::<MovementSettings>()
+            .init_resource::<CameraConfig>()
+            .add_event::<Landed>()
             .add_system(initial_grab_cursor.on_startup())
-            .add_system(player_move)
-            .add_system(player_look)
-            .add_system(cursor_grab);
+            .add_system(toggle_walking.run_if(in_state(GameState::Playing)))
+            .add_system(
+                player_walk
+                    .after(toggle_walking)
+                    .run_if(in_state(GameState::Playing)),
+            )
+            .add_system(player_move.run_if(in_state(GameState::Playing)))
+            .add_system(player_look.run_if(in_state(GameState::Playing)));
     }
 }
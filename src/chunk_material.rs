@@ -0,0 +1,124 @@
+use bevy::pbr::{MaterialPipeline, MaterialPipelineKey, StandardMaterialFlags};
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use bevy::render::mesh::{MeshVertexAttribute, MeshVertexBufferLayout};
+use bevy::render::render_resource::{
+    AsBindGroup, RenderPipelineDescriptor, ShaderRef, SpecializedMeshPipelineError, VertexFormat,
+};
+
+use crate::world::LIGHT_FALLOFF;
+
+const CHUNK_SHADER: &str = "../resources/chunk.wgsl";
+const CHUNK_PREPASS_SHADER: &str = "../resources/chunk_prepass.wgsl";
+const TILE_LOCATION: u32 = 7; // Past every attribute the mesh and prepass pipelines use
+const LIGHT_LOCATION: u32 = 8;
+
+/// The atlas rect of the tile a vertex's face shows, as min then size. Greedy meshed
+/// quads' UVs run on past the tile, one tile per block, and the shaders wrap them back
+/// into this.
+pub const ATTRIBUTE_TILE: MeshVertexAttribute =
+    MeshVertexAttribute::new("Tile", 0x6d63_7469, VertexFormat::Float32x4);
+
+/// How many levels below `MAX_LIGHT` the block light and the sky light a vertex's face
+/// looks into are. The shaders take the sky light down by the time of day, so it can
+/// change without remeshing anything.
+pub const ATTRIBUTE_LIGHT: MeshVertexAttribute =
+    MeshVertexAttribute::new("Light", 0x6d63_6c74, VertexFormat::Float32x2);
+
+/// Adds `ATTRIBUTE_TILE` and `ATTRIBUTE_LIGHT` to whatever vertex layout the pipeline
+/// already has, so the prepass keeps its own locations.
+pub fn add_chunk_attributes(
+    descriptor: &mut RenderPipelineDescriptor,
+    layout: &MeshVertexBufferLayout,
+) -> Result<(), SpecializedMeshPipelineError> {
+    let chunk = layout.get_layout(&[
+        ATTRIBUTE_TILE.at_shader_location(TILE_LOCATION),
+        ATTRIBUTE_LIGHT.at_shader_location(LIGHT_LOCATION),
+    ])?;
+    descriptor.vertex.buffers[0]
+        .attributes
+        .extend(chunk.attributes);
+    Ok(())
+}
+
+/// Opaque and cutout blocks, lit the same way StandardMaterial does it but with UVs
+/// wrapped into each face's tile
+#[derive(AsBindGroup, TypeUuid, Clone, Debug)]
+#[uuid = "5b0a3c1e-7f2d-4c8e-9a61-2d4f8e3b7c05"]
+pub struct ChunkMaterial {
+    #[uniform(0)]
+    pub reflectance: f32,
+    #[uniform(0)]
+    pub perceptual_roughness: f32,
+    #[uniform(0)]
+    pub alpha_cutoff: f32, // Cutout texels below this alpha are left out
+    #[uniform(0)]
+    pub flags: u32, // StandardMaterialFlags, only the alpha mode bits are used
+    #[uniform(0)]
+    pub sky_dimming: f32, // Levels sky light is taken down by, 0 at noon
+    #[uniform(0)]
+    pub light_falloff: f32, // Brightness multiplier for each level below MAX_LIGHT
+    #[texture(1)]
+    #[sampler(2)]
+    pub texture: Handle<Image>,
+    pub alpha_mode: AlphaMode,
+}
+
+impl ChunkMaterial {
+    pub fn new(texture: Handle<Image>, alpha_mode: AlphaMode) -> Self {
+        let (flags, alpha_cutoff) = match alpha_mode {
+            AlphaMode::Mask(cutoff) => (StandardMaterialFlags::ALPHA_MODE_MASK, cutoff),
+            _ => (StandardMaterialFlags::ALPHA_MODE_OPAQUE, 0.5),
+        };
+        Self {
+            reflectance: 0.1,
+            perceptual_roughness: 0.1,
+            alpha_cutoff,
+            flags: flags.bits(),
+            sky_dimming: 0.0,
+            light_falloff: LIGHT_FALLOFF,
+            texture,
+            alpha_mode,
+        }
+    }
+}
+
+impl Material for ChunkMaterial {
+    fn vertex_shader() -> ShaderRef {
+        CHUNK_SHADER.into()
+    }
+
+    fn fragment_shader() -> ShaderRef {
+        CHUNK_SHADER.into()
+    }
+
+    // Shadows of cutout blocks need the same wrapped UVs to leave the gaps out.
+    fn prepass_vertex_shader() -> ShaderRef {
+        CHUNK_PREPASS_SHADER.into()
+    }
+
+    fn prepass_fragment_shader() -> ShaderRef {
+        CHUNK_PREPASS_SHADER.into()
+    }
+
+    fn alpha_mode(&self) -> AlphaMode {
+        self.alpha_mode
+    }
+
+    fn specialize(
+        _pipeline: &MaterialPipeline<Self>,
+        descriptor: &mut RenderPipelineDescriptor,
+        layout: &MeshVertexBufferLayout,
+        _key: MaterialPipelineKey<Self>,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        add_chunk_attributes(descriptor, layout)
+    }
+}
+
+/// The material chunks' opaque and cutout meshes are drawn with
+pub struct ChunkMaterialPlugin;
+impl Plugin for ChunkMaterialPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugin(MaterialPlugin::<ChunkMaterial>::default());
+    }
+}
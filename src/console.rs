@@ -0,0 +1,297 @@
+use bevy::prelude::*;
+use bevy::transform::TransformSystem;
+
+use crate::cam::Player;
+use crate::controls::{Action, Actions, TextFocus};
+use crate::edit::{FlattenArea, MAX_FLATTEN_RADIUS};
+use crate::menu::GameState;
+use crate::mode::GameMode;
+use crate::save::SaveWorld;
+use crate::weather::{Weather, WeatherKind};
+use crate::world::{RegenerateWorld, WorldConfig};
+
+pub const FONT: &str = "../resources/fonts/DejaVuSansMono.ttf";
+const FONT_SIZE: f32 = 18.0;
+const OUTPUT_LINES: usize = 8; // Lines of output kept on screen
+const HELP: &str =
+    "Commands: /tp <x> <y> <z>, /pos, /seed <seed>, /weather <clear|rain>, /flatten [radius], /save, /help";
+
+/// What's typed into the console and what it printed. Open while typing, commands
+/// don't close it, Escape or an empty Enter does.
+#[derive(Resource, Default)]
+pub struct Console {
+    open: bool,
+    input: String,
+    output: Vec<String>,
+}
+
+impl Console {
+    fn print(&mut self, line: impl Into<String>) {
+        let line = line.into();
+        info!("{}", line);
+        self.output.push(line);
+        if self.output.len() > OUTPUT_LINES {
+            self.output.remove(0);
+        }
+    }
+}
+
+/// A parsed console line
+#[derive(Debug, PartialEq)]
+enum Command {
+    Teleport(Vec3),
+    Position,
+    Seed(u32),
+    Weather(WeatherKind),
+    Flatten(Option<i32>),
+    Save,
+    Help,
+}
+
+/// Parses a command, with or without its leading slash
+fn parse_command(line: &str) -> Result<Command, String> {
+    let mut words = line.trim().trim_start_matches('/').split_whitespace();
+    let name = words.next().unwrap_or_default();
+    let args: Vec<&str> = words.collect();
+
+    match (name, args.as_slice()) {
+        ("tp", [x, y, z]) => {
+            let coord = |value: &str| {
+                value
+                    .parse::<f32>()
+                    .map_err(|_| format!("Not a number: {}", value))
+            };
+            Ok(Command::Teleport(Vec3::new(
+                coord(x)?,
+                coord(y)?,
+                coord(z)?,
+            )))
+        }
+        ("tp", _) => Err("Usage: /tp <x> <y> <z>".to_string()),
+        ("pos", []) => Ok(Command::Position),
+        // Same rules as --seed, words are hashed.
+        ("seed", [_, ..]) => Ok(Command::Seed(
+            WorldConfig::from_seed_string(&args.join(" ")).seed,
+        )),
+        ("seed", []) => Err("Usage: /seed <seed>".to_string()),
+        ("weather", [kind]) => WeatherKind::from_name(kind)
+            .map(Command::Weather)
+            .ok_or_else(|| "Usage: /weather <clear|rain>".to_string()),
+        ("weather", _) => Err("Usage: /weather <clear|rain>".to_string()),
+        ("flatten", []) => Ok(Command::Flatten(None)),
+        ("flatten", [radius]) => radius
+            .parse()
+            .ok()
+            .filter(|radius| (1..=MAX_FLATTEN_RADIUS).contains(radius))
+            .map(|radius| Command::Flatten(Some(radius)))
+            .ok_or_else(|| format!("Radius must be 1 to {}", MAX_FLATTEN_RADIUS)),
+        ("flatten", _) => Err("Usage: /flatten [radius]".to_string()),
+        ("save", []) => Ok(Command::Save),
+        ("help", _) => Ok(Command::Help),
+        _ => Err(format!("Unknown command: {}. Try /help", name)),
+    }
+}
+
+#[derive(Component)]
+struct ConsoleRoot;
+
+#[derive(Component)]
+struct ConsoleText;
+
+/// A dark box in the bottom left, above the hotbar, hidden until the console opens
+fn setup_console(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    position: UiRect {
+                        left: Val::Px(12.0),
+                        bottom: Val::Px(72.0),
+                        ..default()
+                    },
+                    max_size: Size::width(Val::Percent(60.0)),
+                    padding: UiRect::all(Val::Px(6.0)),
+                    ..default()
+                },
+                background_color: Color::rgba(0.0, 0.0, 0.0, 0.6).into(),
+                visibility: Visibility::Hidden,
+                ..default()
+            },
+            ConsoleRoot,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font: asset_server.load(FONT),
+                        font_size: FONT_SIZE,
+                        color: Color::WHITE,
+                    },
+                ),
+                ConsoleText,
+            ));
+        });
+}
+
+/// Opens the console with `/`, then types into it until Escape. Runs after Update, so
+/// the key that closes it doesn't reach anything else in the same frame.
+#[allow(clippy::too_many_arguments)]
+fn console_input(
+    actions: Actions,
+    keys: Res<Input<KeyCode>>,
+    mut chars: EventReader<ReceivedCharacter>,
+    mut console: ResMut<Console>,
+    mut focus: ResMut<TextFocus>,
+    mut players: Query<(&mut Transform, &mut Player)>,
+    mut regenerate: EventWriter<RegenerateWorld>,
+    mut weather: ResMut<Weather>,
+    mut save: EventWriter<SaveWorld>,
+    mut flatten: EventWriter<FlattenArea>,
+    mode: Res<GameMode>,
+) {
+    if !console.open {
+        // The slash that opened it is typed already.
+        chars.clear();
+        if actions.just_pressed(Action::OpenConsole) {
+            console.open = true;
+            console.input = "/".to_string();
+            focus.0 = true;
+        }
+        return;
+    }
+
+    for event in chars.iter() {
+        match event.char {
+            // Backspace, or delete on macOS
+            '\u{8}' | '\u{7f}' => {
+                console.input.pop();
+            }
+            c if !c.is_control() => console.input.push(c),
+            _ => (),
+        }
+    }
+
+    let submitted = keys.just_pressed(KeyCode::Return);
+    let line = console.input.trim().to_string();
+    if keys.just_pressed(KeyCode::Escape) || (submitted && line.trim_matches('/').is_empty()) {
+        console.open = false;
+        console.input.clear();
+        focus.0 = false;
+        return;
+    }
+    if !submitted {
+        return;
+    }
+
+    console.input = "/".to_string();
+    console.print(format!("> {}", line));
+    match parse_command(&line) {
+        Ok(Command::Teleport(pos)) => {
+            for (mut transform, mut player) in players.iter_mut() {
+                transform.translation = pos;
+                player.velocity = Vec3::ZERO;
+            }
+            console.print(format!(
+                "Teleported to {:.1} {:.1} {:.1}",
+                pos.x, pos.y, pos.z
+            ));
+        }
+        Ok(Command::Position) => {
+            for (transform, _) in players.iter() {
+                let pos = transform.translation;
+                console.print(format!("Position: {:.1} {:.1} {:.1}", pos.x, pos.y, pos.z));
+            }
+        }
+        Ok(Command::Seed(seed)) => {
+            regenerate.send(RegenerateWorld { seed });
+            console.print(format!("Regenerating with seed {}", seed));
+        }
+        Ok(Command::Weather(kind)) => {
+            weather.set(kind);
+            console.print(format!("Weather set to {:?}", kind));
+        }
+        Ok(Command::Flatten(_)) if *mode != GameMode::Creative => {
+            console.print("Flattening is creative only");
+        }
+        Ok(Command::Flatten(radius)) => {
+            flatten.send(FlattenArea { radius });
+            console.print("Flattening around the block you're looking at");
+        }
+        Ok(Command::Save) => {
+            save.send(SaveWorld);
+            console.print("Saving world");
+        }
+        Ok(Command::Help) => console.print(HELP),
+        Err(e) => console.print(e),
+    }
+}
+
+/// Redraws the console when it changes
+fn show_console(
+    console: Res<Console>,
+    mut roots: Query<&mut Visibility, With<ConsoleRoot>>,
+    mut texts: Query<&mut Text, With<ConsoleText>>,
+) {
+    for mut visibility in roots.iter_mut() {
+        *visibility = if console.open {
+            Visibility::Visible
+        } else {
+            Visibility::Hidden
+        };
+    }
+
+    for mut text in texts.iter_mut() {
+        let mut lines = console.output.clone();
+        lines.push(format!("{}_", console.input));
+        text.sections[0].value = lines.join("\n");
+    }
+}
+
+/// A command console for moving around and trying seeds
+pub struct ConsolePlugin;
+impl Plugin for ConsolePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Console>()
+            .add_system(setup_console.on_startup())
+            .add_system(
+                console_input
+                    .in_base_set(CoreSet::PostUpdate)
+                    .run_if(in_state(GameState::Playing))
+                    .before(TransformSystem::TransformPropagate),
+            )
+            .add_system(
+                show_console
+                    .in_base_set(CoreSet::PostUpdate)
+                    .after(console_input)
+                    .run_if(resource_changed::<Console>()),
+            );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_commands() {
+        assert_eq!(
+            parse_command("/tp 1 -2.5 3"),
+            Ok(Command::Teleport(Vec3::new(1.0, -2.5, 3.0)))
+        );
+        assert_eq!(parse_command("pos"), Ok(Command::Position));
+        assert_eq!(parse_command("/seed 42"), Ok(Command::Seed(42)));
+        assert_eq!(
+            parse_command("/weather rain"),
+            Ok(Command::Weather(WeatherKind::Rain))
+        );
+        assert_eq!(parse_command("/flatten"), Ok(Command::Flatten(None)));
+        assert_eq!(parse_command("/flatten 8"), Ok(Command::Flatten(Some(8))));
+        assert!(parse_command("/flatten 0").is_err());
+        assert!(parse_command("/flatten 1000").is_err());
+        assert!(parse_command("/tp 1 2").is_err());
+        assert!(parse_command("/tp a b c").is_err());
+        assert!(parse_command("/fly").is_err());
+    }
+}
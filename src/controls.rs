@@ -0,0 +1,179 @@
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::Path;
+
+const CONTROLS_FILE: &str = "resources/controls.ron";
+
+/// Something the player can do with a key or mouse button
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Deserialize)]
+pub enum Action {
+    MoveForward,
+    MoveBack,
+    MoveLeft,
+    MoveRight,
+    Jump, // Also flies up
+    Descend,
+    Sprint,
+    AdjustFlySpeed, // Held while scrolling
+    ToggleWalking,
+    ToggleGameMode,
+    Pause,
+    BreakBlock,
+    PlaceBlock,
+    Flatten,
+    PauseTime,
+    TimeSpeed,
+    Save,
+    Screenshot,
+    OpenConsole,
+    HighlightChunk,
+    GreedyMeshing,
+    ExportChunk,
+    ChunkBorders,
+    NoisePreview,
+    RegenerateWorld,
+    ToggleFullscreen,
+    ToggleView,
+}
+
+impl Action {
+    fn default_binding(self) -> Binding {
+        use Binding::*;
+        match self {
+            Action::MoveForward => Key(KeyCode::W),
+            Action::MoveBack => Key(KeyCode::S),
+            Action::MoveLeft => Key(KeyCode::A),
+            Action::MoveRight => Key(KeyCode::D),
+            Action::Jump => Key(KeyCode::Space),
+            Action::Descend => Key(KeyCode::LShift),
+            Action::Sprint => Key(KeyCode::LControl),
+            Action::AdjustFlySpeed => Key(KeyCode::LAlt),
+            Action::ToggleWalking => Key(KeyCode::G),
+            Action::ToggleGameMode => Key(KeyCode::M),
+            Action::Pause => Key(KeyCode::Escape),
+            Action::BreakBlock => Mouse(MouseButton::Left),
+            Action::PlaceBlock => Mouse(MouseButton::Right),
+            Action::Flatten => Key(KeyCode::F),
+            Action::PauseTime => Key(KeyCode::T),
+            Action::TimeSpeed => Key(KeyCode::Y),
+            Action::Save => Key(KeyCode::F5),
+            Action::Screenshot => Key(KeyCode::F2),
+            Action::OpenConsole => Key(KeyCode::Slash),
+            Action::HighlightChunk => Key(KeyCode::F3),
+            Action::GreedyMeshing => Key(KeyCode::F4),
+            Action::ExportChunk => Key(KeyCode::F6),
+            Action::ChunkBorders => Key(KeyCode::F7),
+            Action::NoisePreview => Key(KeyCode::F8),
+            Action::RegenerateWorld => Key(KeyCode::F9),
+            Action::ToggleFullscreen => Key(KeyCode::F11),
+            Action::ToggleView => Key(KeyCode::V),
+        }
+    }
+}
+
+/// A key or mouse button an action is bound to
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Deserialize)]
+pub enum Binding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+}
+
+/// Which key or button does what. Loaded from `CONTROLS_FILE`, anything it leaves out
+/// keeps its default binding.
+#[derive(Resource, Default)]
+pub struct InputConfig {
+    bindings: HashMap<Action, Binding>,
+}
+
+impl InputConfig {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let file = BufReader::new(File::open(path)?);
+        let bindings = ron::de::from_reader(file)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        Ok(Self { bindings })
+    }
+
+    pub fn binding(&self, action: Action) -> Binding {
+        self.bindings
+            .get(&action)
+            .copied()
+            .unwrap_or_else(|| action.default_binding())
+    }
+}
+
+/// The controls file if there is one, or the default bindings
+fn load_controls() -> InputConfig {
+    match InputConfig::load(Path::new(CONTROLS_FILE)) {
+        Ok(config) => config,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => InputConfig::default(),
+        Err(e) => {
+            error!(
+                "Couldn't load {}, using the default controls: {}",
+                CONTROLS_FILE, e
+            );
+            InputConfig::default()
+        }
+    }
+}
+
+/// Set while something like the console is taking typed text, so keys don't also
+/// trigger their actions
+#[derive(Resource, Default)]
+pub struct TextFocus(pub bool);
+
+/// Reads actions through the bindings, for systems that would otherwise look at keys
+#[derive(SystemParam)]
+pub struct Actions<'w> {
+    config: Res<'w, InputConfig>,
+    focus: Res<'w, TextFocus>,
+    keys: Res<'w, Input<KeyCode>>,
+    mouse: Res<'w, Input<MouseButton>>,
+}
+
+impl Actions<'_> {
+    pub fn pressed(&self, action: Action) -> bool {
+        if self.focus.0 {
+            return false;
+        }
+        match self.config.binding(action) {
+            Binding::Key(key) => self.keys.pressed(key),
+            Binding::Mouse(button) => self.mouse.pressed(button),
+        }
+    }
+
+    pub fn just_pressed(&self, action: Action) -> bool {
+        if self.focus.0 {
+            return false;
+        }
+        match self.config.binding(action) {
+            Binding::Key(key) => self.keys.just_pressed(key),
+            Binding::Mouse(button) => self.mouse.just_pressed(button),
+        }
+    }
+}
+
+/// Remappable key and mouse bindings
+pub struct ControlsPlugin;
+impl Plugin for ControlsPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(load_controls())
+            .init_resource::<TextFocus>();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn controls_file_matches_the_defaults() {
+        let config = InputConfig::load(Path::new(CONTROLS_FILE)).unwrap();
+        for (action, binding) in config.bindings.iter() {
+            assert_eq!(*binding, action.default_binding(), "{:?}", action);
+        }
+    }
+}
@@ -0,0 +1,346 @@
+use bevy::prelude::*;
+use std::path::PathBuf;
+
+use crate::cam::Player;
+use crate::controls::{Action, Actions};
+use crate::edit::wire_box;
+use crate::world::*;
+
+const BORDER_INSET: f32 = 0.05; // Keeps neighbouring borders from drawing over each other
+const NOISE_PREVIEW_SIZE: f32 = 256.0; // In pixels
+
+/// Which debug visualizers are turned on. F4 also toggles greedy meshing, see [`Meshing`],
+/// and F9 regenerates the world with a random seed.
+#[derive(Resource, Default)]
+pub struct DebugSettings {
+    pub highlight_chunk: bool, // F3
+    pub chunk_borders: bool,   // F7
+    pub noise_preview: bool,   // F8
+}
+
+/// Marks the box drawn around the chunk the player is in
+#[derive(Component)]
+struct ChunkHighlight;
+
+/// The wireframe around a loaded chunk, at its position in chunks
+#[derive(Component)]
+struct ChunkBorder(IVec2);
+
+/// Shared by every chunk border, with a colour for each place a chunk can load from
+#[derive(Resource)]
+struct ChunkBorderAssets {
+    mesh: Handle<Mesh>,
+    generated: Handle<StandardMaterial>,
+    cache: Handle<StandardMaterial>,
+    stored: Handle<StandardMaterial>,
+}
+
+impl ChunkBorderAssets {
+    fn material(&self, source: ChunkSource) -> Handle<StandardMaterial> {
+        match source {
+            ChunkSource::Generated => self.generated.clone(),
+            ChunkSource::Cache => self.cache.clone(),
+            ChunkSource::Stored => self.stored.clone(),
+        }
+    }
+}
+
+fn setup_chunk_highlight(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let (size, height) = (CHUNK_SIZE as f32, CHUNK_HEIGHT as f32);
+    commands.spawn((
+        PbrBundle {
+            mesh: meshes.add(Mesh::from(shape::Box::new(size, height, size))),
+            material: materials.add(StandardMaterial {
+                base_color: Color::rgba(1.0, 0.2, 0.2, 0.15),
+                alpha_mode: AlphaMode::Blend,
+                unlit: true,
+                // Visible from the inside too.
+                cull_mode: None,
+                double_sided: true,
+                ..default()
+            }),
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+        ChunkHighlight,
+    ));
+}
+
+/// Marks the corner image showing the height map
+#[derive(Component)]
+struct NoisePreview;
+
+fn setup_noise_preview(mut commands: Commands) {
+    commands.spawn((
+        ImageBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    top: Val::Px(12.0),
+                    right: Val::Px(12.0),
+                    ..default()
+                },
+                size: Size::new(Val::Px(NOISE_PREVIEW_SIZE), Val::Px(NOISE_PREVIEW_SIZE)),
+                ..default()
+            },
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+        NoisePreview,
+    ));
+}
+
+fn setup_chunk_borders(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let mut line = |color: Color| {
+        materials.add(StandardMaterial {
+            base_color: color,
+            unlit: true,
+            ..default()
+        })
+    };
+
+    let half =
+        Vec3::new(CHUNK_SIZE as f32, CHUNK_HEIGHT as f32, CHUNK_SIZE as f32) / 2.0 - BORDER_INSET;
+    commands.insert_resource(ChunkBorderAssets {
+        mesh: meshes.add(wire_box(half)),
+        generated: line(Color::GREEN),
+        cache: line(Color::YELLOW),
+        stored: line(Color::CYAN),
+    });
+}
+
+fn toggle_debug(
+    actions: Actions,
+    mut settings: ResMut<DebugSettings>,
+    mut meshing: ResMut<Meshing>,
+    mut map: ResMut<Map>,
+    mut regenerate: EventWriter<RegenerateWorld>,
+) {
+    if actions.just_pressed(Action::HighlightChunk) {
+        settings.highlight_chunk = !settings.highlight_chunk;
+    }
+
+    if actions.just_pressed(Action::ChunkBorders) {
+        settings.chunk_borders = !settings.chunk_borders;
+    }
+
+    if actions.just_pressed(Action::NoisePreview) {
+        settings.noise_preview = !settings.noise_preview;
+    }
+
+    // Try out seeds without restarting.
+    if actions.just_pressed(Action::RegenerateWorld) {
+        regenerate.send(RegenerateWorld {
+            seed: rand::random(),
+        });
+    }
+
+    // Switch between greedy and per-block meshing to compare them.
+    if actions.just_pressed(Action::GreedyMeshing) {
+        meshing.greedy = !meshing.greedy;
+        map.remesh_all();
+        info!("Greedy meshing: {}", meshing.greedy);
+    }
+}
+
+/// Moves the highlight to the chunk under the player, once that chunk has been spawned
+#[allow(clippy::type_complexity)]
+fn highlight_chunk(
+    settings: Res<DebugSettings>,
+    map: Res<Map>,
+    player: Query<&Transform, (With<Player>, Without<ChunkHighlight>)>,
+    chunks: Query<&Transform, (With<Chunk>, Without<ChunkHighlight>)>,
+    mut highlight: Query<(&mut Transform, &mut Visibility), With<ChunkHighlight>>,
+    mut last_chunk: Local<Option<IVec2>>,
+) {
+    let (mut transform, mut visibility) = highlight.single_mut();
+
+    if !settings.highlight_chunk {
+        *visibility = Visibility::Hidden;
+        *last_chunk = None;
+        return;
+    }
+
+    let player = player.single();
+    let block = player.translation.floor().as_ivec3();
+    let chunk = Map::world_to_chunk(block);
+
+    if *last_chunk != Some(chunk) {
+        info!(
+            "Entered chunk {} ({:?}, ground at y {})",
+            chunk,
+            map.biome(block),
+            map.surface_height(block.x, block.z)
+        );
+        *last_chunk = Some(chunk);
+    }
+
+    // Where the chunk is actually drawn, so a chunk that hasn't spawned shows nothing.
+    let spawned = map
+        .chunk_entity(chunk)
+        .and_then(|entity| chunks.get(entity).ok());
+    let Some(spawned) = spawned else {
+        *visibility = Visibility::Hidden;
+        return;
+    };
+    transform.translation = spawned.translation + CHUNK_EXTENT.as_vec3() / 2.0;
+    *visibility = Visibility::Visible;
+}
+
+/// Writes the chunk under the player to an OBJ in the working directory with F6
+fn export_chunk(
+    actions: Actions,
+    map: Res<Map>,
+    atlas: Res<Assets<TextureAtlas>>,
+    meshing: Res<Meshing>,
+    player: Query<&Transform, With<Player>>,
+) {
+    if !actions.just_pressed(Action::ExportChunk) {
+        return;
+    }
+
+    let Some(atlas) = atlas.get(&map.texture_atlas) else {
+        return;
+    };
+    let player = player.single();
+    let chunk = Map::world_to_chunk(player.translation.floor().as_ivec3());
+    let path = PathBuf::from(format!("chunk_{}_{}.obj", chunk.x, chunk.y));
+
+    match map.export_obj(chunk, &path, atlas, meshing.greedy) {
+        Ok(()) => info!("Exported chunk {} to {}", chunk, path.display()),
+        Err(e) => error!("Failed to export chunk {}: {}", chunk, e),
+    }
+}
+
+/// Puts a border around chunks as they load, coloured by where they came from, and
+/// takes it away again when they unload
+fn update_chunk_borders(
+    mut commands: Commands,
+    settings: Res<DebugSettings>,
+    assets: Res<ChunkBorderAssets>,
+    mut loaded: EventReader<ChunkLoaded>,
+    mut unloaded: EventReader<ChunkUnloaded>,
+    borders: Query<(Entity, &ChunkBorder)>,
+) {
+    // Reloading a chunk replaces its border too.
+    let loaded: Vec<&ChunkLoaded> = loaded.iter().collect();
+    let gone: Vec<IVec2> = unloaded
+        .iter()
+        .map(|event| event.0)
+        .chain(loaded.iter().map(|event| event.position))
+        .collect();
+    for (entity, border) in borders.iter() {
+        if gone.contains(&border.0) {
+            commands.entity(entity).despawn();
+        }
+    }
+
+    for event in loaded {
+        let half = CHUNK_SIZE as f32 / 2.0;
+        let origin = event.position.as_vec2() * CHUNK_SIZE as f32;
+        let visibility = if settings.chunk_borders {
+            Visibility::Visible
+        } else {
+            Visibility::Hidden
+        };
+        commands.spawn((
+            PbrBundle {
+                mesh: assets.mesh.clone(),
+                material: assets.material(event.source),
+                transform: Transform::from_xyz(
+                    origin.x + half,
+                    CHUNK_HEIGHT as f32 / 2.0,
+                    origin.y + half,
+                ),
+                visibility,
+                ..default()
+            },
+            ChunkBorder(event.position),
+        ));
+    }
+}
+
+fn show_chunk_borders(
+    settings: Res<DebugSettings>,
+    mut borders: Query<&mut Visibility, With<ChunkBorder>>,
+) {
+    for mut visibility in borders.iter_mut() {
+        *visibility = if settings.chunk_borders {
+            Visibility::Visible
+        } else {
+            Visibility::Hidden
+        };
+    }
+}
+
+/// Shows or hides the height map preview. It's rebuilt every time it's shown, so it
+/// matches the map if that's been regenerated.
+fn show_noise_preview(
+    settings: Res<DebugSettings>,
+    map: Res<Map>,
+    mut images: ResMut<Assets<Image>>,
+    mut preview: Query<(&mut UiImage, &mut Visibility), With<NoisePreview>>,
+    mut shown: Local<bool>,
+) {
+    let (mut image, mut visibility) = preview.single_mut();
+    if settings.noise_preview == *shown {
+        return;
+    }
+    *shown = settings.noise_preview;
+
+    if settings.noise_preview {
+        image.texture = images.add(map.height_map_image());
+        *visibility = Visibility::Visible;
+    } else {
+        *visibility = Visibility::Hidden;
+    }
+}
+
+/// Logs how many chunks are drawn after culling, at debug level
+fn log_culling(culling: Res<ChunkCulling>) {
+    debug!("Drawing {} of {} chunks", culling.visible, culling.total);
+}
+
+/// Logs chunks coming and going, at debug level
+fn log_chunk_events(
+    mut loaded: EventReader<ChunkLoaded>,
+    mut unloaded: EventReader<ChunkUnloaded>,
+) {
+    for event in loaded.iter() {
+        debug!("Loaded chunk {} ({:?})", event.position, event.source);
+    }
+    for event in unloaded.iter() {
+        debug!("Unloaded chunk {}", event.0);
+    }
+}
+
+/// Debug visualizers
+pub struct DebugPlugin;
+impl Plugin for DebugPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<DebugSettings>()
+            .add_system(setup_chunk_highlight.on_startup())
+            .add_system(setup_chunk_borders.on_startup())
+            .add_system(toggle_debug)
+            .add_system(highlight_chunk.after(toggle_debug))
+            .add_system(setup_noise_preview.on_startup())
+            .add_system(show_noise_preview.after(toggle_debug))
+            .add_system(update_chunk_borders)
+            .add_system(
+                show_chunk_borders
+                    .after(toggle_debug)
+                    .run_if(resource_changed::<DebugSettings>()),
+            )
+            .add_system(export_chunk)
+            .add_system(log_chunk_events)
+            .add_system(log_culling.run_if(resource_changed::<ChunkCulling>()));
+    }
+}
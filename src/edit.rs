@@ -0,0 +1,425 @@
+use bevy::prelude::*;
+use bevy::render::render_resource::PrimitiveTopology;
+use bevy::window::{CursorGrabMode, PrimaryWindow};
+
+use crate::cam::{player_aabb, Player};
+use crate::controls::{Action, Actions};
+use crate::gamepad::GamepadInput;
+use crate::menu::GameState;
+use crate::mode::GameMode;
+use crate::world::*;
+
+const REACH: f32 = 8.0; // In blocks
+const OUTLINE_MARGIN: f32 = 0.005; // Keeps the outline from z-fighting the block faces
+const SECONDS_PER_HARDNESS: f32 = 0.5; // How long breaking takes in survival, per hardness
+pub const MAX_FLATTEN_RADIUS: i32 = 16; // In blocks, every column in it is relit
+
+/// The block type used when building
+#[derive(Resource)]
+pub struct SelectedBlock(pub BlockType);
+
+impl Default for SelectedBlock {
+    fn default() -> Self {
+        Self(BlockType::Stone)
+    }
+}
+
+/// Radius of the flatten tool, in blocks
+#[derive(Resource)]
+pub struct FlattenSettings {
+    pub radius: i32,
+}
+
+impl Default for FlattenSettings {
+    fn default() -> Self {
+        Self { radius: 4 }
+    }
+}
+
+/// Asks for the area around the looked-at block to be flattened, like pressing F. A
+/// radius is kept for the tool from then on.
+pub struct FlattenArea {
+    pub radius: Option<i32>,
+}
+
+/// Sent when the player breaks a block
+pub struct BlockBroken {
+    pub pos: IVec3,
+    pub btype: BlockType,
+}
+
+/// Sent when the player places a block
+pub struct BlockPlaced(pub BlockType);
+
+/// The block being broken in survival, and how long it has been held for
+#[derive(Resource, Default)]
+struct BreakProgress {
+    block: Option<IVec3>,
+    time: f32,
+}
+
+/// Marks the box drawn around the looked-at block
+#[derive(Component)]
+struct BlockOutline;
+
+/// The 12 edges of a box centered on the origin, as lines
+pub fn wire_box(half_size: Vec3) -> Mesh {
+    let h = half_size;
+    let corners: Vec<[f32; 3]> = (0..8)
+        .map(|i| {
+            [
+                if i & 1 == 0 { -h.x } else { h.x },
+                if i & 2 == 0 { -h.y } else { h.y },
+                if i & 4 == 0 { -h.z } else { h.z },
+            ]
+        })
+        .collect();
+
+    // Corners one bit apart share an edge.
+    let mut positions = Vec::new();
+    for i in 0..8usize {
+        for bit in [1, 2, 4] {
+            if i & bit == 0 {
+                positions.push(corners[i]);
+                positions.push(corners[i | bit]);
+            }
+        }
+    }
+
+    let mut mesh = Mesh::new(PrimitiveTopology::LineList);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, vec![[0., 1., 0.]; positions.len()]);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh
+}
+
+fn setup_block_outline(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    commands.spawn((
+        PbrBundle {
+            mesh: meshes.add(wire_box(Vec3::splat(0.5 + OUTLINE_MARGIN))),
+            material: materials.add(StandardMaterial {
+                base_color: Color::BLACK,
+                unlit: true,
+                ..default()
+            }),
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+        BlockOutline,
+    ));
+}
+
+/// The block a ray hit, and the face it went in through
+#[derive(Clone, Copy, Debug)]
+pub struct RayHit {
+    pub block: IVec3,
+    pub normal: IVec3, // Zero if the ray started inside the block
+    pub distance: f32, // Along the ray to where it went in
+}
+
+/// Walks the block grid along the ray (DDA) and returns the first block within `reach`
+/// that isn't air or water.
+pub fn raycast(map: &Map, origin: Vec3, direction: Vec3, reach: f32) -> Option<RayHit> {
+    let direction = direction.normalize_or_zero();
+    if direction == Vec3::ZERO {
+        return None;
+    }
+
+    let mut block = origin.floor().as_ivec3();
+    let mut step = IVec3::ZERO;
+    let mut t_max = Vec3::splat(f32::INFINITY);
+    let mut t_delta = Vec3::splat(f32::INFINITY);
+
+    for axis in 0..3 {
+        if direction[axis] > 0.0 {
+            step[axis] = 1;
+            t_delta[axis] = 1.0 / direction[axis];
+            t_max[axis] = (block[axis] as f32 + 1.0 - origin[axis]) * t_delta[axis];
+        } else if direction[axis] < 0.0 {
+            step[axis] = -1;
+            t_delta[axis] = -1.0 / direction[axis];
+            t_max[axis] = (origin[axis] - block[axis] as f32) * t_delta[axis];
+        }
+    }
+
+    let mut distance = 0.0;
+    let mut normal = IVec3::ZERO;
+    while distance <= reach {
+        if map.is_targetable(block) {
+            return Some(RayHit {
+                block,
+                normal,
+                distance,
+            });
+        }
+
+        // Step along whichever axis reaches the next block boundary first.
+        let axis = if t_max.x < t_max.y && t_max.x < t_max.z {
+            0
+        } else if t_max.y < t_max.z {
+            1
+        } else {
+            2
+        };
+
+        distance = t_max[axis];
+        block[axis] += step[axis];
+        normal = IVec3::ZERO;
+        normal[axis] = -step[axis];
+        t_max[axis] += t_delta[axis];
+    }
+
+    None
+}
+
+/// Whether the block at `block` would overlap a player whose eyes are at `eye`.
+fn intersects_player(block: IVec3, eye: Vec3) -> bool {
+    let (player_min, player_max) = player_aabb(eye);
+    let block_min = block.as_vec3();
+    let block_max = block_min + Vec3::ONE;
+
+    player_min.cmplt(block_max).all() && player_max.cmpgt(block_min).all()
+}
+
+/// Removes the looked-at block, unless it's unbreakable like bedrock. Creative breaks
+/// it on the click, survival once the button has been held on it for as long as its
+/// hardness asks.
+#[allow(clippy::too_many_arguments)]
+fn break_block(
+    actions: Actions,
+    gamepad: Res<GamepadInput>,
+    mode: Res<GameMode>,
+    time: Res<Time>,
+    primary_window: Query<&Window, With<PrimaryWindow>>,
+    player: Query<&Transform, With<Player>>,
+    mut progress: ResMut<BreakProgress>,
+    mut map: ResMut<Map>,
+    mut broken: EventWriter<BlockBroken>,
+) {
+    let (clicked, held) = match *mode {
+        GameMode::Creative => (
+            actions.just_pressed(Action::BreakBlock) || gamepad.break_block,
+            false,
+        ),
+        GameMode::Survival => (
+            false,
+            actions.pressed(Action::BreakBlock) || gamepad.breaking,
+        ),
+    };
+    if !clicked && !held {
+        *progress = BreakProgress::default();
+        return;
+    }
+
+    if let Ok(window) = primary_window.get_single() {
+        if window.cursor.grab_mode == CursorGrabMode::None {
+            return;
+        }
+    } else {
+        warn!("Primary window not found for `break_block`!");
+        return;
+    }
+
+    let player = player.single();
+    let Some(hit) = raycast(&map, player.translation, player.forward(), REACH) else {
+        *progress = BreakProgress::default();
+        return;
+    };
+    if !map.can_break(hit.block) {
+        *progress = BreakProgress::default();
+        return;
+    }
+
+    if held {
+        // Looking at another block starts over.
+        if progress.block != Some(hit.block) {
+            *progress = BreakProgress {
+                block: Some(hit.block),
+                time: 0.0,
+            };
+        }
+        progress.time += time.delta_seconds();
+        if progress.time < map.hardness(hit.block) * SECONDS_PER_HARDNESS {
+            return;
+        }
+        *progress = BreakProgress::default();
+    }
+
+    if let Some(btype) = map.get_block(hit.block) {
+        map.set_block(hit.block, BlockType::Air);
+        broken.send(BlockBroken {
+            pos: hit.block,
+            btype,
+        });
+    }
+}
+
+/// Places the selected block against the face of the looked-at block
+fn place_block(
+    actions: Actions,
+    gamepad: Res<GamepadInput>,
+    primary_window: Query<&Window, With<PrimaryWindow>>,
+    selected: Res<SelectedBlock>,
+    player: Query<&Transform, With<Player>>,
+    mut map: ResMut<Map>,
+    mut placed: EventWriter<BlockPlaced>,
+) {
+    if !actions.just_pressed(Action::PlaceBlock) && !gamepad.place_block {
+        return;
+    }
+
+    if let Ok(window) = primary_window.get_single() {
+        if window.cursor.grab_mode == CursorGrabMode::None {
+            return;
+        }
+    } else {
+        warn!("Primary window not found for `place_block`!");
+        return;
+    }
+
+    let player = player.single();
+    if let Some(hit) = raycast(&map, player.translation, player.forward(), REACH) {
+        if hit.normal == IVec3::ZERO {
+            return;
+        }
+
+        let target = hit.block + hit.normal;
+        // Water can be built into, any other block is in the way.
+        if map.is_targetable(target) || intersects_player(target, player.translation) {
+            return;
+        }
+
+        // Marks the chunk dirty, remesh_chunks picks it up. Logs run away from the face
+        // they're placed against.
+        map.set_block_along(target, selected.0, BlockAxis::of_normal(hit.normal));
+        placed.send(BlockPlaced(selected.0));
+    }
+}
+
+/// Moves the outline to the block that breaking and placing would act on
+fn outline_block(
+    map: Res<Map>,
+    player: Query<&Transform, (With<Player>, Without<BlockOutline>)>,
+    mut outline: Query<(&mut Transform, &mut Visibility), With<BlockOutline>>,
+) {
+    let (mut transform, mut visibility) = outline.single_mut();
+    let player = player.single();
+
+    if let Some(hit) = raycast(&map, player.translation, player.forward(), REACH) {
+        transform.translation = hit.block.as_vec3() + Vec3::splat(0.5);
+        *visibility = Visibility::Visible;
+    } else {
+        *visibility = Visibility::Hidden;
+    }
+}
+
+/// Flattens the area around the looked-at block to its height with the selected block,
+/// on F or a `FlattenArea`. Creative only.
+#[allow(clippy::too_many_arguments)]
+fn flatten_area(
+    actions: Actions,
+    mode: Res<GameMode>,
+    mut requests: EventReader<FlattenArea>,
+    primary_window: Query<&Window, With<PrimaryWindow>>,
+    selected: Res<SelectedBlock>,
+    mut settings: ResMut<FlattenSettings>,
+    player: Query<&Transform, With<Player>>,
+    mut map: ResMut<Map>,
+) {
+    let mut requested = false;
+    for request in requests.iter() {
+        if let Some(radius) = request.radius {
+            settings.radius = radius;
+        }
+        requested = true;
+    }
+    if *mode != GameMode::Creative {
+        return;
+    }
+
+    // The key only counts while playing, not while the cursor is free for a menu.
+    if !requested {
+        if !actions.just_pressed(Action::Flatten) {
+            return;
+        }
+        if let Ok(window) = primary_window.get_single() {
+            if window.cursor.grab_mode == CursorGrabMode::None {
+                return;
+            }
+        } else {
+            warn!("Primary window not found for `flatten_area`!");
+            return;
+        }
+    }
+
+    let player = player.single();
+    if let Some(hit) = raycast(&map, player.translation, player.forward(), REACH) {
+        let radius = settings.radius;
+
+        for x in -radius..=radius {
+            for z in -radius..=radius {
+                if x * x + z * z > radius * radius {
+                    continue;
+                }
+
+                let column = hit.block + IVec3::new(x, 0, z);
+                if map.can_break(column) {
+                    map.set_block(column, selected.0);
+                }
+
+                // Clear everything above the platform, up to the column's top block. Air
+                // is skipped, since every block set relights the column below it.
+                let top = (column.y + 1..CHUNK_HEIGHT)
+                    .rev()
+                    .find(|y| map.get_block(IVec3::new(column.x, *y, column.z)).is_some());
+                let Some(top) = top else {
+                    continue;
+                };
+                for y in column.y + 1..=top {
+                    let pos = IVec3::new(column.x, y, column.z);
+                    if map.get_block(pos).is_some() && map.can_break(pos) {
+                        map.set_block(pos, BlockType::Air);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Block editing tools
+pub struct EditPlugin;
+impl Plugin for EditPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SelectedBlock>()
+            .init_resource::<FlattenSettings>()
+            .init_resource::<BreakProgress>()
+            .add_event::<BlockBroken>()
+            .add_event::<BlockPlaced>()
+            .add_event::<FlattenArea>()
+            .add_system(setup_block_outline.on_startup())
+            .add_system(
+                flatten_area
+                    .before(remesh_chunks)
+                    .run_if(in_state(GameState::Playing)),
+            )
+            .add_system(
+                break_block
+                    .before(remesh_chunks)
+                    .run_if(in_state(GameState::Playing)),
+            )
+            .add_system(
+                place_block
+                    .before(remesh_chunks)
+                    .run_if(in_state(GameState::Playing)),
+            )
+            .add_system(
+                outline_block
+                    .after(break_block)
+                    .after(place_block)
+                    .after(flatten_area),
+            );
+    }
+}
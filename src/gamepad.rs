@@ -0,0 +1,106 @@
+use bevy::input::InputSystem;
+use bevy::prelude::*;
+
+/// Stick dead zone and how fast the right stick turns the camera
+#[derive(Resource)]
+pub struct GamepadConfig {
+    pub dead_zone: f32,  // Fraction of the stick's travel ignored around the middle
+    pub look_speed: f32, // Radians per second with the stick pushed all the way
+}
+
+impl Default for GamepadConfig {
+    fn default() -> Self {
+        Self {
+            dead_zone: 0.15,
+            look_speed: 2.5,
+        }
+    }
+}
+
+/// What the connected gamepads ask for this frame, dead zone already applied. The
+/// keyboard and mouse systems add it to their own input, so both work at once.
+#[derive(Resource, Default)]
+pub struct GamepadInput {
+    pub movement: Vec2,    // Left stick, x to the right and y forward
+    pub look: Vec2,        // Right stick, in radians this frame
+    pub rise: f32,         // A flies up and jumps, B flies down
+    pub sprint: bool,      // Left stick pressed in
+    pub break_block: bool, // Right trigger, just pressed
+    pub breaking: bool,    // Right trigger, held
+    pub place_block: bool, // Left trigger, just pressed
+    pub cycle: i32,        // Hotbar slots to step, from the bumpers
+}
+
+/// Ignores the middle `dead_zone` of the stick and stretches the rest back over 0..1,
+/// so movement starts smoothly at the edge of the dead zone.
+fn apply_dead_zone(stick: Vec2, dead_zone: f32) -> Vec2 {
+    let length = stick.length();
+    if length <= dead_zone {
+        return Vec2::ZERO;
+    }
+    let scaled = ((length - dead_zone) / (1.0 - dead_zone)).min(1.0);
+    stick / length * scaled
+}
+
+/// Reads every connected gamepad into `GamepadInput`
+fn read_gamepads(
+    time: Res<Time>,
+    config: Res<GamepadConfig>,
+    gamepads: Res<Gamepads>,
+    axes: Res<Axis<GamepadAxis>>,
+    buttons: Res<Input<GamepadButton>>,
+    mut input: ResMut<GamepadInput>,
+) {
+    let mut next = GamepadInput::default();
+
+    for gamepad in gamepads.iter() {
+        let stick = |x, y| {
+            let value = |axis| axes.get(GamepadAxis::new(gamepad, axis)).unwrap_or(0.0);
+            apply_dead_zone(Vec2::new(value(x), value(y)), config.dead_zone)
+        };
+        let pressed = |button| buttons.pressed(GamepadButton::new(gamepad, button));
+        let just_pressed = |button| buttons.just_pressed(GamepadButton::new(gamepad, button));
+
+        next.movement += stick(GamepadAxisType::LeftStickX, GamepadAxisType::LeftStickY);
+        next.look += stick(GamepadAxisType::RightStickX, GamepadAxisType::RightStickY)
+            * config.look_speed
+            * time.delta_seconds();
+
+        if pressed(GamepadButtonType::South) {
+            next.rise += 1.0;
+        }
+        if pressed(GamepadButtonType::East) {
+            next.rise -= 1.0;
+        }
+        next.sprint |= pressed(GamepadButtonType::LeftThumb);
+
+        next.break_block |= just_pressed(GamepadButtonType::RightTrigger2);
+        next.breaking |= pressed(GamepadButtonType::RightTrigger2);
+        next.place_block |= just_pressed(GamepadButtonType::LeftTrigger2);
+        if just_pressed(GamepadButtonType::RightTrigger) {
+            next.cycle += 1;
+        }
+        if just_pressed(GamepadButtonType::LeftTrigger) {
+            next.cycle -= 1;
+        }
+    }
+
+    next.movement = next.movement.clamp_length_max(1.0);
+    next.rise = next.rise.clamp(-1.0, 1.0);
+    *input = next;
+}
+
+/// Gamepad controls, on top of the keyboard and mouse
+pub struct GamepadPlugin;
+impl Plugin for GamepadPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<GamepadConfig>()
+            .init_resource::<GamepadInput>()
+            // Right after Bevy reads the gamepads, so everything in Update sees this frame.
+            .add_system(
+                read_gamepads
+                    .in_base_set(CoreSet::PreUpdate)
+                    .after(InputSystem),
+            );
+    }
+}
@@ -0,0 +1,160 @@
+use bevy::prelude::*;
+
+use crate::cam::{Landed, Player, SpawnPoint};
+use crate::menu::GameState;
+use crate::mode::GameMode;
+
+const MAX_HEALTH: f32 = 20.0;
+const SAFE_FALL: f32 = 3.0; // Blocks that can be fallen without getting hurt
+const FALL_DAMAGE: f32 = 1.0; // Per block fallen past `SAFE_FALL`
+const WATER_FALL_DAMAGE: f32 = 0.25; // Share of the damage taken landing in water
+const BAR_WIDTH: f32 = 200.0; // In pixels
+const BAR_HEIGHT: f32 = 8.0;
+
+/// How much more the player can take before dying. Only survival hurts.
+#[derive(Component)]
+pub struct Health(pub f32);
+
+impl Default for Health {
+    fn default() -> Self {
+        Self(MAX_HEALTH)
+    }
+}
+
+/// The filled part of the health bar
+#[derive(Component)]
+struct HealthBar;
+
+/// Marks the health bar's root, shown in survival
+#[derive(Component)]
+struct HealthDisplay;
+
+/// A red bar above the hotbar
+fn setup_health_bar(mut commands: Commands) {
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    position: UiRect {
+                        bottom: Val::Px(68.0), // Just above the hotbar
+                        ..default()
+                    },
+                    size: Size::width(Val::Percent(100.0)),
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                visibility: Visibility::Hidden,
+                ..default()
+            },
+            HealthDisplay,
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        size: Size::new(Val::Px(BAR_WIDTH), Val::Px(BAR_HEIGHT)),
+                        ..default()
+                    },
+                    background_color: Color::rgba(0.1, 0.1, 0.1, 0.6).into(),
+                    ..default()
+                })
+                .with_children(|parent| {
+                    parent.spawn((
+                        NodeBundle {
+                            style: Style {
+                                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                                ..default()
+                            },
+                            background_color: Color::rgb(0.8, 0.15, 0.15).into(),
+                            ..default()
+                        },
+                        HealthBar,
+                    ));
+                });
+        });
+}
+
+/// Hurts survival players for however far past `SAFE_FALL` they fell, less if they
+/// landed in water
+fn fall_damage(
+    mode: Res<GameMode>,
+    mut landings: EventReader<Landed>,
+    mut players: Query<&mut Health, With<Player>>,
+) {
+    for landing in landings.iter() {
+        if *mode != GameMode::Survival {
+            continue;
+        }
+        let mut damage = (landing.distance - SAFE_FALL).max(0.0) * FALL_DAMAGE;
+        if landing.in_water {
+            damage *= WATER_FALL_DAMAGE;
+        }
+        if damage <= 0.0 {
+            continue;
+        }
+        for mut health in players.iter_mut() {
+            health.0 = (health.0 - damage).max(0.0);
+            info!(
+                "Fell {:.1} blocks, health {:.0}",
+                landing.distance, health.0
+            );
+        }
+    }
+}
+
+/// Puts players who ran out of health back at the spawn point, healed
+fn respawn(spawn: Res<SpawnPoint>, mut players: Query<(&mut Transform, &mut Player, &mut Health)>) {
+    for (mut transform, mut player, mut health) in players.iter_mut() {
+        if health.0 > 0.0 {
+            continue;
+        }
+        info!("Died, respawning");
+        transform.translation = spawn.0;
+        player.velocity = Vec3::ZERO;
+        player.fall_distance = 0.0;
+        health.0 = MAX_HEALTH;
+    }
+}
+
+fn update_health_bar(
+    mode: Res<GameMode>,
+    players: Query<&Health, With<Player>>,
+    mut displays: Query<&mut Visibility, With<HealthDisplay>>,
+    mut bars: Query<&mut Style, With<HealthBar>>,
+) {
+    // Only touched when they differ, so the UI isn't laid out again every frame.
+    let shown = match *mode {
+        GameMode::Survival => Visibility::Inherited,
+        GameMode::Creative => Visibility::Hidden,
+    };
+    for mut visibility in displays.iter_mut() {
+        if *visibility != shown {
+            *visibility = shown;
+        }
+    }
+    let Ok(health) = players.get_single() else {
+        return;
+    };
+    let width = Val::Percent(health.0 / MAX_HEALTH * 100.0);
+    for mut style in bars.iter_mut() {
+        if style.size.width != width {
+            style.size.width = width;
+        }
+    }
+}
+
+/// Health, fall damage and dying in survival
+pub struct HealthPlugin;
+impl Plugin for HealthPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(setup_health_bar.on_startup())
+            .add_system(fall_damage.run_if(in_state(GameState::Playing)))
+            .add_system(
+                respawn
+                    .after(fall_damage)
+                    .run_if(in_state(GameState::Playing)),
+            )
+            .add_system(update_health_bar.after(respawn));
+    }
+}
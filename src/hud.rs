@@ -0,0 +1,180 @@
+use bevy::input::mouse::MouseWheel;
+use bevy::prelude::*;
+
+use crate::controls::{Action, Actions};
+use crate::edit::SelectedBlock;
+use crate::gamepad::GamepadInput;
+use crate::menu::GameState;
+use crate::world::{BlockRegistry, BlockType};
+
+const HOTBAR: [BlockType; 7] = [
+    BlockType::Grass,
+    BlockType::Dirt,
+    BlockType::Stone,
+    BlockType::Sand,
+    BlockType::Gravel,
+    BlockType::Wood,
+    BlockType::Torch,
+];
+const HOTBAR_KEYS: [KeyCode; 7] = [
+    KeyCode::Key1,
+    KeyCode::Key2,
+    KeyCode::Key3,
+    KeyCode::Key4,
+    KeyCode::Key5,
+    KeyCode::Key6,
+    KeyCode::Key7,
+];
+const SLOT_SIZE: f32 = 48.0; // In pixels
+const CROSSHAIR_SIZE: f32 = 16.0;
+const ACTIVE_SLOT: Color = Color::rgba(1.0, 1.0, 1.0, 0.9);
+const INACTIVE_SLOT: Color = Color::rgba(0.1, 0.1, 0.1, 0.6);
+
+/// A hotbar slot, holding the index of its block in `HOTBAR`
+#[derive(Component)]
+struct HotbarSlot(usize);
+
+/// A plus in the middle of the screen, and the hotbar along the bottom
+fn setup_hud(mut commands: Commands, registry: Res<BlockRegistry>) {
+    // Crosshair
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            ..default()
+        })
+        .with_children(|parent| {
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        size: Size::new(Val::Px(CROSSHAIR_SIZE), Val::Px(CROSSHAIR_SIZE)),
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        ..default()
+                    },
+                    ..default()
+                })
+                .with_children(|parent| {
+                    for size in [
+                        Size::new(Val::Px(CROSSHAIR_SIZE), Val::Px(2.0)),
+                        Size::new(Val::Px(2.0), Val::Px(CROSSHAIR_SIZE)),
+                    ] {
+                        parent.spawn(NodeBundle {
+                            style: Style {
+                                position_type: PositionType::Absolute,
+                                size,
+                                ..default()
+                            },
+                            background_color: Color::WHITE.into(),
+                            ..default()
+                        });
+                    }
+                });
+        });
+
+    // Hotbar
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    bottom: Val::Px(12.0),
+                    ..default()
+                },
+                size: Size::width(Val::Percent(100.0)),
+                justify_content: JustifyContent::Center,
+                gap: Size::width(Val::Px(4.0)),
+                ..default()
+            },
+            ..default()
+        })
+        .with_children(|parent| {
+            for (i, btype) in HOTBAR.iter().enumerate() {
+                parent
+                    .spawn((
+                        NodeBundle {
+                            style: Style {
+                                size: Size::new(Val::Px(SLOT_SIZE), Val::Px(SLOT_SIZE)),
+                                padding: UiRect::all(Val::Px(4.0)),
+                                ..default()
+                            },
+                            background_color: INACTIVE_SLOT.into(),
+                            ..default()
+                        },
+                        HotbarSlot(i),
+                    ))
+                    .with_children(|parent| {
+                        parent.spawn(NodeBundle {
+                            style: Style {
+                                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                                ..default()
+                            },
+                            background_color: registry.get(btype).color().into(),
+                            ..default()
+                        });
+                    });
+            }
+        });
+}
+
+/// Number keys pick a slot, the scroll wheel and gamepad bumpers step through them.
+/// Scrolling with the fly speed key held is left to the camera.
+fn select_block(
+    keys: Res<Input<KeyCode>>,
+    actions: Actions,
+    gamepad: Res<GamepadInput>,
+    mut scroll: EventReader<MouseWheel>,
+    mut selected: ResMut<SelectedBlock>,
+) {
+    let current = HOTBAR.iter().position(|btype| *btype == selected.0);
+    let mut slot = current.unwrap_or(0) as i32;
+
+    for (i, key) in HOTBAR_KEYS.iter().enumerate() {
+        if keys.just_pressed(*key) {
+            slot = i as i32;
+        }
+    }
+
+    let adjusting_speed = actions.pressed(Action::AdjustFlySpeed);
+    for event in scroll.iter() {
+        // Scrolling down moves right, like most games.
+        if !adjusting_speed {
+            slot -= event.y.signum() as i32;
+        }
+    }
+    slot += gamepad.cycle;
+
+    let slot = slot.rem_euclid(HOTBAR.len() as i32) as usize;
+    if current != Some(slot) {
+        selected.0 = HOTBAR[slot];
+    }
+}
+
+/// Lights up the slot of the selected block
+fn update_hotbar(
+    selected: Res<SelectedBlock>,
+    mut slots: Query<(&HotbarSlot, &mut BackgroundColor)>,
+) {
+    for (slot, mut color) in slots.iter_mut() {
+        color.0 = if HOTBAR[slot.0] == selected.0 {
+            ACTIVE_SLOT
+        } else {
+            INACTIVE_SLOT
+        };
+    }
+}
+
+/// Crosshair and hotbar
+pub struct HudPlugin;
+impl Plugin for HudPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(setup_hud.on_startup())
+            .add_system(select_block.run_if(in_state(GameState::Playing)))
+            .add_system(update_hotbar.after(select_block));
+    }
+}
@@ -0,0 +1,121 @@
+use bevy::prelude::*;
+
+use crate::cam::Player;
+use crate::console::FONT;
+use crate::menu::GameState;
+use crate::world::{Map, RenderDistance};
+
+const BAR_WIDTH: f32 = 400.0; // In pixels
+const BAR_HEIGHT: f32 = 16.0;
+
+/// Marks the loading screen's root
+#[derive(Component)]
+struct LoadingScreen;
+
+#[derive(Component)]
+struct LoadingText;
+
+#[derive(Component)]
+struct LoadingBar;
+
+/// Covers the screen with a progress bar while the world around spawn generates
+fn setup_loading_screen(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                    flex_direction: FlexDirection::Column,
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    gap: Size::height(Val::Px(12.0)),
+                    ..default()
+                },
+                background_color: Color::rgb(0.1, 0.08, 0.06).into(),
+                z_index: ZIndex::Global(20),
+                ..default()
+            },
+            LoadingScreen,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                TextBundle::from_section(
+                    "Generating world",
+                    TextStyle {
+                        font: asset_server.load(FONT),
+                        font_size: 24.0,
+                        color: Color::WHITE,
+                    },
+                ),
+                LoadingText,
+            ));
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        size: Size::new(Val::Px(BAR_WIDTH), Val::Px(BAR_HEIGHT)),
+                        ..default()
+                    },
+                    background_color: Color::rgb(0.25, 0.25, 0.25).into(),
+                    ..default()
+                })
+                .with_children(|parent| {
+                    parent.spawn((
+                        NodeBundle {
+                            style: Style {
+                                size: Size::new(Val::Percent(0.0), Val::Percent(100.0)),
+                                ..default()
+                            },
+                            background_color: Color::rgb(0.3, 0.7, 0.3).into(),
+                            ..default()
+                        },
+                        LoadingBar,
+                    ));
+                });
+        });
+}
+
+/// Fills the bar with the share of chunks in render distance that are loaded, and starts
+/// the game once they all are
+fn update_loading_screen(
+    map: Res<Map>,
+    render_distance: Res<RenderDistance>,
+    player: Query<&Transform, With<Player>>,
+    mut texts: Query<&mut Text, With<LoadingText>>,
+    mut bars: Query<&mut Style, With<LoadingBar>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    let Ok(player) = player.get_single() else {
+        return;
+    };
+    let center = Map::world_to_chunk(player.translation.floor().as_ivec3());
+    let (loaded, total) = map.loaded_within(center, render_distance.0);
+    let progress = loaded as f32 / total.max(1) as f32;
+
+    for mut text in texts.iter_mut() {
+        text.sections[0].value = format!("Generating world... {:.0}%", progress * 100.0);
+    }
+    for mut style in bars.iter_mut() {
+        style.size.width = Val::Percent(progress * 100.0);
+    }
+
+    if loaded == total {
+        next_state.set(GameState::Playing);
+    }
+}
+
+fn despawn_loading_screen(mut commands: Commands, screens: Query<Entity, With<LoadingScreen>>) {
+    for screen in screens.iter() {
+        commands.entity(screen).despawn_recursive();
+    }
+}
+
+/// A loading screen until the chunks around spawn are ready
+pub struct LoadingPlugin;
+impl Plugin for LoadingPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(setup_loading_screen.in_schedule(OnEnter(GameState::Loading)))
+            .add_system(update_loading_screen.run_if(in_state(GameState::Loading)))
+            .add_system(despawn_loading_screen.in_schedule(OnExit(GameState::Loading)));
+    }
+}
@@ -1,10 +1,9 @@
 use std::f32::consts::PI;
 
 use bevy::{
-    diagnostic::{FrameTimeDiagnosticsPlugin, LogDiagnosticsPlugin},
-    pbr::CascadeShadowConfigBuilder,
+    pbr::{CascadeShadowConfig, CascadeShadowConfigBuilder},
     prelude::*,
-    window::{WindowMode, WindowResolution},
+    window::PrimaryWindow,
 };
 // use bevy_flycam::PlayerPlugin;
 
@@ -14,64 +13,201 @@ use world::*;
 mod cam;
 use cam::*;
 
-const CHUNK_SIZE: i32 = 16;
-const RENDER_DISTANCE: i32 = 4; // In chunks
+mod edit;
+use edit::*;
+
+mod save;
+use save::*;
+
+mod debug;
+use debug::*;
+
+mod sky;
+use sky::*;
+
+mod hud;
+use hud::*;
+
+mod water;
+use water::*;
+
+mod chunk_material;
+use chunk_material::*;
+
+mod gamepad;
+use gamepad::*;
+
+mod controls;
+use controls::*;
+
+mod screenshot;
+use screenshot::*;
+
+mod console;
+use console::*;
+
+mod mode;
+use mode::*;
+
+mod mob;
+use mob::*;
+
+mod particles;
+use particles::*;
+
+mod sound;
+use sound::*;
+
+mod weather;
+use weather::*;
+
+mod menu;
+use menu::*;
+
+mod loading;
+use loading::*;
+
+mod settings;
+use settings::*;
+
+mod health;
+use health::*;
 
 // This is a simple example of a camera that flies around.
 // There's an included example of a system that toggles the "enabled"
 // property of the fly camera with "T"
 
-fn init(
-    mut commands: Commands,
-    asset_server: Res<AssetServer>,
-    mut atlas: ResMut<Assets<TextureAtlas>>,
-    mut map: ResMut<Map>,
-) {
-    // directional 'sun' light
-    commands.spawn(DirectionalLightBundle {
-        directional_light: DirectionalLight {
-            shadows_enabled: true,
-            illuminance: 10000.0,
-            ..default()
-        },
-        transform: Transform {
-            translation: Vec3::new(0.0, 100.0, 0.0),
-            rotation: Quat::from_rotation_x(-PI / 4.),
+/// Shadows reach as far as the loaded terrain and no further.
+fn shadow_config(render_distance: &RenderDistance) -> CascadeShadowConfig {
+    CascadeShadowConfigBuilder {
+        first_cascade_far_bound: CHUNK_SIZE as f32,
+        num_cascades: 4,
+        maximum_distance: (CHUNK_SIZE * render_distance.0) as f32,
+        ..default()
+    }
+    .into()
+}
+
+fn init(mut commands: Commands, render_distance: Res<RenderDistance>) {
+    // directional 'sun' light, SkyPlugin moves it through the day
+    commands.spawn((
+        DirectionalLightBundle {
+            directional_light: DirectionalLight {
+                shadows_enabled: true,
+                illuminance: 10000.0,
+                ..default()
+            },
+            transform: Transform {
+                translation: Vec3::new(0.0, 100.0, 0.0),
+                rotation: Quat::from_rotation_x(-PI / 4.),
+                ..default()
+            },
+            cascade_shadow_config: shadow_config(&render_distance),
             ..default()
         },
-        cascade_shadow_config: CascadeShadowConfigBuilder {
-            first_cascade_far_bound: CHUNK_SIZE as f32,
-            num_cascades: 4,
-            maximum_distance: CHUNK_SIZE as f32 * RENDER_DISTANCE as f32,
-            ..default()
+        Sun,
+    ));
+}
+
+/// Keeps the sun's shadows in step with the render distance when it changes
+fn update_shadow_distance(
+    render_distance: Res<RenderDistance>,
+    mut lights: Query<&mut CascadeShadowConfig, With<DirectionalLight>>,
+) {
+    for mut config in lights.iter_mut() {
+        *config = shadow_config(&render_distance);
+    }
+}
+
+/// Value of a `--name <value>` or `--name=<value>` command line argument
+pub fn cli_arg(name: &str) -> Option<String> {
+    let flag = format!("--{}", name);
+    let mut args = std::env::args().skip(1);
+
+    while let Some(arg) = args.next() {
+        if arg == flag {
+            return args.next();
+        } else if let Some(value) = arg.strip_prefix(&format!("{}=", flag)) {
+            return Some(value.to_string());
         }
-        .into(),
-        ..default()
-    });
+    }
 
-    let texture: Handle<Image> = asset_server.load("../resources/alpha_atlas.png");
-    // Save the texture handle so we can use it later.
-    let texture_atlas = TextureAtlas::from_grid(texture, Vec2::new(16., 16.), 16, 16, None, None);
-    map.texture_atlas = atlas.add(texture_atlas);
+    None
+}
+
+/// Shows the world name in the window title
+fn show_world_name(
+    config: Res<WorldConfig>,
+    mut primary_window: Query<&mut Window, With<PrimaryWindow>>,
+) {
+    if let Ok(mut window) = primary_window.get_single_mut() {
+        window.title = format!("Minecraft - {}", config.name);
+    }
 }
 
 fn main() {
+    let mut world_config = cli_arg("seed")
+        .map(|seed| WorldConfig::from_seed_string(&seed))
+        .unwrap_or_default();
+    if let Some(name) = cli_arg("name") {
+        world_config.name = name;
+    }
+    if let Some(gen_mode) = cli_arg("gen").and_then(|name| GenMode::from_name(&name)) {
+        world_config.gen_mode = gen_mode;
+    }
+    if let Some(radius) = cli_arg("island").and_then(|radius| radius.parse().ok()) {
+        world_config.island = Some(Island::new(radius));
+    }
+    if let Some(level) = cli_arg("water-level").and_then(|level| level.parse().ok()) {
+        world_config.water_level = level;
+    }
+    let terrain_params = cli_arg("terrain")
+        .and_then(|name| TerrainPreset::from_name(&name))
+        .map(TerrainParams::preset)
+        .unwrap_or_default();
+    let video_settings = VideoSettings::load_or_default();
+
     App::new()
         .insert_resource(Msaa::Sample4)
         .add_plugins(DefaultPlugins.set(WindowPlugin {
             primary_window: Some(Window {
                 title: "Minecraft".to_string(),
-                resolution: WindowResolution::new(1440.0, 1080.0), // 4:3
-                mode: WindowMode::Windowed,
+                resolution: video_settings.window_resolution(),
+                mode: video_settings.mode(),
+                present_mode: video_settings.present_mode(),
                 ..Default::default()
             }),
             ..Default::default()
         }))
-        // .add_plugin(LogDiagnosticsPlugin::default())
-        // .add_plugin(FrameTimeDiagnosticsPlugin::default())
+        // .add_plugin(bevy::diagnostic::LogDiagnosticsPlugin::default())
+        // .add_plugin(bevy::diagnostic::FrameTimeDiagnosticsPlugin::default())
+        .insert_resource(world_config)
+        .insert_resource(terrain_params)
+        .insert_resource(video_settings)
+        .add_plugin(ControlsPlugin)
+        .add_plugin(WorldPlugin)
         .add_plugin(PlayerPlugin)
+        .add_plugin(EditPlugin)
+        .add_plugin(SavePlugin)
+        .add_plugin(DebugPlugin)
+        .add_plugin(SkyPlugin)
+        .add_plugin(HudPlugin)
+        .add_plugin(WaterPlugin)
+        .add_plugin(ChunkMaterialPlugin)
+        .add_plugin(GamepadPlugin)
+        .add_plugin(ScreenshotPlugin)
+        .add_plugin(ConsolePlugin)
+        .add_plugin(GameModePlugin)
+        .add_plugin(MobPlugin)
+        .add_plugin(ParticlePlugin)
+        .add_plugin(SoundPlugin)
+        .add_plugin(WeatherPlugin)
+        .add_plugin(MenuPlugin)
+        .add_plugin(LoadingPlugin)
+        .add_plugin(SettingsPlugin)
+        .add_plugin(HealthPlugin)
         .add_startup_system(init)
-        .init_resource::<Map>()
-        .add_system(update_world)
+        .add_startup_system(show_world_name)
+        .add_system(update_shadow_distance.run_if(resource_changed::<RenderDistance>()))
         .run();
 }
@@ -0,0 +1,259 @@
+use bevy::app::AppExit;
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+
+use crate::cam::set_cursor_grab;
+use crate::console::FONT;
+use crate::controls::{Action, Actions};
+use crate::settings::VideoSettings;
+
+const BUTTON_SIZE: Size = Size::new(Val::Px(240.0), Val::Px(48.0));
+const BUTTON_FONT_SIZE: f32 = 24.0;
+const BUTTON: Color = Color::rgba(0.15, 0.15, 0.15, 0.9);
+const BUTTON_HOVERED: Color = Color::rgba(0.3, 0.3, 0.3, 0.9);
+const BUTTON_PRESSED: Color = Color::rgba(0.45, 0.45, 0.45, 0.9);
+
+/// What the game is doing. The world and player only run while `Playing`, gate their
+/// systems with `run_if(in_state(GameState::Playing))`.
+#[derive(States, Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub enum GameState {
+    #[default]
+    Loading, // Until the chunks around spawn are generated
+    Playing,
+    Paused,
+    Settings, // Opened from the pause menu
+}
+
+/// What a menu button does when clicked
+#[derive(Component, Clone, Copy)]
+enum MenuButton {
+    Resume,
+    Settings,
+    Quit,
+    Fullscreen,
+    Resolution,
+    VSync,
+    FramerateLimit,
+    Back,
+}
+
+impl MenuButton {
+    fn label(self, settings: &VideoSettings) -> String {
+        let on_off = |on| if on { "On" } else { "Off" };
+        match self {
+            MenuButton::Resume => "Resume".to_string(),
+            MenuButton::Settings => "Settings".to_string(),
+            MenuButton::Quit => "Quit".to_string(),
+            MenuButton::Fullscreen => format!("Fullscreen: {}", on_off(settings.fullscreen)),
+            MenuButton::Resolution => {
+                format!(
+                    "Resolution: {}x{}",
+                    settings.resolution.0, settings.resolution.1
+                )
+            }
+            MenuButton::VSync => format!("VSync: {}", on_off(settings.vsync)),
+            MenuButton::FramerateLimit => match settings.framerate_limit.0 {
+                Some(fps) => format!("Max FPS: {}", fps),
+                None => "Max FPS: Off".to_string(),
+            },
+            MenuButton::Back => "Back".to_string(),
+        }
+    }
+}
+
+/// Marks a menu's root, removed again when leaving its state
+#[derive(Component)]
+struct Menu;
+
+/// The text on a button, kept up to date with the settings it shows
+#[derive(Component)]
+struct ButtonLabel(MenuButton);
+
+/// Escape pauses the game, and resumes it again from the pause menu
+fn toggle_pause(
+    actions: Actions,
+    state: Res<State<GameState>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if !actions.just_pressed(Action::Pause) {
+        return;
+    }
+    match state.0 {
+        GameState::Playing => next_state.set(GameState::Paused),
+        GameState::Paused => next_state.set(GameState::Playing),
+        GameState::Settings => next_state.set(GameState::Paused),
+        GameState::Loading => (),
+    }
+}
+
+fn grab_cursor(mut primary_window: Query<&mut Window, With<PrimaryWindow>>) {
+    if let Ok(mut window) = primary_window.get_single_mut() {
+        set_cursor_grab(&mut window, true);
+    }
+}
+
+fn release_cursor(mut primary_window: Query<&mut Window, With<PrimaryWindow>>) {
+    if let Ok(mut window) = primary_window.get_single_mut() {
+        set_cursor_grab(&mut window, false);
+    }
+}
+
+/// Dims the screen and puts a column of buttons in the middle of it
+fn spawn_menu(
+    commands: &mut Commands,
+    font: Handle<Font>,
+    buttons: &[MenuButton],
+    settings: &VideoSettings,
+) {
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                    flex_direction: FlexDirection::Column,
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    gap: Size::height(Val::Px(12.0)),
+                    ..default()
+                },
+                background_color: Color::rgba(0.0, 0.0, 0.0, 0.5).into(),
+                // Above the HUD and console.
+                z_index: ZIndex::Global(10),
+                ..default()
+            },
+            Menu,
+        ))
+        .with_children(|parent| {
+            for &button in buttons {
+                parent
+                    .spawn((
+                        ButtonBundle {
+                            style: Style {
+                                size: BUTTON_SIZE,
+                                justify_content: JustifyContent::Center,
+                                align_items: AlignItems::Center,
+                                ..default()
+                            },
+                            background_color: BUTTON.into(),
+                            ..default()
+                        },
+                        button,
+                    ))
+                    .with_children(|parent| {
+                        parent.spawn((
+                            TextBundle::from_section(
+                                button.label(settings),
+                                TextStyle {
+                                    font: font.clone(),
+                                    font_size: BUTTON_FONT_SIZE,
+                                    color: Color::WHITE,
+                                },
+                            ),
+                            ButtonLabel(button),
+                        ));
+                    });
+            }
+        });
+}
+
+fn setup_pause_menu(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    settings: Res<VideoSettings>,
+) {
+    let buttons = [MenuButton::Resume, MenuButton::Settings, MenuButton::Quit];
+    spawn_menu(&mut commands, asset_server.load(FONT), &buttons, &settings);
+}
+
+fn setup_settings_menu(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    settings: Res<VideoSettings>,
+) {
+    let buttons = [
+        MenuButton::Fullscreen,
+        MenuButton::Resolution,
+        MenuButton::VSync,
+        MenuButton::FramerateLimit,
+        MenuButton::Back,
+    ];
+    spawn_menu(&mut commands, asset_server.load(FONT), &buttons, &settings);
+}
+
+fn update_button_labels(
+    settings: Res<VideoSettings>,
+    mut labels: Query<(&mut Text, &ButtonLabel)>,
+) {
+    for (mut text, label) in labels.iter_mut() {
+        text.sections[0].value = label.0.label(&settings);
+    }
+}
+
+fn despawn_menu(mut commands: Commands, menus: Query<Entity, With<Menu>>) {
+    for menu in menus.iter() {
+        commands.entity(menu).despawn_recursive();
+    }
+}
+
+/// Highlights hovered buttons and acts on clicked ones
+#[allow(clippy::type_complexity)]
+fn menu_buttons(
+    mut buttons: Query<
+        (&Interaction, &MenuButton, &mut BackgroundColor),
+        (Changed<Interaction>, With<Button>),
+    >,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut settings: ResMut<VideoSettings>,
+    mut exit: EventWriter<AppExit>,
+) {
+    for (interaction, button, mut color) in buttons.iter_mut() {
+        *color = match interaction {
+            Interaction::Clicked => BUTTON_PRESSED,
+            Interaction::Hovered => BUTTON_HOVERED,
+            Interaction::None => BUTTON,
+        }
+        .into();
+
+        if *interaction == Interaction::Clicked {
+            match button {
+                MenuButton::Resume => next_state.set(GameState::Playing),
+                MenuButton::Settings => next_state.set(GameState::Settings),
+                // Saving on exit takes care of the world.
+                MenuButton::Quit => exit.send(AppExit),
+                MenuButton::Fullscreen => settings.fullscreen = !settings.fullscreen,
+                MenuButton::Resolution => settings.next_resolution(),
+                MenuButton::VSync => settings.vsync = !settings.vsync,
+                MenuButton::FramerateLimit => {
+                    settings.framerate_limit = settings.framerate_limit.next()
+                }
+                MenuButton::Back => next_state.set(GameState::Paused),
+            }
+        }
+    }
+}
+
+/// Game states, and the pause and settings menus
+pub struct MenuPlugin;
+impl Plugin for MenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_state::<GameState>()
+            .add_system(toggle_pause)
+            .add_system(
+                menu_buttons
+                    .run_if(in_state(GameState::Paused).or_else(in_state(GameState::Settings))),
+            )
+            .add_system(
+                update_button_labels
+                    .after(menu_buttons)
+                    .run_if(resource_changed::<VideoSettings>()),
+            )
+            .add_system(setup_pause_menu.in_schedule(OnEnter(GameState::Paused)))
+            .add_system(despawn_menu.in_schedule(OnExit(GameState::Paused)))
+            .add_system(setup_settings_menu.in_schedule(OnEnter(GameState::Settings)))
+            .add_system(despawn_menu.in_schedule(OnExit(GameState::Settings)))
+            // The menus all want the cursor, only play needs it grabbed.
+            .add_system(grab_cursor.in_schedule(OnEnter(GameState::Playing)))
+            .add_system(release_cursor.in_schedule(OnExit(GameState::Playing)));
+    }
+}
@@ -0,0 +1,153 @@
+use bevy::math::Vec3Swizzles;
+use bevy::prelude::*;
+use rand::Rng;
+use std::f32::consts::TAU;
+
+use crate::cam::GRAVITY;
+use crate::menu::GameState;
+use crate::world::*;
+
+const MOB_CHANCE: f64 = 0.1; // Chance of a mob turning up in a newly loaded chunk
+const MOB_SIZE: f32 = 0.8; // Width, depth and height, in blocks
+const MOB_SPEED: f32 = 1.5; // Blocks per second
+const MOB_COLOR: Color = Color::rgb(0.85, 0.6, 0.55);
+
+/// A creature that wanders about on the surface. Its transform is the middle of its cube.
+#[derive(Component, Default)]
+pub struct Mob {
+    heading: Vec2,   // Direction on the ground, zero while it stands still
+    fall_speed: f32, // Positive is down
+    turn_in: f32,    // Seconds until it picks a new heading
+}
+
+/// The mesh and material all mobs share
+#[derive(Resource)]
+struct MobAssets {
+    mesh: Handle<Mesh>,
+    material: Handle<StandardMaterial>,
+}
+
+fn setup_mobs(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    commands.insert_resource(MobAssets {
+        mesh: meshes.add(shape::Cube { size: MOB_SIZE }.into()),
+        material: materials.add(MOB_COLOR.into()),
+    });
+}
+
+/// Top of the ground in the column under `pos`, or `None` for water and chunks that
+/// aren't loaded, which mobs keep out of.
+fn ground_height(map: &Map, pos: Vec2) -> Option<f32> {
+    let (x, z) = (pos.x.floor() as i32, pos.y.floor() as i32);
+    let y = map.surface_height(x, z);
+    match map.get_block(IVec3::new(x, y, z))? {
+        BlockType::Water => None,
+        _ => Some((y + 1) as f32),
+    }
+}
+
+/// Now and then puts a mob somewhere on the surface of a chunk that just loaded
+fn spawn_mobs(
+    mut commands: Commands,
+    map: Res<Map>,
+    assets: Res<MobAssets>,
+    mut loaded: EventReader<ChunkLoaded>,
+) {
+    let mut rng = rand::thread_rng();
+    for event in loaded.iter() {
+        if !rng.gen_bool(MOB_CHANCE) {
+            continue;
+        }
+
+        let corner = event.position * CHUNK_SIZE;
+        let column =
+            corner + IVec2::new(rng.gen_range(0..CHUNK_SIZE), rng.gen_range(0..CHUNK_SIZE));
+        let pos = column.as_vec2() + Vec2::splat(0.5);
+        let Some(ground) = ground_height(&map, pos) else {
+            continue;
+        };
+
+        commands.spawn((
+            PbrBundle {
+                mesh: assets.mesh.clone(),
+                material: assets.material.clone(),
+                transform: Transform::from_xyz(pos.x, ground + MOB_SIZE / 2.0, pos.y),
+                ..default()
+            },
+            Mob::default(),
+        ));
+    }
+}
+
+/// Walks mobs in a random direction for a few seconds at a time, stepping up single
+/// blocks and falling down drops. Anything higher, water, or the edge of the loaded
+/// world turns them around.
+fn wander_mobs(time: Res<Time>, map: Res<Map>, mut mobs: Query<(&mut Transform, &mut Mob)>) {
+    let mut rng = rand::thread_rng();
+    let delta = time.delta_seconds();
+
+    for (mut transform, mut mob) in mobs.iter_mut() {
+        mob.turn_in -= delta;
+        if mob.turn_in <= 0.0 {
+            mob.turn_in = rng.gen_range(2.0..5.0);
+            mob.heading = if rng.gen_bool(0.3) {
+                Vec2::ZERO
+            } else {
+                Vec2::from_angle(rng.gen_range(0.0..TAU))
+            };
+        }
+
+        let feet = transform.translation.y - MOB_SIZE / 2.0;
+        let next = transform.translation.xz() + mob.heading * MOB_SPEED * delta;
+        match ground_height(&map, next) {
+            Some(ground) if ground <= feet + 1.0 => {
+                transform.translation.x = next.x;
+                transform.translation.z = next.y;
+            }
+            _ => mob.heading = -mob.heading,
+        }
+
+        // Fall onto the ground, or get lifted onto the block just stepped up on.
+        let ground = ground_height(&map, transform.translation.xz()).unwrap_or(feet);
+        mob.fall_speed += GRAVITY * delta;
+        let mut feet = feet - mob.fall_speed * delta;
+        if feet <= ground {
+            feet = ground;
+            mob.fall_speed = 0.0;
+        }
+        transform.translation.y = feet + MOB_SIZE / 2.0;
+    }
+}
+
+/// Mobs go with the chunk they're standing in
+fn despawn_mobs(
+    mut commands: Commands,
+    mut unloaded: EventReader<ChunkUnloaded>,
+    mobs: Query<(Entity, &Transform), With<Mob>>,
+) {
+    let gone: Vec<IVec2> = unloaded.iter().map(|event| event.0).collect();
+    if gone.is_empty() {
+        return;
+    }
+
+    for (entity, transform) in mobs.iter() {
+        let chunk = Map::world_to_chunk(transform.translation.floor().as_ivec3());
+        if gone.contains(&chunk) {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+/// Wandering creatures
+pub struct MobPlugin;
+impl Plugin for MobPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(setup_mobs.on_startup())
+            .add_system(spawn_mobs)
+            .add_system(wander_mobs.run_if(in_state(GameState::Playing)))
+            .add_system(despawn_mobs);
+    }
+}
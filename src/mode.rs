@@ -0,0 +1,47 @@
+use bevy::prelude::*;
+
+use crate::cam::Player;
+use crate::controls::{Action, Actions};
+use crate::menu::GameState;
+
+/// How the player interacts with the world. Creative flies and breaks blocks at once
+/// with as many blocks as it likes, survival walks under gravity and breaks slowly.
+#[derive(Resource, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum GameMode {
+    #[default]
+    Creative,
+    Survival,
+}
+
+/// Switches game mode when M is pressed. Survival can't fly, so it lands the player.
+fn toggle_game_mode(actions: Actions, mut mode: ResMut<GameMode>, mut players: Query<&mut Player>) {
+    if !actions.just_pressed(Action::ToggleGameMode) {
+        return;
+    }
+
+    *mode = match *mode {
+        GameMode::Creative => GameMode::Survival,
+        GameMode::Survival => GameMode::Creative,
+    };
+    info!("Game mode: {:?}", *mode);
+
+    if *mode == GameMode::Survival {
+        for mut player in players.iter_mut() {
+            if !player.walking {
+                player.walking = true;
+                player.velocity = Vec3::ZERO;
+                player.grounded = false;
+                player.fall_distance = 0.0;
+            }
+        }
+    }
+}
+
+/// Creative and survival modes
+pub struct GameModePlugin;
+impl Plugin for GameModePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<GameMode>()
+            .add_system(toggle_game_mode.run_if(in_state(GameState::Playing)));
+    }
+}
@@ -0,0 +1,116 @@
+use bevy::prelude::*;
+use rand::Rng;
+
+use crate::cam::{FlyCam, GRAVITY};
+use crate::edit::BlockBroken;
+use crate::menu::GameState;
+use crate::world::BlockRegistry;
+
+const BURST_SIZE: usize = 16; // Particles per broken block
+const PARTICLE_SIZE: f32 = 0.12; // In blocks
+const PARTICLE_LIFETIME: f32 = 0.5; // Seconds
+const PARTICLE_SPEED: f32 = 3.0; // Fastest a particle leaves the block at
+
+/// A bit of a broken block, flying off and fading out
+#[derive(Component)]
+struct Particle {
+    velocity: Vec3,
+    age: f32,
+    material: Handle<StandardMaterial>, // Shared by the burst, so they fade together
+}
+
+/// The quad every particle is drawn with
+#[derive(Resource)]
+struct ParticleMesh(Handle<Mesh>);
+
+fn setup_particles(mut commands: Commands, mut meshes: ResMut<Assets<Mesh>>) {
+    let mesh = meshes.add(shape::Quad::new(Vec2::splat(PARTICLE_SIZE)).into());
+    commands.insert_resource(ParticleMesh(mesh));
+}
+
+/// Scatters a burst of particles the colour of the block from where it was broken
+fn spawn_particles(
+    mut commands: Commands,
+    registry: Res<BlockRegistry>,
+    mesh: Res<ParticleMesh>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut broken: EventReader<BlockBroken>,
+) {
+    let mut rng = rand::thread_rng();
+    for event in broken.iter() {
+        let material = materials.add(StandardMaterial {
+            base_color: registry.get(&event.btype).color(),
+            alpha_mode: AlphaMode::Blend,
+            ..default()
+        });
+
+        let center = event.pos.as_vec3() + Vec3::splat(0.5);
+        for _ in 0..BURST_SIZE {
+            let offset = Vec3::new(
+                rng.gen_range(-0.5..0.5),
+                rng.gen_range(-0.5..0.5),
+                rng.gen_range(-0.5..0.5),
+            );
+            // Outwards from the middle, and a little up.
+            let velocity = (offset + Vec3::Y * 0.5) * PARTICLE_SPEED * rng.gen_range(0.5..1.0);
+
+            commands.spawn((
+                PbrBundle {
+                    mesh: mesh.0.clone(),
+                    material: material.clone(),
+                    transform: Transform::from_translation(center + offset * 0.8),
+                    ..default()
+                },
+                Particle {
+                    velocity,
+                    age: 0.0,
+                    material: material.clone(),
+                },
+            ));
+        }
+    }
+}
+
+/// Moves particles under gravity, turns them to face the camera, fades them and
+/// removes them at the end of their life
+fn update_particles(
+    mut commands: Commands,
+    time: Res<Time>,
+    camera: Query<&Transform, (With<FlyCam>, Without<Particle>)>,
+    mut particles: Query<(Entity, &mut Transform, &mut Particle)>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let delta = time.delta_seconds();
+    let facing = camera
+        .get_single()
+        .map(|camera| camera.rotation)
+        .unwrap_or_default();
+
+    for (entity, mut transform, mut particle) in particles.iter_mut() {
+        particle.age += delta;
+        if particle.age >= PARTICLE_LIFETIME {
+            commands.entity(entity).despawn();
+            continue;
+        }
+
+        particle.velocity.y -= GRAVITY * delta;
+        transform.translation += particle.velocity * delta;
+        transform.rotation = facing;
+
+        if let Some(material) = materials.get_mut(&particle.material) {
+            material
+                .base_color
+                .set_a(1.0 - particle.age / PARTICLE_LIFETIME);
+        }
+    }
+}
+
+/// Bits flying off broken blocks
+pub struct ParticlePlugin;
+impl Plugin for ParticlePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(setup_particles.on_startup())
+            .add_system(spawn_particles)
+            .add_system(update_particles.run_if(in_state(GameState::Playing)));
+    }
+}
@@ -0,0 +1,177 @@
+use bevy::app::AppExit;
+use bevy::prelude::*;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::cam::{collides, player_aabb, Player, PLAYER_EYE_HEIGHT};
+use crate::cli_arg;
+use crate::controls::{Action, Actions};
+use crate::menu::GameState;
+use crate::world::{Map, PlayerSave, WorldConfig};
+
+const DEFAULT_SAVE_DIR: &str = "saves";
+
+/// Asks for the world to be saved, like pressing F5
+pub struct SaveWorld;
+
+/// Base directory that worlds are saved under. Set with `--save-dir <path>`.
+#[derive(Resource)]
+pub struct SaveDir {
+    pub base: PathBuf,
+}
+
+impl Default for SaveDir {
+    fn default() -> Self {
+        Self {
+            base: PathBuf::from(
+                cli_arg("save-dir").unwrap_or_else(|| DEFAULT_SAVE_DIR.to_string()),
+            ),
+        }
+    }
+}
+
+impl SaveDir {
+    /// Directory for a single world, so multiple worlds can live side by side.
+    pub fn world_dir(&self, config: &WorldConfig) -> PathBuf {
+        let name = config
+            .name
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect::<String>();
+        self.base.join(name)
+    }
+}
+
+/// Creates the directory if needed and makes sure we can write to it.
+fn check_writable(dir: &Path) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    let probe = dir.join(".write_test");
+    fs::write(&probe, b"")?;
+    fs::remove_file(probe)
+}
+
+/// Validates the save directory on startup
+fn check_save_dir(save_dir: Res<SaveDir>, config: Res<WorldConfig>) {
+    let dir = save_dir.world_dir(&config);
+    match check_writable(&dir) {
+        Ok(()) => info!("Saving world to {}", dir.display()),
+        Err(e) => error!("Save directory {} is not writable: {}", dir.display(), e),
+    }
+}
+
+/// Opens the world's save directory, so chunks are read from it as they load, and puts
+/// the player back where they left off if it was saved before. Runs after the player is
+/// spawned at the surface, which is where they stay if there's no saved position.
+fn load_world(
+    save_dir: Res<SaveDir>,
+    mut config: ResMut<WorldConfig>,
+    mut map: ResMut<Map>,
+    mut players: Query<&mut Transform, With<Player>>,
+) {
+    let dir = save_dir.world_dir(&config);
+    let saved = Map::has_save(&dir);
+
+    match map.load(&dir) {
+        Ok(player) => {
+            if saved {
+                info!("Loaded world from {}", dir.display());
+            }
+            // New chunks are generated from the config, so they fill to the saved level.
+            config.water_level = map.water_level();
+            if let Some(player) = player {
+                for mut transform in players.iter_mut() {
+                    transform.translation = player.translation;
+                    transform.rotation = player.rotation;
+                }
+            }
+        }
+        Err(e) => error!("Failed to load {}: {}", dir.display(), e),
+    }
+}
+
+/// Lifts the player out of the ground if they're stuck in it once the chunks around them
+/// are in, which happens when a save's terrain doesn't match what's generated any more.
+/// They end up standing on top of the column.
+fn unbury_player(map: Res<Map>, mut players: Query<(&mut Transform, &mut Player)>) {
+    for (mut transform, mut player) in players.iter_mut() {
+        let (min, max) = player_aabb(transform.translation);
+        if !collides(&map, min, max) {
+            continue;
+        }
+
+        let pos = transform.translation;
+        let column = pos.floor().as_ivec3();
+        let ground = map.surface_height(column.x, column.z);
+        let eye = (ground + 1) as f32 + PLAYER_EYE_HEIGHT;
+        warn!(
+            "Player was stuck in the ground at {:.1} {:.1} {:.1}, moved up to {:.1}",
+            pos.x, pos.y, pos.z, eye
+        );
+        transform.translation.y = eye;
+        player.velocity = Vec3::ZERO;
+        player.fall_distance = 0.0;
+    }
+}
+
+fn write_world(
+    save_dir: &SaveDir,
+    config: &WorldConfig,
+    map: &mut Map,
+    player: Option<&Transform>,
+) {
+    let dir = save_dir.world_dir(config);
+    let player = player.map(|transform| PlayerSave {
+        translation: transform.translation,
+        rotation: transform.rotation,
+    });
+    match map.save(&dir, player) {
+        Ok(()) => info!("Saved world to {}", dir.display()),
+        Err(e) => error!("Failed to save {}: {}", dir.display(), e),
+    }
+}
+
+/// Saves when F5 is pressed or a `SaveWorld` is sent
+fn save_world(
+    actions: Actions,
+    mut requests: EventReader<SaveWorld>,
+    save_dir: Res<SaveDir>,
+    config: Res<WorldConfig>,
+    mut map: ResMut<Map>,
+    players: Query<&Transform, With<Player>>,
+) {
+    let requested = !requests.is_empty();
+    requests.clear();
+    if actions.just_pressed(Action::Save) || requested {
+        write_world(&save_dir, &config, &mut map, players.iter().next());
+    }
+}
+
+/// Saves once more before the app closes
+fn save_on_exit(
+    exit: EventReader<AppExit>,
+    save_dir: Res<SaveDir>,
+    config: Res<WorldConfig>,
+    mut map: ResMut<Map>,
+    players: Query<&Transform, With<Player>>,
+) {
+    if !exit.is_empty() {
+        write_world(&save_dir, &config, &mut map, players.iter().next());
+    }
+}
+
+/// World persistence
+pub struct SavePlugin;
+impl Plugin for SavePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SaveDir>()
+            .add_event::<SaveWorld>()
+            .add_system(check_save_dir.on_startup())
+            // After Startup, so the player has been spawned.
+            .add_startup_system(load_world.in_base_set(StartupSet::PostStartup))
+            .add_system(unbury_player.in_schedule(OnExit(GameState::Loading)))
+            .add_system(save_world)
+            // AppExit is sent in PostUpdate when the window closes, so look for it after that.
+            .add_system(save_on_exit.in_base_set(CoreSet::Last));
+    }
+}
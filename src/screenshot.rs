@@ -0,0 +1,263 @@
+use bevy::pbr::FogSettings;
+use bevy::prelude::*;
+use bevy::render::camera::RenderTarget;
+use bevy::render::extract_component::{ExtractComponent, ExtractComponentPlugin};
+use bevy::render::render_asset::RenderAssets;
+use bevy::render::render_resource::{
+    BufferDescriptor, BufferUsages, CommandEncoderDescriptor, Extent3d, ImageCopyBuffer,
+    ImageDataLayout, MapMode, TextureDimension, TextureFormat, TextureUsages,
+};
+use bevy::render::renderer::{render_system, RenderDevice, RenderQueue};
+use bevy::render::{RenderApp, RenderSet};
+use bevy::tasks::IoTaskPool;
+use bevy::window::PrimaryWindow;
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{self, BufWriter};
+use std::num::NonZeroU32;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use wgpu::{Maintain, COPY_BYTES_PER_ROW_ALIGNMENT};
+
+use crate::cam::FlyCam;
+use crate::controls::{Action, Actions};
+
+const SCREENSHOT_DIR: &str = "screenshots";
+
+/// Renders a single frame into `image` from the player's point of view. Bevy can't read
+/// back the window itself, so screenshots come from this camera, which goes away once
+/// its frame has been copied out.
+#[derive(Component, Clone)]
+struct ScreenshotCamera {
+    image: Handle<Image>,
+}
+
+impl ExtractComponent for ScreenshotCamera {
+    type Query = &'static Self;
+    type Filter = ();
+    type Out = Self;
+
+    fn extract_component(item: &Self) -> Option<Self> {
+        Some(item.clone())
+    }
+}
+
+/// A frame read back from the GPU, tightly packed RGBA
+struct Capture {
+    image: Handle<Image>,
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+/// Shared by the main and render worlds, the render world fills it as frames are read back
+#[derive(Resource, Clone, Default)]
+struct Captures(Arc<Mutex<Vec<Capture>>>);
+
+/// Starts a screenshot when F2 is pressed, one at a time
+fn take_screenshot(
+    mut commands: Commands,
+    actions: Actions,
+    primary_window: Query<&Window, With<PrimaryWindow>>,
+    players: Query<(Entity, &Projection, Option<&FogSettings>), With<FlyCam>>,
+    pending: Query<(), With<ScreenshotCamera>>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    if !actions.just_pressed(Action::Screenshot) || !pending.is_empty() {
+        return;
+    }
+    let Ok(window) = primary_window.get_single() else {
+        warn!("Primary window not found for `take_screenshot`!");
+        return;
+    };
+
+    let size = Extent3d {
+        width: window.physical_width().max(1),
+        height: window.physical_height().max(1),
+        depth_or_array_layers: 1,
+    };
+    let mut image = Image::new_fill(
+        size,
+        TextureDimension::D2,
+        &[0, 0, 0, 255],
+        TextureFormat::Rgba8UnormSrgb,
+    );
+    image.texture_descriptor.usage |= TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_SRC;
+    let image = images.add(image);
+
+    for (player, projection, fog) in players.iter() {
+        // A child of the player, so it sees exactly what they see.
+        let mut camera = commands.spawn((
+            Camera3dBundle {
+                camera: Camera {
+                    target: RenderTarget::Image(image.clone()),
+                    order: -1,
+                    ..default()
+                },
+                projection: projection.clone(),
+                ..default()
+            },
+            UiCameraConfig { show_ui: false },
+            ScreenshotCamera {
+                image: image.clone(),
+            },
+        ));
+        if let Some(fog) = fog {
+            camera.insert(fog.clone());
+        }
+        let camera = camera.id();
+        commands.entity(player).add_child(camera);
+    }
+}
+
+/// Copies the frames of screenshot cameras out of their images, once each. Runs right
+/// after rendering and waits for the GPU, which only costs the one frame.
+fn copy_screenshots(
+    cameras: Query<&ScreenshotCamera>,
+    images: Res<RenderAssets<Image>>,
+    device: Res<RenderDevice>,
+    queue: Res<RenderQueue>,
+    captures: Res<Captures>,
+    mut copied: Local<HashSet<Handle<Image>>>,
+) {
+    copied.retain(|image| cameras.iter().any(|camera| camera.image == *image));
+
+    for camera in cameras.iter() {
+        if copied.contains(&camera.image) {
+            continue;
+        }
+        // Not on the GPU yet, so nothing was rendered into it either.
+        let Some(gpu_image) = images.get(&camera.image) else {
+            continue;
+        };
+
+        let (width, height) = (gpu_image.size.x as u32, gpu_image.size.y as u32);
+        let row_bytes = width * 4;
+        let padded_row_bytes =
+            row_bytes.div_ceil(COPY_BYTES_PER_ROW_ALIGNMENT) * COPY_BYTES_PER_ROW_ALIGNMENT;
+
+        let buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("screenshot_buffer"),
+            size: (padded_row_bytes * height) as u64,
+            usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor::default());
+        encoder.copy_texture_to_buffer(
+            gpu_image.texture.as_image_copy(),
+            ImageCopyBuffer {
+                buffer: &buffer,
+                layout: ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: NonZeroU32::new(padded_row_bytes),
+                    rows_per_image: None,
+                },
+            },
+            Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+        queue.submit([encoder.finish()]);
+
+        let slice = buffer.slice(..);
+        let (sender, receiver) = mpsc::channel();
+        device.map_buffer(&slice, MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        device.poll(Maintain::Wait);
+        copied.insert(camera.image.clone());
+
+        if let Ok(Ok(())) = receiver.recv() {
+            // Rows are padded out to the copy alignment, drop the padding.
+            let pixels = slice
+                .get_mapped_range()
+                .chunks(padded_row_bytes as usize)
+                .flat_map(|row| &row[..row_bytes as usize])
+                .copied()
+                .collect();
+            buffer.unmap();
+
+            captures.0.lock().unwrap().push(Capture {
+                image: camera.image.clone(),
+                width,
+                height,
+                pixels,
+            });
+        } else {
+            error!("Couldn't read the screenshot back from the GPU");
+        }
+    }
+}
+
+/// Where a screenshot taken now goes, named after the time
+fn screenshot_path() -> PathBuf {
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_millis())
+        .unwrap_or_default();
+    Path::new(SCREENSHOT_DIR).join(format!("screenshot_{}.png", millis))
+}
+
+fn write_png(path: &Path, capture: &Capture) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut encoder = png::Encoder::new(
+        BufWriter::new(File::create(path)?),
+        capture.width,
+        capture.height,
+    );
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_source_srgb(png::SrgbRenderingIntent::Perceptual);
+    encoder.write_header()?.write_image_data(&capture.pixels)?;
+    Ok(())
+}
+
+/// Removes the cameras of finished screenshots and writes them out in the background
+fn save_screenshots(
+    mut commands: Commands,
+    captures: Res<Captures>,
+    cameras: Query<(Entity, &ScreenshotCamera)>,
+) {
+    for capture in captures.0.lock().unwrap().drain(..) {
+        for (entity, camera) in cameras.iter() {
+            if camera.image == capture.image {
+                commands.entity(entity).despawn_recursive();
+            }
+        }
+
+        let path = screenshot_path();
+        IoTaskPool::get()
+            .spawn(async move {
+                match write_png(&path, &capture) {
+                    Ok(()) => info!("Saved screenshot to {}", path.display()),
+                    Err(e) => error!("Failed to save {}: {}", path.display(), e),
+                }
+            })
+            .detach();
+    }
+}
+
+/// F2 saves a screenshot to `SCREENSHOT_DIR`
+pub struct ScreenshotPlugin;
+impl Plugin for ScreenshotPlugin {
+    fn build(&self, app: &mut App) {
+        let captures = Captures::default();
+        app.insert_resource(captures.clone())
+            .add_plugin(ExtractComponentPlugin::<ScreenshotCamera>::default())
+            .add_system(take_screenshot)
+            .add_system(save_screenshots);
+
+        if let Ok(render_app) = app.get_sub_app_mut(RenderApp) {
+            render_app.insert_resource(captures).add_system(
+                copy_screenshots
+                    .in_set(RenderSet::Render)
+                    .after(render_system),
+            );
+        }
+    }
+}
@@ -0,0 +1,165 @@
+use bevy::prelude::*;
+use bevy::window::{PresentMode, PrimaryWindow, WindowMode, WindowResolution};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{self, BufReader};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::controls::{Action, Actions};
+
+const SETTINGS_FILE: &str = "settings.ron";
+const RESOLUTIONS: [(u32, u32); 5] = [
+    (1280, 720),
+    (1440, 1080), // 4:3
+    (1600, 900),
+    (1920, 1080),
+    (2560, 1440),
+];
+const FRAMERATE_LIMITS: [Option<u32>; 5] = [None, Some(30), Some(60), Some(120), Some(144)];
+
+/// Frames per second to stay under, to save power. `None` runs as fast as the present
+/// mode allows, which without VSync is as fast as it can.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub struct FramerateLimit(pub Option<u32>);
+
+impl FramerateLimit {
+    /// The next of `FRAMERATE_LIMITS`, wrapping around at the end
+    pub fn next(self) -> Self {
+        let current = FRAMERATE_LIMITS.iter().position(|&limit| limit == self.0);
+        Self(FRAMERATE_LIMITS[current.map_or(0, |i| (i + 1) % FRAMERATE_LIMITS.len())])
+    }
+}
+
+/// How the window is shown. Changed from the settings menu and F11, and kept in
+/// `SETTINGS_FILE` between runs.
+#[derive(Resource, Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VideoSettings {
+    pub fullscreen: bool, // Borderless, at the monitor's resolution
+    pub resolution: (u32, u32),
+    pub vsync: bool,
+    pub framerate_limit: FramerateLimit,
+}
+
+impl Default for VideoSettings {
+    fn default() -> Self {
+        Self {
+            fullscreen: false,
+            resolution: RESOLUTIONS[1],
+            vsync: true,
+            framerate_limit: FramerateLimit::default(),
+        }
+    }
+}
+
+impl VideoSettings {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let file = BufReader::new(File::open(path)?);
+        ron::de::from_reader(file).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let text = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        fs::write(path, text)
+    }
+
+    /// The settings file if there is one, or the defaults
+    pub fn load_or_default() -> Self {
+        match Self::load(Path::new(SETTINGS_FILE)) {
+            Ok(settings) => settings,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Self::default(),
+            Err(e) => {
+                error!(
+                    "Couldn't load {}, using the default settings: {}",
+                    SETTINGS_FILE, e
+                );
+                Self::default()
+            }
+        }
+    }
+
+    pub fn mode(&self) -> WindowMode {
+        if self.fullscreen {
+            WindowMode::BorderlessFullscreen
+        } else {
+            WindowMode::Windowed
+        }
+    }
+
+    pub fn present_mode(&self) -> PresentMode {
+        if self.vsync {
+            PresentMode::AutoVsync
+        } else {
+            PresentMode::AutoNoVsync
+        }
+    }
+
+    pub fn window_resolution(&self) -> WindowResolution {
+        WindowResolution::new(self.resolution.0 as f32, self.resolution.1 as f32)
+    }
+
+    /// Moves on to the next of `RESOLUTIONS`, wrapping around at the end
+    pub fn next_resolution(&mut self) {
+        let current = RESOLUTIONS.iter().position(|&r| r == self.resolution);
+        self.resolution = RESOLUTIONS[current.map_or(0, |i| (i + 1) % RESOLUTIONS.len())];
+    }
+
+    fn apply(&self, window: &mut Window) {
+        window.mode = self.mode();
+        window.present_mode = self.present_mode();
+        window
+            .resolution
+            .set(self.resolution.0 as f32, self.resolution.1 as f32);
+    }
+}
+
+fn toggle_fullscreen(actions: Actions, mut settings: ResMut<VideoSettings>) {
+    if actions.just_pressed(Action::ToggleFullscreen) {
+        settings.fullscreen = !settings.fullscreen;
+    }
+}
+
+/// Puts changed settings into effect on the window and writes them out. The window is
+/// created with them already, so there's nothing to do when they're first added.
+fn apply_video_settings(
+    settings: Res<VideoSettings>,
+    mut primary_window: Query<&mut Window, With<PrimaryWindow>>,
+) {
+    if settings.is_added() {
+        return;
+    }
+    if let Ok(mut window) = primary_window.get_single_mut() {
+        settings.apply(&mut window);
+    }
+    if let Err(e) = settings.save(Path::new(SETTINGS_FILE)) {
+        error!("Couldn't save {}: {}", SETTINGS_FILE, e);
+    }
+}
+
+/// Sleeps off whatever is left of the frame's share of a second under the framerate
+/// limit, timed from when this last finished
+fn limit_framerate(settings: Res<VideoSettings>, mut frame_start: Local<Option<Instant>>) {
+    if let (Some(fps), Some(start)) = (settings.framerate_limit.0, *frame_start) {
+        let frame = Duration::from_secs_f64(1.0 / f64::from(fps));
+        if let Some(remaining) = frame.checked_sub(start.elapsed()) {
+            std::thread::sleep(remaining);
+        }
+    }
+    *frame_start = Some(Instant::now());
+}
+
+/// Fullscreen, resolution, VSync and framerate limit, applied as they change
+pub struct SettingsPlugin;
+impl Plugin for SettingsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(toggle_fullscreen)
+            .add_system(
+                apply_video_settings
+                    .after(toggle_fullscreen)
+                    .run_if(resource_changed::<VideoSettings>()),
+            )
+            .add_system(limit_framerate.in_base_set(CoreSet::Last));
+    }
+}
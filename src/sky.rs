@@ -0,0 +1,461 @@
+use bevy::pbr::{FogFalloff, FogSettings, MaterialPipeline, MaterialPipelineKey};
+use bevy::pbr::{NotShadowCaster, NotShadowReceiver};
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use bevy::render::mesh::MeshVertexBufferLayout;
+use bevy::render::render_resource::{
+    AddressMode, AsBindGroup, Extent3d, RenderPipelineDescriptor, SamplerDescriptor, ShaderRef,
+    SpecializedMeshPipelineError, TextureDimension, TextureFormat,
+};
+use bevy::render::texture::ImageSampler;
+use std::f32::consts::TAU;
+
+use crate::cam::FlyCam;
+use crate::controls::{Action, Actions};
+use crate::menu::GameState;
+use crate::water::Underwater;
+use crate::weather::Weather;
+use crate::world::{Daylight, RenderDistance, CHUNK_SIZE, MAX_LIGHT};
+
+const DAY_LENGTH: f32 = 600.0; // Seconds for a full day and night
+const DAY_ILLUMINANCE: f32 = 10000.0;
+const NIGHT_ILLUMINANCE: f32 = 50.0;
+const DAY_AMBIENT: f32 = 0.3;
+const NIGHT_AMBIENT: f32 = 0.02;
+const DAY_SKY: Color = Color::rgb(0.5, 0.7, 1.0);
+const NIGHT_SKY: Color = Color::rgb(0.01, 0.01, 0.05);
+const DAY_ZENITH: Color = Color::rgb(0.2, 0.4, 0.9);
+const NIGHT_ZENITH: Color = Color::rgb(0.0, 0.0, 0.02);
+const SUN_COLOR: Color = Color::rgb(1.0, 0.95, 0.8);
+const SUN_SIZE: f32 = 0.05; // Angular radius of the disc, in radians
+const SKY_SHADER: &str = "../resources/sky.wgsl";
+const SKY_RADIUS: f32 = 800.0; // Past the fog, inside the camera's far plane
+const CLOUD_HEIGHT: f32 = 80.0; // In blocks, above the peaks of the default terrain
+const CLOUD_EXTENT: f32 = 512.0; // Width of the cloud plane, in blocks
+const CLOUD_SCALE: f32 = 256.0; // Blocks covered by one repeat of the cloud texture
+const CLOUD_TEXTURE_SIZE: u32 = 128; // In pixels
+const CLOUD_WIND: Vec2 = Vec2::new(1.5, 0.5); // Blocks per second the clouds drift
+const CLOUD_COVER: f32 = 0.55; // Noise level where clouds start, higher is clearer skies
+const NIGHT_CLOUDS: f32 = 0.1; // Cloud brightness at night, 1.0 at noon
+const SPEEDS: [f32; 3] = [1.0, 10.0, 100.0]; // Cycled through with Y
+const UNDERWATER_FOG: Color = Color::rgb(0.05, 0.25, 0.35);
+const UNDERWATER_FOG_START: f32 = 0.0; // In blocks
+const UNDERWATER_FOG_END: f32 = 12.0;
+const NIGHT_SKY_LIGHT: u8 = 6; // Sky light level on blocks at night
+
+/// Where the fog starts and where it hides everything, as fractions of the render distance
+#[derive(Resource)]
+pub struct FogConfig {
+    pub start: f32,
+    pub end: f32,
+}
+
+impl Default for FogConfig {
+    fn default() -> Self {
+        Self {
+            start: 0.5,
+            // Loading is counted from the player's chunk, so the loaded edge can be up to
+            // a chunk closer than the full distance.
+            end: 0.8,
+        }
+    }
+}
+
+/// Marks the directional light that plays the sun
+#[derive(Component)]
+pub struct Sun;
+
+/// How far through the day it is. 0.0 is sunrise, 0.25 noon, 0.5 sunset and 0.75 midnight.
+#[derive(Resource)]
+pub struct TimeOfDay {
+    pub fraction: f32,
+    pub day_length: f32, // In seconds
+    pub speed: f32,      // Multiplier for testing, 0.0 pauses
+}
+
+impl Default for TimeOfDay {
+    fn default() -> Self {
+        Self {
+            fraction: 0.2,
+            day_length: DAY_LENGTH,
+            speed: 1.0,
+        }
+    }
+}
+
+impl TimeOfDay {
+    /// How high the sun is, in radians above the eastern horizon.
+    fn sun_angle(&self) -> f32 {
+        self.fraction * TAU
+    }
+
+    /// 1.0 at noon, 0.0 while the sun is below the horizon.
+    pub fn daylight(&self) -> f32 {
+        self.sun_angle().sin().max(0.0)
+    }
+
+    /// Sky colour at the horizon for the current time, shared by the clear colour and fog.
+    pub fn sky_color(&self) -> Color {
+        mix_colors(NIGHT_SKY, DAY_SKY, self.daylight())
+    }
+
+    /// Sky colour straight up.
+    fn zenith_color(&self) -> Color {
+        mix_colors(NIGHT_ZENITH, DAY_ZENITH, self.daylight())
+    }
+}
+
+fn mix_colors(from: Color, to: Color, t: f32) -> Color {
+    let (from, to) = (from.as_rgba_f32(), to.as_rgba_f32());
+    Color::rgb(
+        from[0] + (to[0] - from[0]) * t,
+        from[1] + (to[1] - from[1]) * t,
+        from[2] + (to[2] - from[2]) * t,
+    )
+}
+
+fn advance_time(time: Res<Time>, mut time_of_day: ResMut<TimeOfDay>) {
+    let step = time.delta_seconds() * time_of_day.speed / time_of_day.day_length;
+    time_of_day.fraction = (time_of_day.fraction + step).fract();
+}
+
+/// T pauses time, Y cycles how fast it runs
+fn time_controls(actions: Actions, mut time_of_day: ResMut<TimeOfDay>) {
+    if actions.just_pressed(Action::PauseTime) {
+        time_of_day.speed = if time_of_day.speed == 0.0 { 1.0 } else { 0.0 };
+        info!("Time speed: {}", time_of_day.speed);
+    }
+
+    if actions.just_pressed(Action::TimeSpeed) {
+        let next = SPEEDS
+            .iter()
+            .position(|speed| *speed == time_of_day.speed)
+            .map_or(0, |i| (i + 1) % SPEEDS.len());
+        time_of_day.speed = SPEEDS[next];
+        info!("Time speed: {}", time_of_day.speed);
+    }
+}
+
+/// Moves the sun across the sky and dims everything at night and in the rain
+fn update_sky(
+    time_of_day: Res<TimeOfDay>,
+    weather: Res<Weather>,
+    mut clear_color: ResMut<ClearColor>,
+    mut ambient: ResMut<AmbientLight>,
+    mut sun: Query<(&mut Transform, &mut DirectionalLight), With<Sun>>,
+) {
+    let daylight = time_of_day.daylight();
+    let dimming = weather.dimming();
+
+    for (mut transform, mut light) in sun.iter_mut() {
+        // Rising in the east, straight down at noon.
+        transform.rotation = Quat::from_rotation_x(-time_of_day.sun_angle());
+        light.illuminance =
+            (NIGHT_ILLUMINANCE + (DAY_ILLUMINANCE - NIGHT_ILLUMINANCE) * daylight) * dimming;
+    }
+
+    clear_color.0 = time_of_day.sky_color() * dimming;
+    ambient.brightness = (NIGHT_AMBIENT + (DAY_AMBIENT - NIGHT_AMBIENT) * daylight) * dimming;
+}
+
+/// Sets the level open sky lights blocks at, from the night's up to MAX_LIGHT at noon.
+fn update_daylight(time_of_day: Res<TimeOfDay>, mut daylight: ResMut<Daylight>) {
+    let range = (MAX_LIGHT - NIGHT_SKY_LIGHT) as f32;
+    let level = NIGHT_SKY_LIGHT + (time_of_day.daylight() * range).round() as u8;
+
+    if daylight.0 != level {
+        daylight.0 = level;
+    }
+}
+
+// ---------- Sky dome ----------
+
+/// Horizon to zenith gradient with the sun in it, drawn on the inside of a sphere
+#[derive(AsBindGroup, TypeUuid, Clone, Debug)]
+#[uuid = "96ba46a1-9c4e-42a4-968d-ed932f033734"]
+pub struct SkyMaterial {
+    #[uniform(0)]
+    horizon: Color,
+    #[uniform(0)]
+    zenith: Color,
+    #[uniform(0)]
+    sun_color: Color, // The alpha fades the sun out below the horizon
+    #[uniform(0)]
+    sun_direction: Vec3, // Towards the sun
+    #[uniform(0)]
+    sun_size: f32,
+}
+
+impl Material for SkyMaterial {
+    fn fragment_shader() -> ShaderRef {
+        SKY_SHADER.into()
+    }
+
+    // Seen from inside.
+    fn specialize(
+        _pipeline: &MaterialPipeline<Self>,
+        descriptor: &mut RenderPipelineDescriptor,
+        _layout: &MeshVertexBufferLayout,
+        _key: MaterialPipelineKey<Self>,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        descriptor.primitive.cull_mode = None;
+        Ok(())
+    }
+}
+
+/// Marks the sphere the sky is drawn on
+#[derive(Component)]
+struct SkyDome;
+
+fn setup_sky_dome(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<SkyMaterial>>,
+) {
+    commands.spawn((
+        MaterialMeshBundle {
+            mesh: meshes.add(
+                shape::UVSphere {
+                    radius: SKY_RADIUS,
+                    sectors: 32,
+                    stacks: 16,
+                }
+                .into(),
+            ),
+            material: materials.add(SkyMaterial {
+                horizon: DAY_SKY,
+                zenith: DAY_ZENITH,
+                sun_color: SUN_COLOR,
+                sun_direction: Vec3::Y,
+                sun_size: SUN_SIZE,
+            }),
+            ..default()
+        },
+        NotShadowCaster,
+        NotShadowReceiver,
+        SkyDome,
+    ));
+}
+
+/// Keeps the dome around the camera and its colours and sun in step with the time of day
+/// and the weather. Rain clouds hide the sun.
+fn update_sky_dome(
+    time_of_day: Res<TimeOfDay>,
+    weather: Res<Weather>,
+    camera: Query<&Transform, (With<FlyCam>, Without<SkyDome>)>,
+    sun: Query<&Transform, (With<Sun>, Without<SkyDome>)>,
+    mut domes: Query<(&mut Transform, &Handle<SkyMaterial>), With<SkyDome>>,
+    mut materials: ResMut<Assets<SkyMaterial>>,
+) {
+    let Ok(camera) = camera.get_single() else {
+        return;
+    };
+    // The light shines along its forward, so the sun is behind it.
+    let sun_direction = sun.get_single().map_or(Vec3::Y, |sun| sun.back());
+
+    for (mut transform, material) in domes.iter_mut() {
+        transform.translation = camera.translation;
+
+        if let Some(material) = materials.get_mut(material) {
+            material.horizon = time_of_day.sky_color() * weather.dimming();
+            material.zenith = time_of_day.zenith_color() * weather.dimming();
+            material.sun_direction = sun_direction;
+            material
+                .sun_color
+                .set_a(((sun_direction.y + 0.1) / 0.15).clamp(0.0, 1.0) * (1.0 - weather.rain));
+        }
+    }
+}
+
+// ---------- Clouds ----------
+
+/// Marks the cloud plane
+#[derive(Component)]
+struct Clouds;
+
+/// Value noise that repeats every `period` pixels, in 0..1
+fn tiling_noise(x: u32, y: u32, period: u32) -> f32 {
+    let cell = CLOUD_TEXTURE_SIZE / period;
+    let value = |cx: u32, cy: u32| {
+        let mut h =
+            (cx % period).wrapping_mul(374_761_393) ^ (cy % period).wrapping_mul(668_265_263);
+        h = (h ^ (h >> 13)).wrapping_mul(1_274_126_177);
+        (h ^ (h >> 16)) as f32 / u32::MAX as f32
+    };
+    let smooth = |t: f32| t * t * (3.0 - 2.0 * t);
+
+    let (cx, cy) = (x / cell, y / cell);
+    let tx = smooth((x % cell) as f32 / cell as f32);
+    let ty = smooth((y % cell) as f32 / cell as f32);
+    let top = value(cx, cy) + (value(cx + 1, cy) - value(cx, cy)) * tx;
+    let bottom = value(cx, cy + 1) + (value(cx + 1, cy + 1) - value(cx, cy + 1)) * tx;
+    top + (bottom - top) * ty
+}
+
+/// White, with the alpha cut out of a few octaves of tiling noise
+fn cloud_texture() -> Image {
+    let mut data = Vec::with_capacity((CLOUD_TEXTURE_SIZE * CLOUD_TEXTURE_SIZE * 4) as usize);
+    for y in 0..CLOUD_TEXTURE_SIZE {
+        for x in 0..CLOUD_TEXTURE_SIZE {
+            let noise = tiling_noise(x, y, 4) * 0.5
+                + tiling_noise(x, y, 8) * 0.3
+                + tiling_noise(x, y, 16) * 0.2;
+            let alpha = ((noise - CLOUD_COVER) / 0.15).clamp(0.0, 1.0) * 0.85;
+            data.extend([255, 255, 255, (alpha * 255.0) as u8]);
+        }
+    }
+
+    let mut image = Image::new(
+        Extent3d {
+            width: CLOUD_TEXTURE_SIZE,
+            height: CLOUD_TEXTURE_SIZE,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+    );
+    image.sampler_descriptor = ImageSampler::Descriptor(SamplerDescriptor {
+        address_mode_u: AddressMode::Repeat,
+        address_mode_v: AddressMode::Repeat,
+        ..ImageSampler::linear_descriptor()
+    });
+    image
+}
+
+fn setup_clouds(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut images: ResMut<Assets<Image>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    commands.spawn((
+        PbrBundle {
+            mesh: meshes.add(shape::Plane::from_size(CLOUD_EXTENT).into()),
+            material: materials.add(StandardMaterial {
+                base_color_texture: Some(images.add(cloud_texture())),
+                alpha_mode: AlphaMode::Blend,
+                unlit: true,
+                double_sided: true,
+                cull_mode: None,
+                ..default()
+            }),
+            transform: Transform::from_xyz(0.0, CLOUD_HEIGHT, 0.0),
+            ..default()
+        },
+        NotShadowCaster,
+        NotShadowReceiver,
+        Clouds,
+    ));
+}
+
+/// Keeps the cloud plane over the camera, drifts the clouds with the wind by scrolling
+/// the plane's UVs, and dims them at night and in the rain
+#[allow(clippy::type_complexity)]
+fn update_clouds(
+    time: Res<Time>,
+    time_of_day: Res<TimeOfDay>,
+    weather: Res<Weather>,
+    camera: Query<&Transform, (With<FlyCam>, Without<Clouds>)>,
+    mut clouds: Query<(&mut Transform, &Handle<Mesh>, &Handle<StandardMaterial>), With<Clouds>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let Ok(camera) = camera.get_single() else {
+        return;
+    };
+    let wind = CLOUD_WIND * time.elapsed_seconds();
+    let brightness =
+        (NIGHT_CLOUDS + (1.0 - NIGHT_CLOUDS) * time_of_day.daylight()) * weather.dimming();
+
+    for (mut transform, mesh, material) in clouds.iter_mut() {
+        transform.translation.x = camera.translation.x;
+        transform.translation.z = camera.translation.z;
+
+        // The texture stays put in the world as the plane moves, apart from the wind.
+        if let Some(mesh) = meshes.get_mut(mesh) {
+            let uvs: Option<Vec<[f32; 2]>> = mesh
+                .attribute(Mesh::ATTRIBUTE_POSITION)
+                .and_then(|positions| positions.as_float3())
+                .map(|positions| {
+                    positions
+                        .iter()
+                        .map(|[x, _, z]| {
+                            let world =
+                                Vec2::new(transform.translation.x + x, transform.translation.z + z);
+                            ((world - wind) / CLOUD_SCALE).to_array()
+                        })
+                        .collect()
+                });
+            if let Some(uvs) = uvs {
+                mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+            }
+        }
+
+        if let Some(material) = materials.get_mut(material) {
+            material.base_color = Color::rgb(brightness, brightness, brightness);
+        }
+    }
+}
+
+/// Gives every 3D camera fog, `update_fog` fills it in
+fn add_fog(mut commands: Commands, cameras: Query<Entity, (With<Camera3d>, Without<FogSettings>)>) {
+    for camera in cameras.iter() {
+        commands.entity(camera).insert(FogSettings::default());
+    }
+}
+
+/// Fades terrain into the sky colour towards the edge of the render distance, or into
+/// murky water close up when the camera is underwater. Both are darker in the rain.
+fn update_fog(
+    time_of_day: Res<TimeOfDay>,
+    weather: Res<Weather>,
+    config: Res<FogConfig>,
+    render_distance: Res<RenderDistance>,
+    underwater: Res<Underwater>,
+    mut fogs: Query<&mut FogSettings>,
+) {
+    let distance = (CHUNK_SIZE * render_distance.0) as f32;
+
+    for mut fog in fogs.iter_mut() {
+        if underwater.0 {
+            // Darker at night, like everything else.
+            let daylight = 0.2 + 0.8 * time_of_day.daylight();
+            fog.color = UNDERWATER_FOG * daylight * weather.dimming();
+            fog.falloff = FogFalloff::Linear {
+                start: UNDERWATER_FOG_START,
+                end: UNDERWATER_FOG_END,
+            };
+        } else {
+            fog.color = time_of_day.sky_color() * weather.dimming();
+            fog.falloff = FogFalloff::Linear {
+                start: distance * config.start,
+                end: distance * config.end,
+            };
+        }
+    }
+}
+
+/// Day/night cycle, sky and fog
+pub struct SkyPlugin;
+impl Plugin for SkyPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugin(MaterialPlugin::<SkyMaterial>::default())
+            .init_resource::<TimeOfDay>()
+            .init_resource::<FogConfig>()
+            .add_system(setup_sky_dome.on_startup())
+            .add_system(update_sky_dome.after(update_sky))
+            .add_system(setup_clouds.on_startup())
+            .add_system(update_clouds.after(advance_time))
+            .add_system(time_controls.run_if(in_state(GameState::Playing)))
+            .add_system(
+                advance_time
+                    .after(time_controls)
+                    .run_if(in_state(GameState::Playing)),
+            )
+            .add_system(update_sky.after(advance_time))
+            .add_system(update_daylight.after(advance_time))
+            .add_system(add_fog)
+            .add_system(update_fog.after(advance_time));
+    }
+}
@@ -0,0 +1,195 @@
+use bevy::audio::{AddAudioSource, Decodable, Source};
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use std::collections::HashMap;
+use std::f32::consts::TAU;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::edit::{BlockBroken, BlockPlaced};
+use crate::world::BlockType;
+
+const SAMPLE_RATE: u32 = 22050;
+const PLACE_SPEED: f32 = 1.25; // Placing plays the break sound a little higher
+
+/// Volume of sound effects, 0 to 1
+#[derive(Resource)]
+pub struct SoundSettings {
+    pub volume: f32,
+}
+
+impl Default for SoundSettings {
+    fn default() -> Self {
+        Self { volume: 0.5 }
+    }
+}
+
+/// A short mono sound. There are no audio files, these are made up in code when the
+/// game starts.
+#[derive(TypeUuid, Clone)]
+#[uuid = "3bd5a4f8-15fa-4136-96e1-1d1610c3beee"]
+pub struct SoundEffect {
+    samples: Arc<[f32]>,
+}
+
+/// Plays a `SoundEffect` back through rodio
+pub struct SoundEffectDecoder {
+    samples: Arc<[f32]>,
+    next: usize,
+}
+
+impl Iterator for SoundEffectDecoder {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let sample = self.samples.get(self.next).copied();
+        self.next += 1;
+        sample
+    }
+}
+
+impl Source for SoundEffectDecoder {
+    fn current_frame_len(&self) -> Option<usize> {
+        Some(self.samples.len().saturating_sub(self.next))
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        Some(Duration::from_secs_f32(
+            self.samples.len() as f32 / SAMPLE_RATE as f32,
+        ))
+    }
+}
+
+impl Decodable for SoundEffect {
+    type DecoderItem = f32;
+    type Decoder = SoundEffectDecoder;
+
+    fn decoder(&self) -> SoundEffectDecoder {
+        SoundEffectDecoder {
+            samples: self.samples.clone(),
+            next: 0,
+        }
+    }
+}
+
+/// What a block sounds like when it's broken or placed
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+enum Material {
+    Stone,
+    Dirt,
+    Grass,
+    Wood,
+}
+
+impl Material {
+    const ALL: [Material; 4] = [
+        Material::Stone,
+        Material::Dirt,
+        Material::Grass,
+        Material::Wood,
+    ];
+
+    fn of(btype: BlockType) -> Option<Self> {
+        match btype {
+            BlockType::Stone | BlockType::CoalOre | BlockType::IronOre | BlockType::Bedrock => {
+                Some(Material::Stone)
+            }
+            BlockType::Dirt | BlockType::Sand | BlockType::Gravel | BlockType::Snow => {
+                Some(Material::Dirt)
+            }
+            BlockType::Grass | BlockType::Leaves => Some(Material::Grass),
+            BlockType::Wood | BlockType::Torch => Some(Material::Wood),
+            BlockType::Water | BlockType::Air => None,
+        }
+    }
+
+    /// Filtered noise that dies away, with a tone mixed in for the harder materials
+    fn synthesize(self) -> SoundEffect {
+        // Length in seconds, how fast it fades, how much of the noise's highs are kept,
+        // and the pitch and share of the tone.
+        let (length, decay, brightness, pitch, tone_mix) = match self {
+            Material::Stone => (0.12, 40.0, 0.6, 900.0, 0.3),
+            Material::Dirt => (0.2, 20.0, 0.08, 0.0, 0.0),
+            Material::Grass => (0.25, 15.0, 0.35, 0.0, 0.0),
+            Material::Wood => (0.18, 25.0, 0.15, 180.0, 0.6),
+        };
+
+        let mut noise: u32 = 0x9e37_79b9;
+        let mut filtered = 0.0;
+        let mut samples: Vec<f32> = (0..(length * SAMPLE_RATE as f32) as usize)
+            .map(|i| {
+                let t = i as f32 / SAMPLE_RATE as f32;
+                noise ^= noise << 13;
+                noise ^= noise >> 17;
+                noise ^= noise << 5;
+                let white = noise as f32 / u32::MAX as f32 * 2.0 - 1.0;
+                filtered += (white - filtered) * brightness;
+                let tone = (t * pitch * TAU).sin();
+                (filtered * (1.0 - tone_mix) + tone * tone_mix) * (-decay * t).exp()
+            })
+            .collect();
+
+        let peak = samples.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+        if peak > 0.0 {
+            samples.iter_mut().for_each(|s| *s /= peak);
+        }
+        SoundEffect {
+            samples: samples.into(),
+        }
+    }
+}
+
+/// The sound for each material
+#[derive(Resource)]
+struct BlockSounds(HashMap<Material, Handle<SoundEffect>>);
+
+fn setup_sounds(mut commands: Commands, mut effects: ResMut<Assets<SoundEffect>>) {
+    let sounds = Material::ALL
+        .iter()
+        .map(|material| (*material, effects.add(material.synthesize())))
+        .collect();
+    commands.insert_resource(BlockSounds(sounds));
+}
+
+/// Plays the block's sound when one is broken or placed
+fn play_block_sounds(
+    audio: Res<Audio<SoundEffect>>,
+    sounds: Res<BlockSounds>,
+    settings: Res<SoundSettings>,
+    mut broken: EventReader<BlockBroken>,
+    mut placed: EventReader<BlockPlaced>,
+) {
+    let broken = broken.iter().map(|event| (event.btype, 1.0));
+    let placed = placed.iter().map(|event| (event.0, PLACE_SPEED));
+
+    for (btype, speed) in broken.chain(placed) {
+        let Some(sound) = Material::of(btype).and_then(|material| sounds.0.get(&material)) else {
+            continue;
+        };
+        audio.play_with_settings(
+            sound.clone(),
+            PlaybackSettings::ONCE
+                .with_volume(settings.volume)
+                .with_speed(speed),
+        );
+    }
+}
+
+/// Sound effects for editing blocks
+pub struct SoundPlugin;
+impl Plugin for SoundPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_audio_source::<SoundEffect>()
+            .init_resource::<SoundSettings>()
+            .add_system(setup_sounds.on_startup())
+            .add_system(play_block_sounds);
+    }
+}
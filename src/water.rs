@@ -0,0 +1,147 @@
+use bevy::pbr::{MaterialPipeline, MaterialPipelineKey};
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use bevy::render::mesh::MeshVertexBufferLayout;
+use bevy::render::render_resource::{
+    AsBindGroup, RenderPipelineDescriptor, ShaderRef, SpecializedMeshPipelineError,
+};
+
+use crate::cam::FlyCam;
+use crate::chunk_material::add_chunk_attributes;
+use crate::world::{BlockType, Map, LIGHT_FALLOFF};
+
+const WATER_SHADER: &str = "../resources/water.wgsl";
+const WAVE_HEIGHT: f32 = 0.15; // In blocks, how far the surface dips at the bottom of a wave
+const WAVE_SPEED: f32 = 1.5;
+const UNDERWATER_TINT: Color = Color::rgba(0.05, 0.35, 0.4, 0.35);
+const SURFACE_MARGIN: f32 = 0.1; // In blocks, past the surface before switching
+
+/// Whether the camera is in water. Sky's fog reads this too.
+#[derive(Resource, Default)]
+pub struct Underwater(pub bool);
+
+/// Marks the full screen overlay shown while underwater
+#[derive(Component)]
+struct UnderwaterOverlay;
+
+/// Blended water that ripples over time. The waves are worked out in the shader from
+/// the global time, so nothing has to update the material each frame.
+#[derive(AsBindGroup, TypeUuid, Clone, Debug)]
+#[uuid = "e3f6b8d5-6d84-4de2-ab48-e1df222e8bea"]
+pub struct WaterMaterial {
+    #[uniform(0)]
+    pub color: Color, // Multiplies the atlas tile, the alpha sets how see-through it is
+    #[uniform(0)]
+    pub wave_height: f32,
+    #[uniform(0)]
+    pub wave_speed: f32,
+    #[uniform(0)]
+    pub sky_dimming: f32, // Same as `ChunkMaterial`'s
+    #[uniform(0)]
+    pub light_falloff: f32,
+    #[texture(1)]
+    #[sampler(2)]
+    pub texture: Handle<Image>,
+}
+
+impl WaterMaterial {
+    pub fn new(texture: Handle<Image>) -> Self {
+        Self {
+            color: Color::rgba(1.0, 1.0, 1.0, 0.75),
+            wave_height: WAVE_HEIGHT,
+            wave_speed: WAVE_SPEED,
+            sky_dimming: 0.0,
+            light_falloff: LIGHT_FALLOFF,
+            texture,
+        }
+    }
+}
+
+impl Material for WaterMaterial {
+    fn vertex_shader() -> ShaderRef {
+        WATER_SHADER.into()
+    }
+
+    fn fragment_shader() -> ShaderRef {
+        WATER_SHADER.into()
+    }
+
+    fn alpha_mode(&self) -> AlphaMode {
+        AlphaMode::Blend
+    }
+
+    fn specialize(
+        _pipeline: &MaterialPipeline<Self>,
+        descriptor: &mut RenderPipelineDescriptor,
+        layout: &MeshVertexBufferLayout,
+        _key: MaterialPipelineKey<Self>,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        add_chunk_attributes(descriptor, layout)
+    }
+}
+
+fn setup_underwater_overlay(mut commands: Commands) {
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                ..default()
+            },
+            background_color: UNDERWATER_TINT.into(),
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+        UnderwaterOverlay,
+    ));
+}
+
+/// Works out whether the camera is in water. Going in checks a little below the camera
+/// and coming out a little above, so bobbing right at the surface doesn't flicker.
+fn check_underwater(
+    map: Res<Map>,
+    camera: Query<&Transform, With<FlyCam>>,
+    mut underwater: ResMut<Underwater>,
+) {
+    let camera = camera.single();
+    let offset = if underwater.0 {
+        SURFACE_MARGIN
+    } else {
+        -SURFACE_MARGIN
+    };
+    let block = (camera.translation + Vec3::Y * offset).floor().as_ivec3();
+    let submerged = map.get_block(block) == Some(BlockType::Water);
+
+    if underwater.0 != submerged {
+        underwater.0 = submerged;
+    }
+}
+
+fn update_underwater_overlay(
+    underwater: Res<Underwater>,
+    mut overlay: Query<&mut Visibility, With<UnderwaterOverlay>>,
+) {
+    for mut visibility in overlay.iter_mut() {
+        *visibility = if underwater.0 {
+            Visibility::Visible
+        } else {
+            Visibility::Hidden
+        };
+    }
+}
+
+/// The animated water material, and the tint while underwater
+pub struct WaterPlugin;
+impl Plugin for WaterPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugin(MaterialPlugin::<WaterMaterial>::default())
+            .init_resource::<Underwater>()
+            .add_system(setup_underwater_overlay.on_startup())
+            .add_system(check_underwater)
+            .add_system(
+                update_underwater_overlay
+                    .after(check_underwater)
+                    .run_if(resource_changed::<Underwater>()),
+            );
+    }
+}
@@ -0,0 +1,206 @@
+use bevy::pbr::NotShadowCaster;
+use bevy::prelude::*;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::cam::FlyCam;
+use crate::menu::GameState;
+use crate::world::{Map, WorldConfig};
+
+const CLEAR_SPELL: (f32, f32) = (120.0, 600.0); // Range of seconds a clear spell lasts
+const RAIN_SPELL: (f32, f32) = (60.0, 240.0); // And a rainy one
+const RAIN_FADE: f32 = 0.1; // How quickly rain starts and stops, per second
+const RAIN_DARKENING: f32 = 0.35; // Share of the light taken away by heavy rain
+const RAIN_RATE: f32 = 400.0; // Drops spawned per second in heavy rain
+const RAIN_RADIUS: f32 = 16.0; // In blocks, around the camera
+const RAIN_HEIGHT: f32 = 16.0; // How far above the camera drops start
+const RAIN_SPEED: f32 = 20.0; // Blocks per second
+const RAIN_LIFETIME: f32 = 2.0; // Seconds
+
+/// Sunny or raining
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum WeatherKind {
+    #[default]
+    Clear,
+    Rain,
+}
+
+impl WeatherKind {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "clear" => Some(WeatherKind::Clear),
+            "rain" => Some(WeatherKind::Rain),
+            _ => None,
+        }
+    }
+}
+
+/// The current weather, and when it changes next. Changes come from an rng seeded with
+/// the world's seed, so a world always starts with the same weather.
+#[derive(Resource)]
+pub struct Weather {
+    pub kind: WeatherKind,
+    pub rain: f32,    // Eases towards 1 while raining and back to 0 when it clears
+    next_change: f32, // Seconds
+    rng: StdRng,
+}
+
+impl FromWorld for Weather {
+    fn from_world(world: &mut World) -> Self {
+        let seed = world
+            .get_resource::<WorldConfig>()
+            .map_or(0, |config| config.seed);
+        let mut weather = Self {
+            kind: WeatherKind::Clear,
+            rain: 0.0,
+            next_change: 0.0,
+            rng: StdRng::seed_from_u64(u64::from(seed)),
+        };
+        weather.set(WeatherKind::Clear);
+        weather
+    }
+}
+
+impl Weather {
+    /// Switches to `kind` for a whole spell of it
+    pub fn set(&mut self, kind: WeatherKind) {
+        let (min, max) = match kind {
+            WeatherKind::Clear => CLEAR_SPELL,
+            WeatherKind::Rain => RAIN_SPELL,
+        };
+        self.kind = kind;
+        self.next_change = self.rng.gen_range(min..max);
+    }
+
+    /// How much of the light gets through the clouds, 1.0 when clear
+    pub fn dimming(&self) -> f32 {
+        1.0 - RAIN_DARKENING * self.rain
+    }
+}
+
+/// A falling rain drop
+#[derive(Component)]
+struct RainDrop {
+    age: f32,
+}
+
+/// The streak every drop is drawn with
+#[derive(Resource)]
+struct RainAssets {
+    mesh: Handle<Mesh>,
+    material: Handle<StandardMaterial>,
+}
+
+fn setup_rain(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    commands.insert_resource(RainAssets {
+        mesh: meshes.add(shape::Box::new(0.02, 0.5, 0.02).into()),
+        material: materials.add(StandardMaterial {
+            base_color: Color::rgba(0.6, 0.7, 0.9, 0.5),
+            alpha_mode: AlphaMode::Blend,
+            unlit: true,
+            ..default()
+        }),
+    });
+}
+
+/// Counts down to the next change of weather and eases the rain in or out
+fn update_weather(time: Res<Time>, mut weather: ResMut<Weather>) {
+    let delta = time.delta_seconds();
+
+    weather.next_change -= delta;
+    if weather.next_change <= 0.0 {
+        let next = match weather.kind {
+            WeatherKind::Clear => WeatherKind::Rain,
+            WeatherKind::Rain => WeatherKind::Clear,
+        };
+        weather.set(next);
+        info!("Weather: {:?}", next);
+    }
+
+    let target = match weather.kind {
+        WeatherKind::Clear => 0.0,
+        WeatherKind::Rain => 1.0,
+    };
+    let step = RAIN_FADE * delta;
+    weather.rain = (weather.rain + (target - weather.rain).clamp(-step, step)).clamp(0.0, 1.0);
+}
+
+/// Drops rain in a cylinder around the camera, more the harder it rains
+fn spawn_rain(
+    mut commands: Commands,
+    time: Res<Time>,
+    weather: Res<Weather>,
+    assets: Res<RainAssets>,
+    camera: Query<&Transform, With<FlyCam>>,
+) {
+    if weather.rain <= 0.0 {
+        return;
+    }
+    let Ok(camera) = camera.get_single() else {
+        return;
+    };
+
+    let mut rng = rand::thread_rng();
+    let expected = RAIN_RATE * weather.rain * time.delta_seconds();
+    let count = expected as u32 + rng.gen_bool(expected.fract() as f64) as u32;
+
+    for _ in 0..count {
+        let offset = Vec2::from_angle(rng.gen_range(0.0..std::f32::consts::TAU))
+            * RAIN_RADIUS
+            * rng.gen::<f32>().sqrt();
+        let height = rng.gen_range(0.0..RAIN_HEIGHT);
+        commands.spawn((
+            PbrBundle {
+                mesh: assets.mesh.clone(),
+                material: assets.material.clone(),
+                transform: Transform::from_translation(
+                    camera.translation + Vec3::new(offset.x, height, offset.y),
+                ),
+                ..default()
+            },
+            NotShadowCaster,
+            RainDrop { age: 0.0 },
+        ));
+    }
+}
+
+/// Moves drops down, removing them when they reach the ground or get old
+fn fall_rain(
+    mut commands: Commands,
+    time: Res<Time>,
+    map: Res<Map>,
+    mut drops: Query<(Entity, &mut Transform, &mut RainDrop)>,
+) {
+    let delta = time.delta_seconds();
+
+    for (entity, mut transform, mut drop) in drops.iter_mut() {
+        drop.age += delta;
+        transform.translation.y -= RAIN_SPEED * delta;
+
+        let pos = transform.translation.floor().as_ivec3();
+        let ground = map.surface_height(pos.x, pos.z);
+        if drop.age >= RAIN_LIFETIME || pos.y <= ground {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+/// Rain that comes and goes
+pub struct WeatherPlugin;
+impl Plugin for WeatherPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Weather>()
+            .add_system(setup_rain.on_startup())
+            .add_system(update_weather.run_if(in_state(GameState::Playing)))
+            .add_system(
+                spawn_rain
+                    .after(update_weather)
+                    .run_if(in_state(GameState::Playing)),
+            )
+            .add_system(fall_rain.run_if(in_state(GameState::Playing)));
+    }
+}
@@ -1,559 +1,4232 @@
 use bevy::prelude::*;
 use bevy::render::mesh::Indices;
-use bevy::render::render_resource::{PrimitiveTopology, Texture};
+use bevy::render::primitives::{Aabb, Frustum};
+use bevy::render::render_resource::{Extent3d, PrimitiveTopology, TextureDimension, TextureFormat};
+use bevy::render::texture::ImageSampler;
+use bevy::tasks::{AsyncComputeTaskPool, Task};
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use futures_lite::future;
 // use bevy_flycam::FlyCam;
 use cam::*;
-use noise::utils::{NoiseMap, NoiseMapBuilder, PlaneMapBuilder};
-use noise::{Fbm, Perlin};
+use noise::{Fbm, MultiFractal, NoiseFn, Perlin, ScaleBias};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use rayon::prelude::*;
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs::{self, File};
 use std::hash::Hash;
-use std::sync::{Arc, Mutex};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 use crate::cam;
+use crate::chunk_material::{ChunkMaterial, ATTRIBUTE_LIGHT, ATTRIBUTE_TILE};
+use crate::menu::GameState;
+use crate::water::WaterMaterial;
 
-const CHUNK_SIZE: i32 = 32;
+pub const CHUNK_SIZE: i32 = 32; // Along x and z
+pub const CHUNK_HEIGHT: i32 = 128; // Chunks are whole columns, so this is the world's height too
+pub const CHUNK_EXTENT: IVec3 = IVec3::new(CHUNK_SIZE, CHUNK_HEIGHT, CHUNK_SIZE);
+const CHUNK_VOLUME: usize = (CHUNK_SIZE * CHUNK_SIZE * CHUNK_HEIGHT) as usize;
 const SEED: u32 = 14;
 const BLOCK_SIZE: Vec3 = Vec3::new(1.0, 1.0, 1.0);
-const RENDER_DISTANCE: i32 = 3; // In chunks
-const WATER_LEVEL: i32 = 7;
+// Faces are always in this order: front, back, left, right, top, bottom.
+const FACE_NORMALS: [IVec3; 6] = [
+    IVec3::Z,
+    IVec3::NEG_Z,
+    IVec3::NEG_X,
+    IVec3::X,
+    IVec3::Y,
+    IVec3::NEG_Y,
+];
+// Which way a face's texture U and V run in the world, matching `box_corners`' UVs.
+// Images have V pointing down, so it runs down the sides.
+const FACE_TEXTURE_AXES: [(Vec3, Vec3); 6] = [
+    (Vec3::X, Vec3::NEG_Y),
+    (Vec3::NEG_X, Vec3::NEG_Y),
+    (Vec3::Z, Vec3::NEG_Y),
+    (Vec3::NEG_Z, Vec3::NEG_Y),
+    (Vec3::X, Vec3::Z),
+    (Vec3::X, Vec3::NEG_Z),
+];
+const TILE_INSET: f32 = 0.5; // Texels trimmed off each edge of a tile, so faces never sample the next one
+const CHUNK_SIDES: [IVec2; 4] = [IVec2::X, IVec2::NEG_X, IVec2::Y, IVec2::NEG_Y];
+const AO_BRIGHTNESS: [f32; 4] = [0.45, 0.65, 0.85, 1.0]; // By occlusion level, 0 is boxed in
+const RENDER_DISTANCE: i32 = 3; // Default radius, in chunks
+const CACHE_SIZE: usize = 64; // Default number of out of range chunks kept with their meshes
+const MAX_HEIGHT: f64 = 64.0; // Terrain height where the noise peaks, in blocks
+const NOISE_SCALE: f64 = 10.0 / 1024.0; // Height noise per block, the spacing the old 1024 wide map had
+const HEIGHT_MAP_IMAGE_SIZE: usize = 256; // In pixels, see `Map::height_map_image`
+const HEIGHT_MAP_IMAGE_STEP: i32 = 4; // Blocks per pixel
+const WATER_LEVEL: i32 = 22; // Default top of the sea, see `WorldConfig::water_level`
+const BEACH_WIDTH: f64 = 1.0; // How far above / below the water level sand reaches
+const SNOW_LINE: f64 = 48.0; // Default height above which the surface is snow, in blocks
+const SNOW_LINE_JITTER: f64 = 1.5; // How far the snow line wanders up or down per column
+const DIRT_DEPTH: i32 = 3; // Layers of dirt under the grass, with stone below them
+const SAND_DEPTH: i32 = 4; // Layers of sand on beaches and in deserts
+const BEDROCK_HEIGHT: i32 = 2; // The bottom layer is all bedrock, the ones above are patchy
+const ORES: [(BlockType, u32, u32); 2] = [
+    // (ore, veins per chunk, blocks per vein)
+    (BlockType::CoalOre, 40, 8),
+    (BlockType::IronOre, 20, 4),
+];
+const FLAT_HEIGHT: i32 = WATER_LEVEL + 2; // Flat worlds are solid below this and empty from it up
+const FLAT_LAYERS: [(BlockType, i32); 3] = [
+    // Top down, with the thickness of each. Anything below them is bedrock.
+    (BlockType::Grass, 1),
+    (BlockType::Dirt, DIRT_DEPTH),
+    (
+        BlockType::Stone,
+        FLAT_HEIGHT - 1 - DIRT_DEPTH - BEDROCK_HEIGHT,
+    ),
+];
+const ISLAND_STEEPNESS: f64 = 4.0; // Default exponent of the island falloff
+const CAVE_FREQUENCY: f64 = 0.05;
+const CAVE_THRESHOLD: f64 = 0.35; // Higher means fewer, narrower caves
+const CAVE_FLOOR: i32 = 1; // Layers at the bottom of the world that are never carved
+const BLOCK_TICK: f32 = 0.05; // Seconds between falling blocks moving down a block
+const WATER_FLOWS_PER_TICK: usize = 256; // Most queued water cells checked each BlockTick
+const BLOCKS_FILE: &str = "resources/blocks.ron";
+const SAVE_MAGIC: &[u8; 8] = b"MCWORLD1"; // Starts every level file
+const LEVEL_FILE: &str = "level.bin"; // The seed and the player, in a world's save directory
+const REGION_DIR: &str = "regions";
+const REGION_MAGIC: &[u8; 8] = b"MCREGION";
+const REGION_SIZE: i32 = 16; // Chunks along each side of a region file
+const REGION_SLOTS: usize = (REGION_SIZE * REGION_SIZE) as usize;
+const CLIMATE_FREQUENCY: f64 = 0.004; // Low, so biomes are a few hundred blocks across
+const BIOME_BLEND: i32 = 3; // Blocks over which biome borders are mixed
+pub const MAX_LIGHT: u8 = 15; // Open sky. Light loses a level for every block it spreads
+pub const LIGHT_FALLOFF: f32 = 0.85; // Brightness multiplier for each level below MAX_LIGHT
+const LIGHT_COLOR: Color = Color::rgb(1.0, 0.8, 0.5); // Of the point lights on light blocks
+const LIGHT_INTENSITY: f32 = 300.0; // In lumens
+const LOD_DISTANCE: i32 = 4; // Default, in chunks. Beyond it chunks are meshed at half resolution
+const MAX_LOD: u8 = 2; // Halvings of the resolution, so the coarsest blocks are 4 wide
+const CULL_MARGIN: f32 = MAX_LIGHT as f32; // Blocks, so lights just out of view still shine in
 
 // ---------- Block ----------
-#[derive(Component, Clone, PartialEq, Eq, Hash, Debug)]
-pub struct Block {
-    mesh: Handle<Mesh>,
-    btype: BlockType,
-}
-
-impl Block {
-    fn new(btype: BlockType) -> Self {
-        Self {
-            mesh: Default::default(),
-            btype,
-        }
-    }
-}
-
-#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
 pub enum BlockType {
     Grass,
     Dirt,
     Stone,
     Water,
+    Sand,
+    Wood,
+    Leaves,
+    CoalOre,
+    IronOre,
+    Snow,
+    Bedrock,
+    Gravel,
+    Torch,
     Air, // Essentially null
 }
 
-impl BlockType {
-    fn get_material(&self) -> StandardMaterial {
-        // Reflectance and perceptual roughness are random. Fix later.
-        match self {
-            BlockType::Grass => StandardMaterial {
-                base_color: Color::hex("91cb7d").unwrap(),
-                reflectance: 0.1,
-                perceptual_roughness: 0.1,
-                ..Default::default()
-            },
-            BlockType::Dirt => StandardMaterial {
-                base_color: Color::hex("9b7653").unwrap(),
-                reflectance: 0.1,
-                perceptual_roughness: 0.1,
-                ..Default::default()
-            },
-            BlockType::Stone => StandardMaterial {
-                base_color: Color::hex("9f9484").unwrap(),
-                reflectance: 0.1,
-                perceptual_roughness: 0.1,
-                ..Default::default()
-            },
-            BlockType::Water => StandardMaterial {
-                base_color: Color::hex("497786BF").unwrap(), // 7F == 0.5 alpha
-                reflectance: 0.2,
-                perceptual_roughness: 0.1,
-                alpha_mode: AlphaMode::Blend,
-                ..Default::default()
-            },
-            BlockType::Air => StandardMaterial {
-                base_color: Color::hex("000000").unwrap(),
-                ..Default::default()
-            },
+/// How a block type looks and behaves, read from `BLOCKS_FILE`
+#[derive(Clone, Debug, Deserialize)]
+pub struct BlockDef {
+    pub faces: [[i32; 2]; 6], // Atlas tile (column, row) per face, in the usual face order
+    color: String,            // Hex, for places that don't use the atlas like the hotbar
+    pub solid: bool,          // Stops rays and movement
+    pub transparent: bool,    // Drawn blended, in the chunk's transparent mesh
+    #[serde(default)]
+    pub cutout: bool, // Solid, but with holes in its texture. Doesn't hide its neighbours
+    #[serde(default)]
+    pub biome_tint: bool, // The top face takes the biome's grass colour
+    #[serde(default = "default_hardness")]
+    pub hardness: f32, // How long it takes to break in survival
+    #[serde(default)]
+    pub unbreakable: bool, // Can't be removed or replaced by the edit tools
+    #[serde(default)]
+    pub falls: bool, // Drops down when there's nothing solid under it, like sand
+    #[serde(default)]
+    pub light: u8, // Light level it gives off, up to MAX_LIGHT
+    #[serde(default)]
+    pub shape: BlockShape,
+    #[serde(default)]
+    pub orientable: bool, // Runs along the axis it's placed against, like logs
+}
+
+/// How a block is meshed
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+pub enum BlockShape {
+    #[default]
+    Cube,
+    Post, // A thin upright in the middle of the block, like a torch. Never culled or merged
+}
+
+/// Which way a block runs. Only orientable blocks are ever anything but `Y`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BlockAxis {
+    X,
+    #[default]
+    Y,
+    Z,
+}
+
+impl BlockAxis {
+    /// The axis a face with this normal points along.
+    pub fn of_normal(normal: IVec3) -> Self {
+        if normal.x != 0 {
+            BlockAxis::X
+        } else if normal.z != 0 {
+            BlockAxis::Z
+        } else {
+            BlockAxis::Y
+        }
+    }
+}
+
+fn default_hardness() -> f32 {
+    1.0
+}
+
+impl BlockDef {
+    /// Whether it hides the faces of the blocks next to it. See-through blocks don't,
+    /// whether they're blended like water or cut out like leaves.
+    fn hides_faces(&self) -> bool {
+        self.solid && !self.cutout
+    }
+
+    /// The atlas tile for a face of the block when it runs along `axis`, and whether the
+    /// tile is turned on its side. Turned blocks put their top and bottom tiles on the
+    /// faces at their ends, and their sides' tiles everywhere else, turned so the grain
+    /// runs along the block.
+    fn face_tile(&self, face: usize, axis: BlockAxis) -> ([i32; 2], bool) {
+        // Faces go front, back, left, right, top, bottom.
+        match (axis, face) {
+            (BlockAxis::Y, _) => (self.faces[face], false),
+            (BlockAxis::X, 2 | 3) => (self.faces[face + 2], false),
+            (BlockAxis::X, 4 | 5) => (self.faces[face - 2], true),
+            (BlockAxis::X, _) => (self.faces[face], true),
+            (BlockAxis::Z, 0 | 1) => (self.faces[face + 4], false),
+            (BlockAxis::Z, 4 | 5) => (self.faces[face - 4], false),
+            (BlockAxis::Z, _) => (self.faces[face], true),
         }
     }
 }
+
+impl BlockDef {
+    pub fn color(&self) -> Color {
+        Color::hex(&self.color).unwrap_or(Color::FUCHSIA)
+    }
+}
+
+/// Every block type's `BlockDef`. Cheap to clone, the generation tasks get their own copy.
+#[derive(Resource, Clone)]
+pub struct BlockRegistry {
+    blocks: Arc<HashMap<BlockType, BlockDef>>,
+}
+
+impl Default for BlockRegistry {
+    fn default() -> Self {
+        Self::load(Path::new(BLOCKS_FILE))
+            .unwrap_or_else(|e| panic!("Couldn't load {}: {}", BLOCKS_FILE, e))
+    }
+}
+
+impl BlockRegistry {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let file = BufReader::new(File::open(path)?);
+        let blocks = ron::de::from_reader(file)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        Ok(Self {
+            blocks: Arc::new(blocks),
+        })
+    }
+
+    pub fn get(&self, btype: &BlockType) -> &BlockDef {
+        self.blocks
+            .get(btype)
+            .unwrap_or_else(|| panic!("{:?} is missing from {}", btype, BLOCKS_FILE))
+    }
+}
 // --------------------------
 
-// ---------- Chunk ----------
-#[derive(Component, Clone)]
-pub struct Chunk {
-    blocks: HashMap<IVec3, Block>,
-    position: IVec2,
+// ---------- Biome ----------
+/// What a column of terrain is like, picked from the temperature and humidity noise
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Biome {
+    Plains,
+    Desert,
+    Snowy,
+    Forest,
 }
 
-impl Chunk {
-    fn new(pos: IVec2) -> Self {
+impl Biome {
+    /// Multiplies the grass texture on top of grass blocks, which is grey.
+    fn grass_tint(&self) -> Vec3 {
+        match self {
+            Biome::Plains => Vec3::new(0.57, 0.74, 0.35),
+            Biome::Desert => Vec3::new(0.75, 0.72, 0.33),
+            Biome::Snowy => Vec3::new(0.5, 0.71, 0.59),
+            Biome::Forest => Vec3::new(0.47, 0.75, 0.35),
+        }
+    }
+
+    /// Percent of grass columns that grow a tree.
+    fn tree_chance(&self) -> u32 {
+        match self {
+            Biome::Plains => 2,
+            Biome::Desert => 0,
+            Biome::Snowy => 1,
+            Biome::Forest => 8,
+        }
+    }
+}
+
+/// Temperature and humidity noise. Every column's biome comes from these.
+pub struct Climate {
+    seed: u32,
+    temperature: Perlin,
+    humidity: Perlin,
+}
+
+impl Climate {
+    pub fn new(seed: u32) -> Self {
         Self {
-            blocks: HashMap::new(),
-            position: pos,
+            seed,
+            temperature: Perlin::new(seed.wrapping_add(2)),
+            humidity: Perlin::new(seed.wrapping_add(3)),
         }
     }
 
-    fn gen_blocks(&mut self, noise: &NoiseMap) {
-        let offset = IVec3::new(self.position.x, 0, self.position.y);
+    /// The biome the noise gives at a point, with hard borders.
+    fn biome_at(&self, x: i32, z: i32) -> Biome {
+        let point = [x as f64 * CLIMATE_FREQUENCY, z as f64 * CLIMATE_FREQUENCY];
+        let temperature = self.temperature.get(point);
+        let humidity = self.humidity.get(point);
 
-        let blocks_mutex = Arc::new(Mutex::new(HashMap::new()));
+        if temperature < -0.25 {
+            Biome::Snowy
+        } else if temperature > 0.25 && humidity < 0.0 {
+            Biome::Desert
+        } else if humidity > 0.1 {
+            Biome::Forest
+        } else {
+            Biome::Plains
+        }
+    }
 
-        // With water
-        (0..CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE)
-            .into_par_iter()
-            .for_each(|i| {
-                let x = i % CHUNK_SIZE;
-                let z = (i / CHUNK_SIZE) % CHUNK_SIZE;
-                let y = i / (CHUNK_SIZE * CHUNK_SIZE);
-                let height = noise.get_value((x + offset.x) as usize, (z + offset.z) as usize)
-                    * CHUNK_SIZE as f64;
+    /// The biome of a block column. Each column looks up a point up to `BIOME_BLEND`
+    /// blocks away, so borders come out ragged instead of as clean lines.
+    pub fn biome(&self, x: i32, z: i32) -> Biome {
+        let hash = column_hash(self.seed.wrapping_add(4), x, z);
+        let range = (BIOME_BLEND * 2 + 1) as u32;
+        let dx = (hash % range) as i32 - BIOME_BLEND;
+        let dz = ((hash >> 16) % range) as i32 - BIOME_BLEND;
+        self.biome_at(x + dx, z + dz)
+    }
 
-                let block_pos = IVec3::new(x, y, z) + offset;
+    /// Grass colour for a block column, averaged over the biomes around it so it fades
+    /// from one to the next.
+    pub fn grass_tint(&self, x: i32, z: i32) -> Vec3 {
+        let mut sum = Vec3::ZERO;
+        for dx in -BIOME_BLEND..=BIOME_BLEND {
+            for dz in -BIOME_BLEND..=BIOME_BLEND {
+                sum += self.biome_at(x + dx, z + dz).grass_tint();
+            }
+        }
+        sum / ((BIOME_BLEND * 2 + 1) * (BIOME_BLEND * 2 + 1)) as f32
+    }
+}
+// ---------------------------
 
-                let mut blocks = blocks_mutex.lock().unwrap();
-
-                if (y as f64) < height.abs() {
-                    let block = if y < 4 {
-                        Block::new(BlockType::Stone)
-                    } else if y < 7 {
-                        Block::new(BlockType::Dirt)
-                    } else {
-                        Block::new(BlockType::Grass)
-                    };
-                    blocks.insert(block_pos, block);
-                } else if y == WATER_LEVEL {
-                    let block = Block::new(BlockType::Water);
-                    blocks.insert(block_pos, block);
-                }
-            });
+/// Cheap deterministic hash of a block column, used for decorations like trees.
+fn column_hash(seed: u32, x: i32, z: i32) -> u32 {
+    let mut hash = seed ^ (x as u32).wrapping_mul(0x27d4eb2d) ^ (z as u32).wrapping_mul(0x165667b1);
+    hash = (hash ^ (hash >> 15)).wrapping_mul(0x85ebca6b);
+    hash ^ (hash >> 13)
+}
 
-        self.blocks
-            .extend(Arc::try_unwrap(blocks_mutex).unwrap().into_inner().unwrap());
+/// The terrain height noise for a seed. Sampled straight from the noise function
+/// wherever it's needed, so it goes on forever in every direction and is the same
+/// whichever order chunks are generated in.
+struct HeightNoise(ScaleBias<f64, Fbm<Perlin>, 3>);
+
+impl HeightNoise {
+    fn new(seed: u32, params: &TerrainParams) -> Self {
+        let fbm = Fbm::<Perlin>::new(seed)
+            .set_octaves(params.octaves)
+            .set_frequency(params.frequency)
+            .set_lacunarity(params.lacunarity)
+            .set_persistence(params.persistence);
+        Self(ScaleBias::new(fbm).set_scale(params.amplitude))
     }
 
-    fn gen_meshes(
+    /// The noise, roughly -1..1, at a point in blocks
+    fn get(&self, x: f64, z: f64) -> f64 {
+        self.0.get([x * NOISE_SCALE, z * NOISE_SCALE, 0.0])
+    }
+}
+
+/// Where a tile (column, row) sits in the atlas, in UV space. Inset by `TILE_INSET`, as
+/// UVs right on the edge between two tiles can sample either.
+fn tile_rect(atlas: &TextureAtlas, tile: [i32; 2]) -> Rect {
+    // Every tile is the same size, so the grid width falls out of the first one.
+    let columns = (atlas.size.x / atlas.textures[0].width()).round() as i32;
+    let rect = atlas.textures[(tile[1] * columns + tile[0]) as usize];
+    Rect {
+        min: (rect.min + TILE_INSET) / atlas.size,
+        max: (rect.max - TILE_INSET) / atlas.size,
+    }
+}
+
+/// Four corners per face of the box from `min` to `max`, in the usual face order and
+/// counter-clockwise when looking at the face.
+fn box_corners(min: Vec3, max: Vec3) -> [Vec3; 24] {
+    [
+        // Front
+        Vec3::new(min.x, min.y, max.z),
+        Vec3::new(max.x, min.y, max.z),
+        Vec3::new(max.x, max.y, max.z),
+        Vec3::new(min.x, max.y, max.z),
+        // Back
+        Vec3::new(max.x, min.y, min.z),
+        Vec3::new(min.x, min.y, min.z),
+        Vec3::new(min.x, max.y, min.z),
+        Vec3::new(max.x, max.y, min.z),
+        // Left
+        Vec3::new(min.x, min.y, min.z),
+        Vec3::new(min.x, min.y, max.z),
+        Vec3::new(min.x, max.y, max.z),
+        Vec3::new(min.x, max.y, min.z),
+        // Right
+        Vec3::new(max.x, min.y, max.z),
+        Vec3::new(max.x, min.y, min.z),
+        Vec3::new(max.x, max.y, min.z),
+        Vec3::new(max.x, max.y, max.z),
+        // Top
+        Vec3::new(min.x, max.y, max.z),
+        Vec3::new(max.x, max.y, max.z),
+        Vec3::new(max.x, max.y, min.z),
+        Vec3::new(min.x, max.y, min.z),
+        // Bottom
+        Vec3::new(min.x, min.y, min.z),
+        Vec3::new(max.x, min.y, min.z),
+        Vec3::new(max.x, min.y, max.z),
+        Vec3::new(min.x, min.y, max.z),
+    ]
+}
+
+/// Vertex data collected while meshing
+#[derive(Default)]
+pub struct MeshData {
+    positions: Vec<Vec3>,
+    uvs: Vec<Vec2>,
+    normals: Vec<Vec3>,
+    tiles: Vec<[f32; 4]>, // The atlas rect each vertex's face shows, see `ATTRIBUTE_TILE`
+    lights: Vec<[f32; 2]>, // Block and sky light, see `ATTRIBUTE_LIGHT`
+    colors: Vec<[f32; 4]>, // Multiplies the texture, for biome tints and occlusion
+    indices: Vec<u32>,
+}
+
+/// Vertex data for both of a chunk's meshes
+#[derive(Default)]
+pub struct ChunkMeshData {
+    opaque: MeshData,
+    cutout: MeshData,      // Leaves, drawn alpha masked
+    transparent: MeshData, // Water, drawn blended over the opaque mesh
+}
+
+impl ChunkMeshData {
+    fn pass(&mut self, block: &BlockDef) -> &mut MeshData {
+        if block.transparent {
+            &mut self.transparent
+        } else if block.cutout {
+            &mut self.cutout
+        } else {
+            &mut self.opaque
+        }
+    }
+}
+
+impl MeshData {
+    /// Corners should be counter-clockwise when looking at the front of the quad, and
+    /// their UVs start in `tile`. `face` indexes `FACE_NORMALS` and decides which way the
+    /// quad is lit. `light` is the block and sky light in front of it, and `ao` the
+    /// occlusion level of each corner, see `Chunk::corner_ao`.
+    fn push_quad(
         &mut self,
-        meshes: &mut ResMut<Assets<Mesh>>,
-        atlas_handle: Handle<TextureAtlas>,
-        atlas: &Res<Assets<TextureAtlas>>,
+        corners: [(Vec3, Vec2); 4],
+        tile: Rect,
+        face: usize,
+        tint: Vec3,
+        light: [u8; 2],
+        ao: [usize; 4],
     ) {
-        // Find the blocks that are not buried.
-        let temp = self.blocks.clone();
-        let visible_blocks = temp
-            .par_iter()
-            .filter(|block| {
-                let block_pos = block.0;
-                let other_blocks = &self.blocks;
-
-                let surrounding = vec![
-                    IVec3::new(block_pos.x - 1, block_pos.y, block_pos.z),
-                    IVec3::new(block_pos.x, block_pos.y - 1, block_pos.z),
-                    IVec3::new(block_pos.x, block_pos.y, block_pos.z - 1),
-                    IVec3::new(block_pos.x + 1, block_pos.y, block_pos.z),
-                    IVec3::new(block_pos.x, block_pos.y + 1, block_pos.z),
-                    IVec3::new(block_pos.x, block_pos.y, block_pos.z + 1),
-                ];
+        let base = self.positions.len() as u32;
+        let tile = [tile.min.x, tile.min.y, tile.width(), tile.height()];
+        let light = light.map(|level| (MAX_LIGHT - level.min(MAX_LIGHT)) as f32);
+        for ((position, uv), level) in corners.into_iter().zip(ao) {
+            self.positions.push(position * BLOCK_SIZE);
+            self.uvs.push(uv);
+            self.normals.push(FACE_NORMALS[face].as_vec3());
+            self.tiles.push(tile);
+            self.lights.push(light);
+            self.colors
+                .push((tint * AO_BRIGHTNESS[level]).extend(1.0).to_array());
+        }
 
-                !(other_blocks.contains_key(&surrounding[0])
-                    && other_blocks.contains_key(&surrounding[1])
-                    && other_blocks.contains_key(&surrounding[2])
-                    && other_blocks.contains_key(&surrounding[3])
-                    && other_blocks.contains_key(&surrounding[4])
-                    && other_blocks.contains_key(&surrounding[5]))
-            })
-            .collect::<Vec<_>>();
+        // Split along the brighter diagonal, or a single dark corner smears across the
+        // whole quad.
+        let [a, b, c, d] = [base, base + 1, base + 2, base + 3];
+        if ao[0] + ao[2] < ao[1] + ao[3] {
+            self.indices.extend_from_slice(&[b, c, d, b, d, a]);
+        } else {
+            self.indices.extend_from_slice(&[a, b, c, a, c, d]);
+        }
+    }
 
-        // Filter out Air blocks.
-        let visible_blocks = visible_blocks
-            .par_iter()
-            .filter(|block| block.1.btype != BlockType::Air)
-            .collect::<Vec<_>>();
+    /// A `BlockShape::Post` in the block whose corner is at `min`. Its faces show the
+    /// middle of the block's tiles, where a torch is drawn, and are always fully lit.
+    fn push_post(&mut self, atlas: &TextureAtlas, def: &BlockDef, min: Vec3) {
+        // In fractions of a tile: two texels wide and ten tall, standing on the bottom.
+        let (left, right, top) = (7.0 / 16.0, 9.0 / 16.0, 6.0 / 16.0);
+        let corners = box_corners(
+            min + Vec3::new(left, 0.0, left),
+            min + Vec3::new(right, 1.0 - top, right),
+        );
 
-        let new_meshes = Arc::new(Mutex::new(HashMap::new()));
+        for face in 0..FACE_NORMALS.len() {
+            let rect = tile_rect(atlas, def.faces[face]);
+            let uv = |x: f32, y: f32| rect.min + Vec2::new(x, y) * rect.size();
+            // The sides show the whole post, the ends the two texels at its top.
+            let bottom = if face < 4 { 1.0 } else { top + 2.0 / 16.0 };
+            let uvs = [
+                uv(left, bottom),
+                uv(right, bottom),
+                uv(right, top),
+                uv(left, top),
+            ];
+            let corner = |i: usize| (corners[face * 4 + i], uvs[i]);
+            let quad = [corner(0), corner(1), corner(2), corner(3)];
+            self.push_quad(quad, rect, face, Vec3::ONE, [MAX_LIGHT; 2], [3; 4]);
+        }
+    }
 
-        // For each visible block, get the verticies and indicies that are not back to back with other blocks.
-        visible_blocks.par_iter().for_each(|block| {
-            let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+    fn into_mesh(self) -> Mesh {
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+        mesh.insert_attribute(
+            Mesh::ATTRIBUTE_NORMAL,
+            self.normals
+                .iter()
+                .map(|n| n.to_array())
+                .collect::<Vec<_>>(),
+        );
+        mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, self.uvs);
+        mesh.insert_attribute(ATTRIBUTE_TILE, self.tiles);
+        mesh.insert_attribute(ATTRIBUTE_LIGHT, self.lights);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, self.colors);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, self.positions);
+        mesh.set_indices(Some(Indices::U32(self.indices)));
+        mesh
+    }
 
-            let block_pos = block.0.as_vec3();
+    /// Writes the vertices and triangles as Wavefront OBJ lines. OBJ counts vertices
+    /// across the whole file, from 1, so `first` is the number the first one gets.
+    fn write_obj(&self, out: &mut impl Write, first: usize) -> io::Result<()> {
+        for p in self.positions.iter() {
+            writeln!(out, "v {} {} {}", p.x, p.y, p.z)?;
+        }
+        // OBJ's V goes up from the bottom of the image, the atlas's goes down.
+        for uv in self.uvs.iter() {
+            writeln!(out, "vt {} {}", uv.x, 1.0 - uv.y)?;
+        }
+        for n in self.normals.iter() {
+            writeln!(out, "vn {} {} {}", n.x, n.y, n.z)?;
+        }
 
-            let block_indicies = vec![
-                0, 1, 3, 3, 1, 2, // Front
-                1, 5, 2, 2, 5, 6, // Right
-                5, 4, 6, 6, 4, 7, // Back
-                4, 0, 7, 7, 0, 3, // Left
-                3, 2, 7, 7, 2, 6, // Top
-                4, 5, 0, 0, 5, 1, // Bottom
-            ];
+        for triangle in self.indices.chunks(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| triangle[i] as usize + first);
+            writeln!(out, "f {a}/{a}/{a} {b}/{b}/{b} {c}/{c}/{c}")?;
+        }
+        Ok(())
+    }
+}
 
-            // Need to figure out an effective way to only render the faces that are visible
-            let block_verticies = vec![
-                // Front
-                Vec3::new(block_pos.x - 1.0, block_pos.y - 1.0, block_pos.z + 1.0),
-                Vec3::new(block_pos.x + 1.0, block_pos.y - 1.0, block_pos.z + 1.0),
-                Vec3::new(block_pos.x + 1.0, block_pos.y + 1.0, block_pos.z + 1.0),
-                Vec3::new(block_pos.x - 1.0, block_pos.y + 1.0, block_pos.z + 1.0),
-                // Back
-                Vec3::new(block_pos.x - 1.0, block_pos.y - 1.0, block_pos.z - 1.0),
-                Vec3::new(block_pos.x + 1.0, block_pos.y - 1.0, block_pos.z - 1.0),
-                Vec3::new(block_pos.x + 1.0, block_pos.y + 1.0, block_pos.z - 1.0),
-                Vec3::new(block_pos.x - 1.0, block_pos.y + 1.0, block_pos.z - 1.0),
-                // Left
-                Vec3::new(block_pos.x - 1.0, block_pos.y - 1.0, block_pos.z - 1.0),
-                Vec3::new(block_pos.x - 1.0, block_pos.y - 1.0, block_pos.z + 1.0),
-                Vec3::new(block_pos.x - 1.0, block_pos.y + 1.0, block_pos.z + 1.0),
-                Vec3::new(block_pos.x - 1.0, block_pos.y + 1.0, block_pos.z - 1.0),
-                // Right
-                Vec3::new(block_pos.x + 1.0, block_pos.y - 1.0, block_pos.z - 1.0),
-                Vec3::new(block_pos.x + 1.0, block_pos.y - 1.0, block_pos.z + 1.0),
-                Vec3::new(block_pos.x + 1.0, block_pos.y + 1.0, block_pos.z + 1.0),
-                Vec3::new(block_pos.x + 1.0, block_pos.y + 1.0, block_pos.z - 1.0),
-                // Top
-                Vec3::new(block_pos.x - 1.0, block_pos.y + 1.0, block_pos.z - 1.0),
-                Vec3::new(block_pos.x + 1.0, block_pos.y + 1.0, block_pos.z - 1.0),
-                Vec3::new(block_pos.x + 1.0, block_pos.y + 1.0, block_pos.z + 1.0),
-                Vec3::new(block_pos.x - 1.0, block_pos.y + 1.0, block_pos.z + 1.0),
-                // Bottom
-                Vec3::new(block_pos.x - 1.0, block_pos.y - 1.0, block_pos.z - 1.0),
-                Vec3::new(block_pos.x + 1.0, block_pos.y - 1.0, block_pos.z - 1.0),
-                Vec3::new(block_pos.x + 1.0, block_pos.y - 1.0, block_pos.z + 1.0),
-                Vec3::new(block_pos.x - 1.0, block_pos.y - 1.0, block_pos.z + 1.0),
-            ];
+/// The block a flat world has at height `y`. A water level at or above `FLAT_HEIGHT`
+/// floods it.
+fn flat_block(y: i32, water_level: i32) -> BlockType {
+    let mut top = FLAT_HEIGHT;
+    for (btype, depth) in FLAT_LAYERS {
+        if y < top && y >= top - depth {
+            return btype;
+        }
+        top -= depth;
+    }
 
-            let mut texture_indices = Vec::new();
-
-            match block.1.btype {
-                BlockType::Grass => {
-                    texture_indices = vec![
-                        [1, 10],
-                        [1, 10],
-                        [1, 10],
-                        [1, 10], // Front
-                        [4, 8],
-                        [4, 8],
-                        [4, 8],
-                        [4, 8], // Back
-                        [3, 5],
-                        [3, 5],
-                        [3, 5],
-                        [3, 5], // Left
-                        [2, 9],
-                        [2, 9],
-                        [2, 9],
-                        [2, 9], // Right
-                        [16, 1],
-                        [16, 1],
-                        [16, 1],
-                        [16, 1], // Top
-                        [15, 5],
-                        [15, 5],
-                        [15, 5],
-                        [15, 5], // Bottom
-                    ];
-                }
-                BlockType::Dirt => {
-                    texture_indices = vec![
-                        [3, 5],
-                        [3, 5],
-                        [3, 5],
-                        [3, 5], // Front
-                        [3, 5],
-                        [3, 5],
-                        [3, 5],
-                        [3, 5], // Back
-                        [3, 5],
-                        [3, 5],
-                        [3, 5],
-                        [3, 5], // Left
-                        [3, 5],
-                        [3, 5],
-                        [3, 5],
-                        [3, 5], // Right
-                        [15, 5],
-                        [15, 5],
-                        [15, 5],
-                        [15, 5], // Top
-                        [15, 5],
-                        [15, 5],
-                        [15, 5],
-                        [15, 5], // Bottom
-                    ];
-                }
-                BlockType::Stone => {
-                    texture_indices = vec![
-                        [14, 3],
-                        [14, 3],
-                        [14, 3],
-                        [14, 3], // Front
-                        [14, 3],
-                        [14, 3],
-                        [14, 3],
-                        [14, 3], // Back
-                        [14, 3],
-                        [14, 3],
-                        [14, 3],
-                        [14, 3], // Left
-                        [14, 3],
-                        [14, 3],
-                        [14, 3],
-                        [14, 3], // Right
-                        [13, 1],
-                        [13, 1],
-                        [13, 1],
-                        [13, 1], // Top
-                        [12, 3],
-                        [12, 3],
-                        [12, 3],
-                        [12, 3], // Bottom
-                    ];
-                }
-                BlockType::Water => {
-                    texture_indices = vec![
-                        [0, 0],
-                        [0, 0],
-                        [0, 0],
-                        [0, 0], // Front
-                        [0, 0],
-                        [0, 0],
-                        [0, 0],
-                        [0, 0], // Back
-                        [0, 0],
-                        [0, 0],
-                        [0, 0],
-                        [0, 0], // Left
-                        [0, 0],
-                        [0, 0],
-                        [0, 0],
-                        [0, 0], // Right
-                        [0, 0],
-                        [0, 0],
-                        [0, 0],
-                        [0, 0], // Top
-                        [0, 0],
-                        [0, 0],
-                        [0, 0],
-                        [0, 0], // Bottom
-                    ];
-                }
-                _ => {
-                    texture_indices = vec![
-                        [4, 15],
-                        [4, 15],
-                        [4, 15],
-                        [4, 15], // Front
-                        [4, 15],
-                        [4, 15],
-                        [4, 15],
-                        [4, 15], // Back
-                        [4, 15],
-                        [4, 15],
-                        [4, 15],
-                        [4, 15], // Left
-                        [4, 15],
-                        [4, 15],
-                        [4, 15],
-                        [4, 15], // Right
-                        [4, 15],
-                        [4, 15],
-                        [4, 15],
-                        [4, 15], // Top
-                        [4, 15],
-                        [4, 15],
-                        [4, 15],
-                        [4, 15], // Bottom
-                    ];
-                }
-            }
-
-            let atlas_info = &atlas.get(&atlas_handle).unwrap().textures;
-            // HOW DO I LINK THE UV_O_POSITION WITH THE TEXTURE ATLAS????
-
-            // Let temp be the texture indicies as a Vec<Vec2>
-            let mut temp = Vec::new();
-            for i in 0..texture_indices.len() {
-                let x = texture_indices[i][0] as f32 / 16.0;
-                let y = texture_indices[i][1] as f32 / 16.0;
-                temp.push(Vec2::new(x, y));
-            }
-
-            mesh.insert_attribute(
-                Mesh::ATTRIBUTE_NORMAL,
-                vec![[0., 1., 0.]; block_verticies.len()],
-            );
+    if y < top {
+        BlockType::Bedrock
+    } else if y <= water_level {
+        BlockType::Water
+    } else {
+        BlockType::Air
+    }
+}
+
+/// Height of the generated ground in a column, in blocks. Everything below it is solid,
+/// apart from caves.
+///
+/// There's one height map for the whole world, and chunks are whole columns, so taller
+/// terrain is only a bigger `vertical_scale`: anything up to `CHUNK_HEIGHT` fits without
+/// clipping. Caves are 3D noise, so they go as deep as the column does.
+fn column_height(noise: &HeightNoise, config: &WorldConfig, x: i32, z: i32) -> f64 {
+    let value = noise.get(
+        x as f64 * config.horizontal_scale,
+        z as f64 * config.horizontal_scale,
+    );
+    // Noise is roughly -1..1, stretch that over 0..vertical_scale.
+    let mut height = (value + 1.0) / 2.0 * config.vertical_scale;
+    if let Some(island) = &config.island {
+        height *= island.falloff(x, z);
+    }
+    height
+}
 
-            mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, temp);
-            mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, block_verticies);
-            mesh.set_indices(Some(Indices::U32(block_indicies)));
+/// The block terrain generation puts at `block_pos`, before ores and trees.
+fn terrain_block(
+    noise: &HeightNoise,
+    caves: &Fbm<Perlin>,
+    config: &WorldConfig,
+    biome: Biome,
+    block_pos: IVec3,
+) -> BlockType {
+    let y = block_pos.y;
 
-            new_meshes.lock().unwrap().insert(block.0, mesh);
-        });
+    // The floor of the world, whatever the terrain above it is doing.
+    let bedrock_top =
+        column_hash(config.seed.wrapping_add(5), block_pos.x, block_pos.z) % BEDROCK_HEIGHT as u32;
+    if y <= bedrock_top as i32 {
+        return BlockType::Bedrock;
+    }
+
+    let height = column_height(noise, config, block_pos.x, block_pos.z);
+    // Layers go down from the top block of the column, wherever that is.
+    let depth = height.ceil() as i32 - 1 - y;
+
+    // Columns whose surface is close to the water level become beaches.
+    let beach = (height - config.water_level as f64).abs() <= BEACH_WIDTH;
+
+    // Cold biomes are snowy all over, everywhere else only the peaks are. Each column
+    // moves the line a little so it isn't a flat ring around the mountain.
+    let hash = column_hash(config.seed.wrapping_add(6), block_pos.x, block_pos.z);
+    let jitter = (hash % 1000) as f64 / 1000.0 * 2.0 - 1.0;
+    let snowy = biome == Biome::Snowy || height > config.snow_line + jitter * SNOW_LINE_JITTER;
 
-        // self.blocks
-        //     .retain(|pos, block| !new_meshes.lock().unwrap().contains_key(&pos));
+    if (y as f64) < height {
+        // Carve out caves, leaving the floor of the world solid.
+        let cave = caves.get([
+            block_pos.x as f64 * CAVE_FREQUENCY,
+            block_pos.y as f64 * CAVE_FREQUENCY,
+            block_pos.z as f64 * CAVE_FREQUENCY,
+        ]);
+        if y >= CAVE_FLOOR && cave > CAVE_THRESHOLD {
+            return BlockType::Air;
+        }
 
-        for (position, mesh) in new_meshes.lock().unwrap().iter() {
-            // Update the mesh in self.blocks
-            let mut block = self.blocks.get(&position).unwrap().to_owned();
-            block.mesh = meshes.add(mesh.clone());
-            let pos = IVec3::new(position.x, position.y, position.z);
-            self.blocks.insert(pos, block.clone());
+        if beach || biome == Biome::Desert {
+            if depth < SAND_DEPTH {
+                BlockType::Sand
+            } else {
+                BlockType::Stone
+            }
+        } else if depth == 0 {
+            // Only the top block, there's dirt under the snow.
+            if snowy {
+                BlockType::Snow
+            } else {
+                BlockType::Grass
+            }
+        } else if depth <= DIRT_DEPTH {
+            BlockType::Dirt
+        } else {
+            BlockType::Stone
         }
+    } else if y <= config.water_level {
+        // Everything between the ground and the water level is sea.
+        BlockType::Water
+    } else {
+        BlockType::Air
     }
 }
-// ---------------------------
 
-// ---------- World ----------
-#[derive(Resource)]
-pub struct Map {
-    chunks: HashMap<IVec2, Chunk>,
-    cache: HashMap<IVec2, Chunk>,
-    noise: NoiseMap,
-    pub texture_atlas: Handle<TextureAtlas>,
+// ---------- Chunk ----------
+/// A chunk's blocks, packed. Each kind of block in the chunk is in `palette` once, and
+/// every block is an index into it, using only as many bits as the palette needs. A
+/// chunk of a single kind of block, like all air, needs no indices at all.
+#[derive(Clone, Debug)]
+pub struct BlockStorage {
+    palette: Vec<BlockType>, // Never empty. Kinds that were replaced stay until a repack
+    bits: u32,               // Per index. 0 while there's only one kind of block
+    words: Vec<u64>,         // The indices, low bits first. None straddles two words
 }
 
-impl FromWorld for Map {
-    fn from_world(_world: &mut World) -> Self {
-        let fbm = Fbm::<Perlin>::new(SEED);
+impl Default for BlockStorage {
+    fn default() -> Self {
+        Self::filled(BlockType::Air)
+    }
+}
 
-        let height_map = PlaneMapBuilder::<_, 3>::new(&fbm)
-            .set_size(1024, 1024)
-            .set_x_bounds(-5.0, 5.0)
-            .set_y_bounds(-5.0, 5.0)
-            .build();
+impl PartialEq for BlockStorage {
+    fn eq(&self, other: &Self) -> bool {
+        self.iter().eq(other.iter())
+    }
+}
 
-        Map {
-            chunks: HashMap::new(),
-            cache: HashMap::new(),
-            noise: height_map,
-            texture_atlas: Handle::default(),
+impl BlockStorage {
+    /// Every block the same
+    pub fn filled(btype: BlockType) -> Self {
+        Self {
+            palette: vec![btype],
+            bits: 0,
+            words: Vec::new(),
         }
     }
-}
-// ---------------------------
 
-// ---------- Systems ----------
+    /// Bits an index needs to tell `len` kinds of block apart
+    fn bits_for(len: usize) -> u32 {
+        usize::BITS - (len.max(1) - 1).leading_zeros()
+    }
 
-// Need ray casting for block addition / deletion. Will do later.
+    /// Which word holds the index of block `i`, and how far up it's shifted
+    fn locate(&self, i: usize) -> (usize, u32) {
+        let per_word = (u64::BITS / self.bits) as usize;
+        (i / per_word, (i % per_word) as u32 * self.bits)
+    }
 
-pub fn update_world(
-    mut commands: Commands,
-    mut map: ResMut<Map>,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-    atlas: Res<Assets<TextureAtlas>>,
-    camera: Query<&Transform, With<FlyCam>>,
-    entities: Query<(Entity, &Chunk), With<Chunk>>,
-) {
-    // In here, I will use the camera's position to determine which chunks to load and unload.
-    let camera = camera.single();
-    let pos = Vec2::new(camera.translation.x, camera.translation.z);
+    fn palette_index(&self, i: usize) -> usize {
+        if self.bits == 0 {
+            return 0;
+        }
+        let (word, shift) = self.locate(i);
+        ((self.words[word] >> shift) & ((1 << self.bits) - 1)) as usize
+    }
 
-    let mut cached_chunks = Vec::new();
+    fn set_palette_index(&mut self, i: usize, index: usize) {
+        let (word, shift) = self.locate(i);
+        let mask = ((1 << self.bits) - 1) << shift;
+        self.words[word] = (self.words[word] & !mask) | ((index as u64) << shift);
+    }
 
-    // Remove chunks outside the render distance and add them to the cache.
-    for (chunk_pos, _chunk) in map.chunks.iter() {
-        let distance = (chunk_pos.as_vec2() - pos).length();
-        if distance > (CHUNK_SIZE * RENDER_DISTANCE) as f32 {
-            cached_chunks.push(*chunk_pos);
+    /// Packs every index again with `bits` each
+    fn repack(&mut self, bits: u32) {
+        let indices: Vec<usize> = (0..CHUNK_VOLUME).map(|i| self.palette_index(i)).collect();
+        let per_word = (u64::BITS / bits) as usize;
+        self.bits = bits;
+        self.words = vec![0; CHUNK_VOLUME.div_ceil(per_word)];
+        for (i, index) in indices.into_iter().enumerate() {
+            self.set_palette_index(i, index);
         }
     }
 
-    // Add the cached chunks to the cache.
-    for chunk_pos in cached_chunks.iter() {
-        if !map.cache.contains_key(chunk_pos) {
-            let chunk = map.chunks.get(chunk_pos).unwrap().clone();
-            map.cache.insert(*chunk_pos, chunk);
-            map.chunks.remove(chunk_pos);
+    /// The block at `i`, see `Chunk::index`
+    pub fn get(&self, i: usize) -> BlockType {
+        self.palette[self.palette_index(i)]
+    }
+
+    /// Sets the block at `i`, widening the indices if it's a new kind of block that
+    /// doesn't fit in them
+    pub fn set(&mut self, i: usize, btype: BlockType) {
+        let index = match self.palette.iter().position(|known| *known == btype) {
+            Some(index) => index,
+            None => {
+                self.palette.push(btype);
+                let bits = Self::bits_for(self.palette.len());
+                if bits > self.bits {
+                    self.repack(bits);
+                }
+                self.palette.len() - 1
+            }
+        };
+        if self.bits > 0 {
+            self.set_palette_index(i, index);
         }
     }
 
-    // Remove cached chunks that are too far away.
-    map.cache.retain(|chunk_pos, _chunk| {
-        let distance = (chunk_pos.as_vec2() - pos).length();
-        if distance > (CHUNK_SIZE * RENDER_DISTANCE) as f32 {
-            cached_chunks.push(*chunk_pos);
-            false
-        } else {
-            true
+    /// Every block in order, for meshing and the like
+    pub fn iter(&self) -> impl Iterator<Item = BlockType> + '_ {
+        (0..CHUNK_VOLUME).map(|i| self.get(i))
+    }
+}
+
+/// Packs a chunk's worth of blocks, in `Chunk::index` order. Anything missing off the end
+/// is air, like the top of chunks saved when they were only `CHUNK_SIZE` tall.
+impl FromIterator<BlockType> for BlockStorage {
+    fn from_iter<T: IntoIterator<Item = BlockType>>(blocks: T) -> Self {
+        let mut blocks: Vec<BlockType> = blocks.into_iter().collect();
+        blocks.resize(CHUNK_VOLUME, BlockType::Air);
+        let mut palette = Vec::new();
+        for btype in blocks.iter() {
+            if !palette.contains(btype) {
+                palette.push(*btype);
+            }
         }
-    });
 
-    // Despawn the chunks.
-    for (entity, chunk) in entities.iter() {
-        if !map.chunks.contains_key(&chunk.position) {
-            commands.entity(entity).despawn_recursive();
+        let mut storage = Self::filled(palette[0]);
+        storage.palette = palette;
+        let bits = Self::bits_for(storage.palette.len());
+        if bits > 0 {
+            storage.repack(bits);
+            for (i, btype) in blocks.iter().enumerate() {
+                let index = storage.palette.iter().position(|known| known == btype);
+                storage.set_palette_index(i, index.unwrap_or(0));
+            }
         }
+        storage
     }
+}
 
-    // Load the chunks.
-    let player_pos = IVec2::new(
-        (pos.x / CHUNK_SIZE as f32).floor() as i32 * CHUNK_SIZE,
-        (pos.y / CHUNK_SIZE as f32).floor() as i32 * CHUNK_SIZE,
-    );
+/// Which of a chunk's blocks are solid and which hide their neighbours' faces, one bit
+/// per block indexed like `Chunk::blocks`. Lets meshing and collisions test a bit rather
+/// than look every block up in the registry. Empty, like a new chunk's, is all clear.
+#[derive(Clone, Default)]
+struct Occupancy {
+    solid: Vec<u64>,  // See `BlockDef::solid`
+    opaque: Vec<u64>, // See `BlockDef::hides_faces`
+}
 
-    // Get chunks around player_pos and put them all in new_chunks.
-    let mut new_chunks = vec![
-        player_pos,
-        player_pos + IVec2::new(CHUNK_SIZE, 0),
-        player_pos + IVec2::new(0, CHUNK_SIZE),
-        player_pos + IVec2::new(CHUNK_SIZE, CHUNK_SIZE),
-        player_pos + IVec2::new(-CHUNK_SIZE, 0),
-        player_pos + IVec2::new(0, -CHUNK_SIZE),
-        player_pos + IVec2::new(-CHUNK_SIZE, -CHUNK_SIZE),
-        player_pos + IVec2::new(-CHUNK_SIZE, CHUNK_SIZE),
-        player_pos + IVec2::new(CHUNK_SIZE, -CHUNK_SIZE),
-    ];
+impl Occupancy {
+    fn new(blocks: &BlockStorage, registry: &BlockRegistry) -> Self {
+        let defs: Vec<&BlockDef> = blocks.palette.iter().map(|b| registry.get(b)).collect();
+        let mut occupancy = Self {
+            solid: vec![0; CHUNK_VOLUME.div_ceil(64)],
+            opaque: vec![0; CHUNK_VOLUME.div_ceil(64)],
+        };
+        for i in 0..CHUNK_VOLUME {
+            occupancy.set(i, defs[blocks.palette_index(i)]);
+        }
+        occupancy
+    }
 
-    // Need to sort the blocks so that the ones closer are rendered first.
+    fn is_empty(&self) -> bool {
+        self.solid.is_empty()
+    }
 
-    // Remove chunks that are already loaded or cached.
-    new_chunks.retain(|chunk_pos| !map.chunks.contains_key(chunk_pos));
+    fn set(&mut self, i: usize, def: &BlockDef) {
+        let (word, bit) = (i / 64, 1 << (i % 64));
+        for (words, on) in [
+            (&mut self.solid, def.solid),
+            (&mut self.opaque, def.hides_faces()),
+        ] {
+            if on {
+                words[word] |= bit;
+            } else {
+                words[word] &= !bit;
+            }
+        }
+    }
 
-    // Load the chunks.
-    for chunk_pos in new_chunks.iter() {
-        // Realized that the perlin noise map required usize coordinates...
-        if chunk_pos.x < 0 || chunk_pos.y < 0 {
-            continue;
+    fn test(words: &[u64], i: usize) -> bool {
+        words
+            .get(i / 64)
+            .is_some_and(|word| word >> (i % 64) & 1 == 1)
+    }
+}
+
+#[derive(Component, Clone)]
+pub struct Chunk {
+    blocks: BlockStorage, // CHUNK_VOLUME of them, see `Chunk::index`
+    occupancy: Occupancy, // Worked out from `blocks` by `gen_occupancy`
+    position: IVec2,      // In chunks, not blocks
+    axes: Vec<BlockAxis>, // Indexed like `blocks`. Empty is everything upright
+    dirty: bool,          // Needs to be re-meshed
+    modified: bool,       // No longer what gen_blocks makes, so it has to be kept and saved
+    last_used: f64,       // When it went into the cache, in seconds since startup
+    mesh: Handle<Mesh>,
+    cutout_mesh: Option<Handle<Mesh>>, // None when there are no cutout blocks
+    transparent_mesh: Option<Handle<Mesh>>, // None when there's nothing see-through
+    biomes: Vec<Biome>,                // One per column. Comes from the seed, so not saved
+    grass_tints: Vec<Vec3>,            // Blended grass colour per column
+    block_light: Vec<u8>,              // Indexed like `blocks`. Empty means dark
+    sky_light: Vec<u8>,                // Same, filled in by `gen_sky_light`
+    lod: u8,                           // Level of detail to mesh at, see `Meshing::lod`
+}
+
+impl Chunk {
+    fn new(pos: IVec2) -> Self {
+        Self {
+            blocks: BlockStorage::default(),
+            occupancy: Occupancy::default(),
+            position: pos,
+            axes: Vec::new(),
+            dirty: false,
+            modified: false,
+            last_used: 0.0,
+            mesh: Handle::default(),
+            cutout_mesh: None,
+            transparent_mesh: None,
+            biomes: Vec::new(),
+            grass_tints: Vec::new(),
+            block_light: Vec::new(),
+            sky_light: Vec::new(),
+            lod: 0,
         }
+    }
 
-        if !map.chunks.contains_key(chunk_pos) {
-            if map.cache.contains_key(chunk_pos) {
-                let chunk = map.cache.get(chunk_pos).unwrap().clone();
-                map.chunks.insert(*chunk_pos, chunk);
-                map.cache.remove(chunk_pos);
-            } else {
-                let mut chunk = Chunk::new(*chunk_pos);
-                chunk.gen_blocks(&map.noise);
-                chunk.gen_meshes(&mut meshes, map.texture_atlas.clone(), &atlas);
-                map.chunks.insert(*chunk_pos, chunk);
+    /// World position of the chunk's first block.
+    fn origin(&self) -> IVec3 {
+        IVec3::new(self.position.x, 0, self.position.y) * CHUNK_SIZE
+    }
+
+    /// Index into `blocks` of a position relative to the chunk, `None` if it's outside.
+    fn index(local: IVec3) -> Option<usize> {
+        if local.cmplt(IVec3::ZERO).any() || local.cmpge(CHUNK_EXTENT).any() {
+            return None;
+        }
+        Some((local.x + local.z * CHUNK_SIZE + local.y * CHUNK_SIZE * CHUNK_SIZE) as usize)
+    }
+
+    /// World position of the block at `index`.
+    fn position_of(&self, index: usize) -> IVec3 {
+        let i = index as i32;
+        let local = IVec3::new(
+            i % CHUNK_SIZE,
+            i / (CHUNK_SIZE * CHUNK_SIZE),
+            (i / CHUNK_SIZE) % CHUNK_SIZE,
+        );
+        self.origin() + local
+    }
+
+    /// The block at a world position. Anything outside the chunk is air.
+    fn get(&self, world_pos: IVec3) -> BlockType {
+        Self::index(world_pos - self.origin()).map_or(BlockType::Air, |i| self.blocks.get(i))
+    }
+
+    /// Sets the block at a world position, does nothing if it's outside the chunk.
+    fn set(&mut self, world_pos: IVec3, btype: BlockType) {
+        if let Some(i) = Self::index(world_pos - self.origin()) {
+            self.blocks.set(i, btype);
+            if !self.axes.is_empty() {
+                self.axes[i] = BlockAxis::Y;
             }
         }
+    }
 
-        let chunk = map.chunks.get(chunk_pos).unwrap();
+    /// Which way the block at a world position runs.
+    fn axis(&self, world_pos: IVec3) -> BlockAxis {
+        Self::index(world_pos - self.origin())
+            .and_then(|i| self.axes.get(i).copied())
+            .unwrap_or_default()
+    }
 
-        commands
-            .spawn(Chunk {
-                blocks: chunk.blocks.clone(),
-                position: chunk.position,
-            })
-            .with_children(|parent| {
-                for block in chunk.blocks.iter() {
-                    parent.spawn(PbrBundle {
-                        mesh: block.1.mesh.clone(),
-                        material: materials.add(block.1.btype.get_material().clone()),
-                        transform: Transform::from_translation(Vec3::new(
-                            block.0.x as f32,
-                            block.0.y as f32,
-                            block.0.z as f32,
-                        )),
-                        ..Default::default()
-                    });
-                }
-            })
-            .insert(VisibilityBundle::default());
+    /// Turns the block at a world position, does nothing if it's outside the chunk.
+    fn set_axis(&mut self, world_pos: IVec3, axis: BlockAxis) {
+        if let Some(i) = Self::index(world_pos - self.origin()) {
+            if self.axes.is_empty() && axis == BlockAxis::Y {
+                return;
+            }
+            // Saved ones can be short, see `BlockStorage::from_iter`.
+            self.axes.resize(CHUNK_VOLUME, BlockAxis::Y);
+            self.axes[i] = axis;
+        }
     }
-}
-// -----------------------------
+
+    fn light_levels(&mut self, kind: LightKind) -> &mut Vec<u8> {
+        match kind {
+            LightKind::Block => &mut self.block_light,
+            LightKind::Sky => &mut self.sky_light,
+        }
+    }
+
+    /// Light at a world position, 0 outside the chunk.
+    fn light(&self, kind: LightKind, world_pos: IVec3) -> u8 {
+        let levels = match kind {
+            LightKind::Block => &self.block_light,
+            LightKind::Sky => &self.sky_light,
+        };
+        Self::index(world_pos - self.origin())
+            .and_then(|i| levels.get(i).copied())
+            .unwrap_or(0)
+    }
+
+    /// Returns whether the level changed.
+    fn set_light(&mut self, kind: LightKind, world_pos: IVec3, level: u8) -> bool {
+        let Some(i) = Self::index(world_pos - self.origin()) else {
+            return false;
+        };
+        let levels = self.light_levels(kind);
+        if levels.is_empty() {
+            if level == 0 {
+                return false;
+            }
+            *levels = vec![0; CHUNK_VOLUME];
+        }
+
+        let changed = levels[i] != level;
+        levels[i] = level;
+        changed
+    }
+
+    /// Works out which blocks are solid, once they're generated or loaded. Edits through
+    /// the map keep it up to date after that.
+    fn gen_occupancy(&mut self, registry: &BlockRegistry) {
+        self.occupancy = Occupancy::new(&self.blocks, registry);
+    }
+
+    /// Whether the block at a world position is solid. Outside the chunk counts as air.
+    fn is_solid(&self, world_pos: IVec3) -> bool {
+        Self::index(world_pos - self.origin())
+            .is_some_and(|i| Occupancy::test(&self.occupancy.solid, i))
+    }
+
+    /// Whether the block at a world position hides the faces next to it
+    fn is_opaque(&self, world_pos: IVec3) -> bool {
+        Self::index(world_pos - self.origin())
+            .is_some_and(|i| Occupancy::test(&self.occupancy.opaque, i))
+    }
+
+    /// Marches down every column, giving full sky light to everything above the first
+    /// solid block, then spreads it sideways under overhangs and into caves. Only sees
+    /// this chunk, `Map::light_chunk` spreads it over the borders.
+    fn gen_sky_light(&mut self, registry: &BlockRegistry) {
+        let offset = self.origin();
+        let solid = |local: IVec3| registry.get(&self.get(offset + local)).solid;
+        let mut levels = vec![0; CHUNK_VOLUME];
+        let mut queue = VecDeque::new();
+
+        for z in 0..CHUNK_SIZE {
+            for x in 0..CHUNK_SIZE {
+                for y in (0..CHUNK_HEIGHT).rev() {
+                    let local = IVec3::new(x, y, z);
+                    if solid(local) {
+                        break;
+                    }
+                    levels[Self::index(local).unwrap()] = MAX_LIGHT;
+                    queue.push_back(local);
+                }
+            }
+        }
+
+        while let Some(local) = queue.pop_front() {
+            let level = levels[Self::index(local).unwrap()];
+            for normal in FACE_NORMALS {
+                let next = local + normal;
+                let Some(i) = Self::index(next) else {
+                    continue;
+                };
+                if levels[i] + 1 < level && !solid(next) {
+                    levels[i] = level - 1;
+                    queue.push_back(next);
+                }
+            }
+        }
+
+        self.sky_light = levels;
+    }
+
+    /// Index of a block's column in `biomes` and `grass_tints`.
+    fn column(&self, world_pos: IVec3) -> usize {
+        let local = world_pos - self.origin();
+        (local.x + local.z * CHUNK_SIZE) as usize
+    }
+
+    pub fn biome(&self, world_pos: IVec3) -> Biome {
+        self.biomes[self.column(world_pos)]
+    }
+
+    /// Works out every column's biome and grass colour.
+    fn gen_biomes(&mut self, climate: &Climate) {
+        let offset = self.origin();
+        let columns = (0..CHUNK_SIZE * CHUNK_SIZE)
+            .map(|i| (offset.x + i % CHUNK_SIZE, offset.z + i / CHUNK_SIZE));
+
+        self.biomes = columns.clone().map(|(x, z)| climate.biome(x, z)).collect();
+        self.grass_tints = columns.map(|(x, z)| climate.grass_tint(x, z)).collect();
+    }
+
+    fn gen_blocks(
+        &mut self,
+        noise: &HeightNoise,
+        caves: &Fbm<Perlin>,
+        climate: &Climate,
+        config: &WorldConfig,
+    ) {
+        self.gen_biomes(climate);
+        if config.gen_mode == GenMode::Flat {
+            // Same layers everywhere, no caves, ores or trees.
+            self.blocks = (0..CHUNK_VOLUME as i32)
+                .map(|i| flat_block(i / (CHUNK_SIZE * CHUNK_SIZE), config.water_level))
+                .collect();
+            return;
+        }
+
+        let offset = self.origin();
+        let biomes = &self.biomes;
+
+        // Every block is independent, so each worker just hands back what it made.
+        // The iteration order matches `Chunk::index`.
+        let blocks: Vec<BlockType> = (0..CHUNK_VOLUME as i32)
+            .into_par_iter()
+            .map(|i| {
+                let x = i % CHUNK_SIZE;
+                let z = (i / CHUNK_SIZE) % CHUNK_SIZE;
+                let y = i / (CHUNK_SIZE * CHUNK_SIZE);
+                let block_pos = IVec3::new(x, y, z) + offset;
+                let biome = biomes[(x + z * CHUNK_SIZE) as usize];
+                terrain_block(noise, caves, config, biome, block_pos)
+            })
+            .collect();
+        self.blocks = blocks.into_iter().collect();
+
+        self.gen_ores(config.seed);
+        self.gen_trees(config.seed);
+    }
+
+    /// Scatters ore veins through the stone with short random walks, each starting
+    /// anywhere between the bedrock and the top of its column's stone. The walks are
+    /// seeded from the chunk position so every chunk always gets the same veins.
+    fn gen_ores(&mut self, seed: u32) {
+        let offset = self.origin();
+        let mut rng = StdRng::seed_from_u64(column_hash(seed, offset.x, offset.z) as u64);
+
+        for (ore, veins, length) in ORES.iter() {
+            for _ in 0..*veins {
+                let column = offset
+                    + IVec3::new(
+                        rng.gen_range(0..CHUNK_SIZE),
+                        0,
+                        rng.gen_range(0..CHUNK_SIZE),
+                    );
+                let stone_top = (BEDROCK_HEIGHT..CHUNK_HEIGHT)
+                    .rev()
+                    .find(|y| self.get(column + IVec3::Y * *y) == BlockType::Stone);
+                let Some(stone_top) = stone_top else {
+                    continue;
+                };
+                let mut pos = column + IVec3::Y * rng.gen_range(BEDROCK_HEIGHT..=stone_top);
+
+                for _ in 0..*length {
+                    // Only ever replace stone.
+                    if self.get(pos) == BlockType::Stone {
+                        self.set(pos, *ore);
+                    }
+
+                    let mut step = IVec3::ZERO;
+                    step[rng.gen_range(0..3)] = if rng.gen() { 1 } else { -1 };
+                    pos += step;
+                }
+            }
+        }
+    }
+
+    /// Grows trees on some grass columns. Trees are kept entirely inside the chunk
+    /// (columns too close to the edge are skipped) so generating a chunk never has to
+    /// touch its neighbours, which might not exist yet.
+    fn gen_trees(&mut self, seed: u32) {
+        let offset = self.origin();
+
+        for x in 2..CHUNK_SIZE - 2 {
+            for z in 2..CHUNK_SIZE - 2 {
+                let hash = column_hash(seed, x + offset.x, z + offset.z);
+                let biome = self.biomes[(x + z * CHUNK_SIZE) as usize];
+                if hash % 100 >= biome.tree_chance() {
+                    continue;
+                }
+
+                // Find the top of the column.
+                let top = (0..CHUNK_HEIGHT)
+                    .rev()
+                    .map(|y| IVec3::new(x, y, z) + offset)
+                    .find(|pos| self.get(*pos) != BlockType::Air);
+
+                let ground = match top {
+                    Some(pos) if self.get(pos) == BlockType::Grass => pos,
+                    _ => continue,
+                };
+
+                let trunk_height = 4 + (hash >> 8) as i32 % 2;
+                let canopy_top = ground.y + trunk_height + 1;
+                if canopy_top >= CHUNK_HEIGHT {
+                    continue;
+                }
+
+                // Leaves: two wide layers around the top of the trunk and a small cap.
+                for y in canopy_top - 2..=canopy_top {
+                    let radius = if y == canopy_top { 1 } else { 2 };
+                    for lx in -radius..=radius {
+                        for lz in -radius..=radius {
+                            let pos = IVec3::new(ground.x + lx, y, ground.z + lz);
+                            if self.get(pos) == BlockType::Air {
+                                self.set(pos, BlockType::Leaves);
+                            }
+                        }
+                    }
+                }
+
+                for y in 1..=trunk_height {
+                    self.set(ground + IVec3::Y * y, BlockType::Wood);
+                }
+            }
+        }
+    }
+
+    /// Builds the chunk's opaque and transparent meshes. Vertices are relative to the
+    /// chunk, the UVs point into the texture atlas.
+    fn gen_meshes(
+        &mut self,
+        meshes: &mut ResMut<Assets<Mesh>>,
+        atlas_handle: Handle<TextureAtlas>,
+        atlas: &Res<Assets<TextureAtlas>>,
+        registry: &BlockRegistry,
+        view: &ChunkView,
+        greedy: bool,
+    ) {
+        let data = self.mesh_data(atlas.get(&atlas_handle).unwrap(), registry, view, greedy);
+        self.set_mesh(meshes, data);
+    }
+
+    /// The vertices for the chunk's mesh. Doesn't touch any assets, so it can run on
+    /// another thread.
+    fn mesh_data(
+        &self,
+        atlas: &TextureAtlas,
+        registry: &BlockRegistry,
+        view: &ChunkView,
+        greedy: bool,
+    ) -> ChunkMeshData {
+        if self.lod > 0 {
+            return self.lod_mesh_data(atlas, registry, view, 1 << self.lod);
+        }
+
+        let mut data = if greedy {
+            self.greedy_mesh_data(atlas, registry, view)
+        } else {
+            self.block_mesh_data(atlas, registry, view)
+        };
+        self.post_mesh_data(&mut data, atlas, registry);
+        data
+    }
+
+    /// Writes the chunk's meshes to a Wavefront OBJ, for looking at the meshing in other
+    /// tools. Positions are relative to the chunk and the UVs point into the atlas, so
+    /// it can be textured with `alpha_atlas.png`.
+    pub fn export_obj(
+        &self,
+        path: &Path,
+        atlas: &TextureAtlas,
+        registry: &BlockRegistry,
+        view: &ChunkView,
+        greedy: bool,
+    ) -> io::Result<()> {
+        let data = self.mesh_data(atlas, registry, view, greedy);
+        let mut out = BufWriter::new(File::create(path)?);
+
+        writeln!(out, "o chunk_{}_{}", self.position.x, self.position.y)?;
+        writeln!(out, "g opaque")?;
+        data.opaque.write_obj(&mut out, 1)?;
+        writeln!(out, "g cutout")?;
+        data.cutout
+            .write_obj(&mut out, 1 + data.opaque.positions.len())?;
+        writeln!(out, "g transparent")?;
+        data.transparent.write_obj(
+            &mut out,
+            1 + data.opaque.positions.len() + data.cutout.positions.len(),
+        )?;
+        out.flush()
+    }
+
+    fn set_mesh(&mut self, meshes: &mut Assets<Mesh>, data: ChunkMeshData) {
+        self.dirty = false;
+        self.mesh = meshes.add(data.opaque.into_mesh());
+        self.cutout_mesh =
+            (!data.cutout.positions.is_empty()).then(|| meshes.add(data.cutout.into_mesh()));
+        self.transparent_mesh = (!data.transparent.positions.is_empty())
+            .then(|| meshes.add(data.transparent.into_mesh()));
+    }
+
+    /// Whether the face of the block at `pos` facing `normal` can be seen. Solid blocks
+    /// hide it, apart from cutout ones like leaves, and so does more of the same block.
+    /// So water only shows where it meets air or a different block, and a clump of
+    /// leaves has no faces inside it.
+    fn face_visible(&self, view: &ChunkView, pos: IVec3, btype: BlockType, normal: IVec3) -> bool {
+        let (neighbour, _, opaque) = self.near(view, pos + normal);
+        !opaque && neighbour != btype
+    }
+
+    /// The block at a world position, and whether it's solid and opaque. Past the chunk's
+    /// sides it comes from the view's ring, above and below the world it's air.
+    fn near(&self, view: &ChunkView, world_pos: IVec3) -> (BlockType, bool, bool) {
+        let local = world_pos - self.origin();
+        match Self::index(local) {
+            Some(i) => (
+                self.blocks.get(i),
+                Occupancy::test(&self.occupancy.solid, i),
+                Occupancy::test(&self.occupancy.opaque, i),
+            ),
+            None => view.ring.get(local),
+        }
+    }
+
+    /// Colour a face is tinted with. Only the tops of biome tinted blocks get one.
+    fn face_tint(&self, def: &BlockDef, pos: IVec3, face: usize) -> Vec3 {
+        if def.biome_tint && FACE_NORMALS[face] == IVec3::Y {
+            self.grass_tints
+                .get(self.column(pos))
+                .copied()
+                .unwrap_or(Vec3::ONE)
+        } else {
+            Vec3::ONE
+        }
+    }
+
+    /// Ambient occlusion level of a face corner, 0 (darkest) to 3, from the three blocks
+    /// around the corner in front of the face. `front` is the block the face looks into,
+    /// `corner` points from it towards the corner: -1 or 1 on the two axes the face lies
+    /// in, 0 on the other.
+    fn corner_ao(&self, view: &ChunkView, front: IVec3, corner: IVec3) -> usize {
+        let solid = |pos: IVec3| self.near(view, pos).1;
+        let mut sides = (0..3).filter(|axis| corner[*axis] != 0).map(|axis| {
+            let mut side = IVec3::ZERO;
+            side[axis] = corner[axis];
+            solid(front + side)
+        });
+        let (side1, side2) = (sides.next().unwrap(), sides.next().unwrap());
+
+        // Two sides already hide the corner block.
+        if side1 && side2 {
+            0
+        } else {
+            3 - side1 as usize - side2 as usize - solid(front + corner) as usize
+        }
+    }
+
+    /// Adds the blocks that aren't cubes, which neither mesher handles.
+    fn post_mesh_data(
+        &self,
+        data: &mut ChunkMeshData,
+        atlas: &TextureAtlas,
+        registry: &BlockRegistry,
+    ) {
+        let offset = self.origin();
+        for (i, btype) in self.blocks.iter().enumerate() {
+            let def = registry.get(&btype);
+            if def.shape == BlockShape::Post {
+                let min = (self.position_of(i) - offset).as_vec3();
+                data.pass(def).push_post(atlas, def, min);
+            }
+        }
+    }
+
+    /// Meshes every block on its own, only building the faces that can be seen.
+    fn block_mesh_data(
+        &self,
+        atlas: &TextureAtlas,
+        registry: &BlockRegistry,
+        view: &ChunkView,
+    ) -> ChunkMeshData {
+        let offset = self.origin();
+
+        let quads = (0..CHUNK_VOLUME)
+            .into_par_iter()
+            .map(|i| (i, self.blocks.get(i)))
+            .filter(|(_, btype)| {
+                *btype != BlockType::Air && registry.get(btype).shape == BlockShape::Cube
+            })
+            .map(|(i, btype)| {
+                let mut quads = Vec::new();
+                let pos = self.position_of(i);
+
+                // Relative to the chunk, the entity transform places it in the world.
+                let min = (pos - offset).as_vec3();
+                let max = min + Vec3::ONE;
+
+                let block_verticies = box_corners(min, max);
+
+                let def = registry.get(&btype);
+
+                for (face, normal) in FACE_NORMALS.iter().enumerate() {
+                    if !self.face_visible(view, pos, btype, *normal) {
+                        continue;
+                    }
+
+                    // The image's V axis points down, so the bottom corners get max.y.
+                    let (tile, turned) = def.face_tile(face, self.axis(pos));
+                    let rect = tile_rect(atlas, tile);
+                    let uvs = [(0.0, 1.0), (1.0, 1.0), (1.0, 0.0), (0.0, 0.0)].map(|(u, v)| {
+                        let uv = if turned {
+                            Vec2::new(v, u)
+                        } else {
+                            Vec2::new(u, v)
+                        };
+                        rect.min + uv * rect.size()
+                    });
+                    let corner = |i: usize| (block_verticies[face * 4 + i], uvs[i]);
+                    let quad = [corner(0), corner(1), corner(2), corner(3)];
+
+                    // Which way each corner is from the middle of the face.
+                    let center = min + Vec3::splat(0.5);
+                    let ao = [0, 1, 2, 3].map(|i| {
+                        let corner = ((block_verticies[face * 4 + i] - center) * 2.0).as_ivec3();
+                        let corner = corner * (IVec3::ONE - normal.abs());
+                        self.corner_ao(view, pos + *normal, corner)
+                    });
+
+                    // Lit by whatever light is in the block the face looks into.
+                    let light = view.light(pos + *normal);
+                    let tint = self.face_tint(def, pos, face);
+                    quads.push((quad, rect, face, def, tint, light, ao));
+                }
+
+                quads
+            })
+            .collect::<Vec<_>>();
+
+        // Concatenate everything, push_quad keeps the indices pointing at the right vertices.
+        let mut data = ChunkMeshData::default();
+        for (corners, rect, face, def, tint, light, ao) in quads.into_iter().flatten() {
+            data.pass(def)
+                .push_quad(corners, rect, face, tint, light, ao);
+        }
+        data
+    }
+
+    /// Meshing for far away chunks: every `scale` wide cube of blocks is drawn as one big
+    /// block, of whichever type fills most of it. Blocks that aren't cubes are left out.
+    fn lod_mesh_data(
+        &self,
+        atlas: &TextureAtlas,
+        registry: &BlockRegistry,
+        view: &ChunkView,
+        scale: i32,
+    ) -> ChunkMeshData {
+        let offset = self.origin();
+        let cells = CHUNK_EXTENT / scale;
+
+        let mut grid = Vec::with_capacity((cells.x * cells.y * cells.z) as usize);
+        for y in 0..cells.y {
+            for z in 0..cells.z {
+                for x in 0..cells.x {
+                    let min = offset + IVec3::new(x, y, z) * scale;
+                    let mut counts: Vec<(BlockType, usize)> = Vec::new();
+                    for i in 0..scale * scale * scale {
+                        let pos =
+                            min + IVec3::new(i % scale, i / (scale * scale), i / scale % scale);
+                        let mut btype = self.get(pos);
+                        if registry.get(&btype).shape != BlockShape::Cube {
+                            btype = BlockType::Air;
+                        }
+                        match counts.iter_mut().find(|(counted, _)| *counted == btype) {
+                            Some((_, count)) => *count += 1,
+                            None => counts.push((btype, 1)),
+                        }
+                    }
+                    grid.push(counts.iter().max_by_key(|(_, count)| *count).unwrap().0);
+                }
+            }
+        }
+
+        // Whether the cell next to a face hides it. Past the chunk's sides there are no
+        // cells, only the view's ring of the neighbours' blocks, so the face is hidden
+        // only if every ring block beside it would hide it. Above and below is air.
+        let hides = |neighbour: BlockType, btype: BlockType| {
+            registry.get(&neighbour).hides_faces() || neighbour == btype
+        };
+        let covered = |cell: IVec3, btype: BlockType| {
+            if cell.y < 0 || cell.y >= cells.y {
+                return false;
+            }
+            if cell.cmpge(IVec3::ZERO).all() && cell.cmplt(cells).all() {
+                let i = cell.x + cell.z * cells.x + cell.y * cells.x * cells.z;
+                return hides(grid[i as usize], btype);
+            }
+            // Clamping squashes the cell onto the ring along the side it's past.
+            let min = cell * scale;
+            (0..scale * scale * scale).all(|i| {
+                let local = min + IVec3::new(i % scale, i / (scale * scale), i / scale % scale);
+                let local = local.clamp(IVec3::NEG_ONE, CHUNK_EXTENT);
+                hides(view.ring.get(local).0, btype)
+            })
+        };
+
+        let mut data = ChunkMeshData::default();
+        for (i, btype) in grid.iter().enumerate() {
+            if *btype == BlockType::Air {
+                continue;
+            }
+            let i = i as i32;
+            let cell = IVec3::new(i % cells.x, i / (cells.x * cells.z), i / cells.x % cells.z);
+            let pos = offset + cell * scale;
+            let min = (cell * scale).as_vec3();
+            let corners = box_corners(min, min + Vec3::splat(scale as f32));
+            let def = registry.get(btype);
+
+            for (face, normal) in FACE_NORMALS.iter().enumerate() {
+                if covered(cell + *normal, *btype) {
+                    continue;
+                }
+
+                // One tile stretched over the whole face, it's too far away to tell.
+                let rect = tile_rect(atlas, def.faces[face]);
+                let uvs = [
+                    Vec2::new(rect.min.x, rect.max.y),
+                    rect.max,
+                    Vec2::new(rect.max.x, rect.min.y),
+                    rect.min,
+                ];
+                let corner = |i: usize| (corners[face * 4 + i], uvs[i]);
+                let quad = [corner(0), corner(1), corner(2), corner(3)];
+
+                // Lit by the block just past the cell's corner on this side.
+                let beside = if normal.cmpgt(IVec3::ZERO).any() {
+                    *normal * scale
+                } else {
+                    *normal
+                };
+                let light = view.light(pos + beside);
+                let tint = self.face_tint(def, pos, face);
+                data.pass(def)
+                    .push_quad(quad, rect, face, tint, light, [3; 4]);
+            }
+        }
+        data
+    }
+
+    /// Greedy meshing: visible faces of the same block type that share a plane are merged
+    /// into bigger quads.
+    ///
+    /// UVs keep counting up across a merged quad (one tile per block) so the texture
+    /// repeats. `ChunkMaterial` and the water shader wrap them back into the quad's tile.
+    fn greedy_mesh_data(
+        &self,
+        atlas: &TextureAtlas,
+        registry: &BlockRegistry,
+        view: &ChunkView,
+    ) -> ChunkMeshData {
+        // What faces have to share to be merged: block, axis, tint, light and corner
+        // occlusion.
+        type FaceKey = (BlockType, BlockAxis, Vec3, [u8; 2], [usize; 4]);
+        let offset = self.origin();
+        let mut data = ChunkMeshData::default();
+
+        for (face, normal) in FACE_NORMALS.iter().enumerate() {
+            // The axis the face points along, and the two axes the face lies in.
+            let axis = if normal.x != 0 {
+                0
+            } else if normal.y != 0 {
+                1
+            } else {
+                2
+            };
+            let (u_axis, v_axis) = ((axis + 1) % 3, (axis + 2) % 3);
+            let positive = normal[axis] > 0;
+            let (mut u_step, mut v_step) = (IVec3::ZERO, IVec3::ZERO);
+            u_step[u_axis] = 1;
+            v_step[v_axis] = 1;
+            // The chunk is taller than it's wide, so each axis has its own size.
+            let (slices, width_u, height_v) = (
+                CHUNK_EXTENT[axis],
+                CHUNK_EXTENT[u_axis],
+                CHUNK_EXTENT[v_axis],
+            );
+            let index = |u: i32, v: i32| (u + v * width_u) as usize;
+
+            for slice in 0..slices {
+                // Which faces in this slice are visible, what they're made of, their tint
+                // and light, and the occlusion at their corners. Only faces that match on all
+                // of it get merged, so the corners of a merged quad can use any face's
+                // occlusion.
+                let mut mask: Vec<Option<FaceKey>> = vec![None; (width_u * height_v) as usize];
+                for v in 0..height_v {
+                    for u in 0..width_u {
+                        let mut pos = offset;
+                        pos[axis] += slice;
+                        pos[u_axis] += u;
+                        pos[v_axis] += v;
+
+                        let btype = self.get(pos);
+                        let def = registry.get(&btype);
+                        if btype != BlockType::Air
+                            && def.shape == BlockShape::Cube
+                            && self.face_visible(view, pos, btype, *normal)
+                        {
+                            let tint = self.face_tint(def, pos, face);
+                            let light = view.light(pos + *normal);
+                            // Corners in (u, v) order: (0, 0), (1, 0), (1, 1), (0, 1).
+                            let ao = [
+                                -u_step - v_step,
+                                u_step - v_step,
+                                u_step + v_step,
+                                v_step - u_step,
+                            ]
+                            .map(|corner| self.corner_ao(view, pos + *normal, corner));
+                            mask[index(u, v)] = Some((btype, self.axis(pos), tint, light, ao));
+                        }
+                    }
+                }
+
+                // Grow each face as wide as possible, then as tall as possible.
+                for v in 0..height_v {
+                    let mut u = 0;
+                    while u < width_u {
+                        let key = match mask[index(u, v)] {
+                            Some(key) => key,
+                            None => {
+                                u += 1;
+                                continue;
+                            }
+                        };
+
+                        let mut width = 1;
+                        while u + width < width_u && mask[index(u + width, v)] == Some(key) {
+                            width += 1;
+                        }
+
+                        let mut height = 1;
+                        while v + height < height_v
+                            && (u..u + width).all(|du| mask[index(du, v + height)] == Some(key))
+                        {
+                            height += 1;
+                        }
+
+                        for dv in v..v + height {
+                            for du in u..u + width {
+                                mask[index(du, dv)] = None;
+                            }
+                        }
+
+                        let mut origin = Vec3::ZERO;
+                        origin[axis] = slice as f32 + if positive { 1.0 } else { 0.0 };
+                        origin[u_axis] = u as f32;
+                        origin[v_axis] = v as f32;
+                        let mut du = Vec3::ZERO;
+                        du[u_axis] = width as f32;
+                        let mut dv = Vec3::ZERO;
+                        dv[v_axis] = height as f32;
+
+                        let (btype, axis, tint, light, ao) = key;
+                        let def = registry.get(&btype);
+                        let (tile, turned) = def.face_tile(face, axis);
+                        let rect = tile_rect(atlas, tile);
+
+                        // u x v points along the normal, so swap the winding for the back faces.
+                        let corners = if positive {
+                            [origin, origin + du, origin + du + dv, origin + dv]
+                        } else {
+                            [origin, origin + dv, origin + du + dv, origin + du]
+                        };
+
+                        // Tiles are laid the way the per block mesher lays them, counting up
+                        // from whichever corner the top left of the texture goes in.
+                        let (tex_u, tex_v) = FACE_TEXTURE_AXES[face];
+                        let along = corners.map(|c| Vec2::new(c.dot(tex_u), c.dot(tex_v)));
+                        let start = along
+                            .iter()
+                            .fold(Vec2::splat(f32::INFINITY), |min, a| min.min(*a));
+                        let quad = [0, 1, 2, 3].map(|i| {
+                            let uv = along[i] - start;
+                            let uv = if turned { Vec2::new(uv.y, uv.x) } else { uv };
+                            (corners[i], rect.min + uv * rect.size())
+                        });
+
+                        let ao = if positive {
+                            ao
+                        } else {
+                            [ao[0], ao[3], ao[2], ao[1]]
+                        };
+                        data.pass(def).push_quad(quad, rect, face, tint, light, ao);
+                        u += width;
+                    }
+                }
+            }
+        }
+
+        data
+    }
+}
+// ---------------------------
+
+// ---------- World ----------
+/// How a world's terrain is made
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum GenMode {
+    #[default]
+    Noise, // Hills, caves, ores and trees
+    Flat, // `FLAT_LAYERS` everywhere, for building and testing
+}
+
+impl GenMode {
+    /// Case insensitive, `None` for anything that isn't a mode.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "noise" => Some(Self::Noise),
+            "flat" => Some(Self::Flat),
+            _ => None,
+        }
+    }
+}
+
+/// Sinks the terrain into the sea away from the origin, so the world is one island
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Island {
+    pub radius: f64,    // In blocks, the land is all gone past this
+    pub steepness: f64, // Higher keeps the land high until closer to the coast
+}
+
+impl Island {
+    pub fn new(radius: f64) -> Self {
+        Self {
+            radius,
+            steepness: ISLAND_STEEPNESS,
+        }
+    }
+
+    /// Terrain height multiplier for a column, 1.0 at the origin and 0.0 past the radius.
+    pub fn falloff(&self, x: i32, z: i32) -> f64 {
+        let distance = (x as f64).hypot(z as f64) / self.radius;
+        (1.0 - distance.powf(self.steepness)).clamp(0.0, 1.0)
+    }
+}
+
+/// Settings that identify a world
+#[derive(Resource, Clone)]
+pub struct WorldConfig {
+    pub name: String, // Used for the save directory and shown in the UI
+    pub seed: u32,
+    pub horizontal_scale: f64, // Noise frequency, lower stretches hills out
+    pub vertical_scale: f64,   // Terrain height where the noise peaks, in blocks
+    pub snow_line: f64,        // Terrain height above which the surface is snow, in blocks
+    pub gen_mode: GenMode,
+    pub island: Option<Island>, // None for land that goes on forever
+    pub water_level: i32,       // The sea fills everything up to and including this y
+}
+
+impl Default for WorldConfig {
+    fn default() -> Self {
+        Self {
+            name: WorldConfig::seed_name(SEED),
+            seed: SEED,
+            horizontal_scale: 1.0,
+            vertical_scale: MAX_HEIGHT,
+            snow_line: SNOW_LINE,
+            gen_mode: GenMode::default(),
+            island: None,
+            water_level: WATER_LEVEL,
+        }
+    }
+}
+
+impl WorldConfig {
+    /// Numbers are used as the seed directly, any other text is hashed (FNV-1a) into one.
+    pub fn from_seed_string(seed: &str) -> Self {
+        let seed = seed.trim().parse::<u32>().unwrap_or_else(|_| {
+            seed.bytes().fold(0x811c9dc5, |hash: u32, byte| {
+                (hash ^ byte as u32).wrapping_mul(0x01000193)
+            })
+        });
+
+        Self {
+            name: Self::seed_name(seed),
+            seed,
+            ..Default::default()
+        }
+    }
+
+    /// Name of a world that wasn't given one, so worlds with different seeds are saved
+    /// apart.
+    pub fn seed_name(seed: u32) -> String {
+        format!("seed_{}", seed)
+    }
+}
+
+/// Starting points for `TerrainParams`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TerrainPreset {
+    Plains,
+    #[default]
+    Hills,
+    Mountains,
+}
+
+impl TerrainPreset {
+    /// Case insensitive, `None` for anything that isn't a preset.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "plains" => Some(Self::Plains),
+            "hills" => Some(Self::Hills),
+            "mountains" => Some(Self::Mountains),
+            _ => None,
+        }
+    }
+}
+
+/// Shape of the terrain height noise, read once when the map is built
+#[derive(Resource, Clone, Copy, Debug, PartialEq)]
+pub struct TerrainParams {
+    pub octaves: usize,
+    pub frequency: f64,   // Of the first octave, higher packs in more hills
+    pub lacunarity: f64,  // Frequency multiplier from one octave to the next
+    pub persistence: f64, // Amplitude multiplier from one octave to the next, higher is rougher
+    pub amplitude: f64,   // Stretches the noise around its middle, higher means taller hills
+}
+
+impl TerrainParams {
+    pub fn preset(preset: TerrainPreset) -> Self {
+        match preset {
+            TerrainPreset::Plains => Self {
+                octaves: 4,
+                frequency: 0.5,
+                lacunarity: 2.0,
+                persistence: 0.35,
+                amplitude: 0.5,
+            },
+            // Fbm's own defaults, what the terrain has always looked like.
+            TerrainPreset::Hills => Self {
+                octaves: Fbm::<Perlin>::DEFAULT_OCTAVE_COUNT,
+                frequency: Fbm::<Perlin>::DEFAULT_FREQUENCY,
+                lacunarity: Fbm::<Perlin>::DEFAULT_LACUNARITY,
+                persistence: Fbm::<Perlin>::DEFAULT_PERSISTENCE,
+                amplitude: 1.0,
+            },
+            TerrainPreset::Mountains => Self {
+                octaves: 8,
+                frequency: 1.2,
+                lacunarity: 2.2,
+                persistence: 0.55,
+                amplitude: 1.6,
+            },
+        }
+    }
+}
+
+impl Default for TerrainParams {
+    fn default() -> Self {
+        Self::preset(TerrainPreset::default())
+    }
+}
+
+/// How chunks get turned into meshes
+#[derive(Resource)]
+pub struct Meshing {
+    pub greedy: bool,      // Merge faces instead of meshing every block on its own
+    pub lod_distance: i32, // In chunks, 0 meshes everything at full detail
+}
+
+impl Default for Meshing {
+    fn default() -> Self {
+        Self {
+            greedy: true,
+            lod_distance: LOD_DISTANCE,
+        }
+    }
+}
+
+impl Meshing {
+    /// Level of detail for a chunk `offset` chunks from the player's. Every
+    /// `lod_distance` further out halves the resolution, up to MAX_LOD.
+    pub fn lod(&self, offset: IVec2) -> u8 {
+        if self.lod_distance <= 0 {
+            return 0;
+        }
+        let distance = (offset.dot(offset) as f32).sqrt() as i32;
+        (distance / self.lod_distance).min(MAX_LOD as i32) as u8
+    }
+}
+
+/// How many chunk entities there are, and how many `cull_chunks` left visible
+#[derive(Resource, Default)]
+pub struct ChunkCulling {
+    pub visible: usize,
+    pub total: usize,
+}
+
+/// Where the player was and which way they were looking
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct PlayerSave {
+    pub translation: Vec3,
+    pub rotation: Quat,
+}
+
+/// What gets written to a world's `LEVEL_FILE`, after `SAVE_MAGIC`. The chunks go in
+/// region files next to it.
+#[derive(Serialize, Deserialize)]
+struct LevelData {
+    seed: u32,
+    water_level: i32,
+    player: Option<PlayerSave>,
+}
+
+/// Radius around the player's chunk that gets loaded, in chunks
+#[derive(Resource)]
+pub struct RenderDistance(pub i32);
+
+impl Default for RenderDistance {
+    fn default() -> Self {
+        Self(RENDER_DISTANCE)
+    }
+}
+
+/// How many chunks that left the render distance are kept around, ready to come back
+#[derive(Resource)]
+pub struct ChunkCacheSize(pub usize);
+
+impl Default for ChunkCacheSize {
+    fn default() -> Self {
+        Self(CACHE_SIZE)
+    }
+}
+
+/// How many chunks can be loaded, and how many generated chunks spawned, in one frame
+#[derive(Resource)]
+pub struct ChunkBudget {
+    pub per_frame: usize,
+}
+
+impl Default for ChunkBudget {
+    fn default() -> Self {
+        Self { per_frame: 2 }
+    }
+}
+
+/// How often queued block updates run
+#[derive(Resource)]
+pub struct BlockTick(Timer);
+
+impl Default for BlockTick {
+    fn default() -> Self {
+        Self(Timer::from_seconds(BLOCK_TICK, TimerMode::Repeating))
+    }
+}
+
+/// The materials every chunk shares. All sample the atlas, blocks only differ by UVs.
+#[derive(Clone, Default)]
+pub struct ChunkMaterials {
+    pub opaque: Handle<ChunkMaterial>,
+    pub cutout: Handle<ChunkMaterial>, // Alpha masked, for the cutout mesh
+    pub water: Handle<WaterMaterial>,  // Alpha blended and animated, for the transparent mesh
+}
+
+impl ChunkMaterials {
+    /// Adds the materials. Call this once, every chunk reuses the handles.
+    pub fn new(
+        materials: &mut Assets<ChunkMaterial>,
+        water_materials: &mut Assets<WaterMaterial>,
+        atlas: Handle<Image>,
+    ) -> Self {
+        Self {
+            opaque: materials.add(ChunkMaterial::new(atlas.clone(), AlphaMode::Opaque)),
+            // Leaves are either fully there or not at all, so a mask does. It writes depth
+            // and needs no sorting, unlike blending.
+            cutout: materials.add(ChunkMaterial::new(atlas.clone(), AlphaMode::Mask(0.5))),
+            water: water_materials.add(WaterMaterial::new(atlas)),
+        }
+    }
+}
+
+#[derive(Resource)]
+pub struct Map {
+    chunks: HashMap<IVec2, Chunk>,
+    cache: HashMap<IVec2, Chunk>, // Out of range chunks, meshes and all, up to ChunkCacheSize
+    stored: HashMap<IVec2, Chunk>, // Modified chunks out of range with nowhere to be saved yet
+    save_dir: Option<PathBuf>,    // Where the world was loaded from or saved to
+    on_disk: HashSet<IVec2>,      // Chunks in that directory's region files
+    seed: u32,
+    config: WorldConfig, // What the map was made with, for working out ungenerated columns
+    generating: HashSet<IVec2>, // Chunks with a ChunkTask in flight
+    entities: HashMap<IVec2, Entity>, // What each spawned chunk is drawn with
+    updates: HashSet<IVec3>, // Blocks next to an edit, checked on the next BlockTick
+    flows: VecDeque<IVec3>, // Cells water might flow into or out of, a few per BlockTick
+    noise: Arc<HeightNoise>, // Read only, shared with the generation tasks
+    caves: Arc<Fbm<Perlin>>, // Sampled in 3D
+    climate: Arc<Climate>,
+    pub blocks: BlockRegistry, // Same as the resource, kept here for meshing and collisions
+    pub texture_atlas: Handle<TextureAtlas>,
+    pub materials: ChunkMaterials,
+}
+
+impl FromWorld for Map {
+    fn from_world(world: &mut World) -> Self {
+        let config = world
+            .get_resource_or_insert_with(WorldConfig::default)
+            .clone();
+        let params = *world.get_resource_or_insert_with(TerrainParams::default);
+        let blocks = world
+            .get_resource_or_insert_with(BlockRegistry::default)
+            .clone();
+        Map::new(config, &params, blocks)
+    }
+}
+
+impl Map {
+    /// An empty map for a world, with nothing loaded yet.
+    fn new(config: WorldConfig, params: &TerrainParams, blocks: BlockRegistry) -> Self {
+        let seed = config.seed;
+
+        Map {
+            chunks: HashMap::new(),
+            cache: HashMap::new(),
+            stored: HashMap::new(),
+            save_dir: None,
+            on_disk: HashSet::new(),
+            seed,
+            config,
+            generating: HashSet::new(),
+            entities: HashMap::new(),
+            updates: HashSet::new(),
+            flows: VecDeque::new(),
+            noise: Arc::new(HeightNoise::new(seed, params)),
+            caves: Arc::new(Fbm::<Perlin>::new(seed.wrapping_add(1))),
+            climate: Arc::new(Climate::new(seed)),
+            blocks,
+            texture_atlas: Handle::default(),
+            materials: ChunkMaterials::default(),
+        }
+    }
+
+    /// Throws every chunk away, edits included, and starts over with a new world. The
+    /// atlas and materials are kept.
+    fn regenerate(&mut self, config: &WorldConfig, params: &TerrainParams) {
+        let fresh = Map::new(config.clone(), params, self.blocks.clone());
+        *self = Map {
+            texture_atlas: self.texture_atlas.clone(),
+            materials: self.materials.clone(),
+            ..fresh
+        };
+    }
+
+    /// The entity a loaded chunk is drawn with, if it's been spawned yet
+    pub fn chunk_entity(&self, chunk_pos: IVec2) -> Option<Entity> {
+        self.entities.get(&chunk_pos).copied()
+    }
+
+    /// Position, in chunks, of the chunk that owns the given block.
+    pub fn world_to_chunk(world_pos: IVec3) -> IVec2 {
+        IVec2::new(
+            world_pos.x.div_euclid(CHUNK_SIZE),
+            world_pos.z.div_euclid(CHUNK_SIZE),
+        )
+    }
+
+    /// How many chunks within `radius` of `center` are loaded, and how many there are.
+    pub fn loaded_within(&self, center: IVec2, radius: i32) -> (usize, usize) {
+        let mut loaded = 0;
+        let mut total = 0;
+        for x in -radius..=radius {
+            for z in -radius..=radius {
+                let chunk_pos = center + IVec2::new(x, z);
+                if within_render_distance(chunk_pos, center, radius) {
+                    total += 1;
+                    loaded += self.chunks.contains_key(&chunk_pos) as usize;
+                }
+            }
+        }
+        (loaded, total)
+    }
+
+    /// A loaded or cached chunk, edits have to reach both.
+    fn chunk(&self, chunk_pos: IVec2) -> Option<&Chunk> {
+        self.chunks
+            .get(&chunk_pos)
+            .or_else(|| self.cache.get(&chunk_pos))
+    }
+
+    fn chunk_mut(&mut self, chunk_pos: IVec2) -> Option<&mut Chunk> {
+        match self.chunks.get_mut(&chunk_pos) {
+            Some(chunk) => Some(chunk),
+            None => self.cache.get_mut(&chunk_pos),
+        }
+    }
+
+    /// The block at a world position. `None` for air, or if the chunk isn't in memory.
+    pub fn get_block(&self, world_pos: IVec3) -> Option<BlockType> {
+        self.chunk(Self::world_to_chunk(world_pos))
+            .map(|chunk| chunk.get(world_pos))
+            .filter(|btype| *btype != BlockType::Air)
+    }
+
+    /// The y of the topmost solid block in a column, or of the water surface if that's
+    /// higher. Columns that aren't in memory are worked out from the height noise, which
+    /// doesn't see caves or trees.
+    pub fn surface_height(&self, x: i32, z: i32) -> i32 {
+        let chunk_pos = Self::world_to_chunk(IVec3::new(x, 0, z));
+        let chunk = self
+            .chunk(chunk_pos)
+            .or_else(|| self.stored.get(&chunk_pos));
+
+        match chunk {
+            Some(chunk) => (0..CHUNK_HEIGHT)
+                .rev()
+                .find(|y| {
+                    let btype = chunk.get(IVec3::new(x, *y, z));
+                    btype == BlockType::Water || self.blocks.get(&btype).solid
+                })
+                .unwrap_or(0),
+            None if self.config.gen_mode == GenMode::Flat => {
+                (FLAT_HEIGHT - 1).max(self.config.water_level)
+            }
+            None => {
+                // Blocks fill everything below the height, and the sea everything below
+                // the water level.
+                let height = column_height(&self.noise, &self.config, x, z);
+                (height.ceil() as i32 - 1).clamp(self.config.water_level, CHUNK_HEIGHT - 1)
+            }
+        }
+    }
+
+    /// Whether a column is sea all the way up to the surface, going by the same blocks or
+    /// noise as `surface_height`.
+    pub fn is_ocean(&self, x: i32, z: i32) -> bool {
+        let chunk_pos = Self::world_to_chunk(IVec3::new(x, 0, z));
+        let chunk = self
+            .chunk(chunk_pos)
+            .or_else(|| self.stored.get(&chunk_pos));
+
+        match chunk {
+            Some(chunk) => {
+                let top = IVec3::new(x, self.surface_height(x, z), z);
+                chunk.get(top) == BlockType::Water
+            }
+            None if self.config.gen_mode == GenMode::Flat => self.config.water_level >= FLAT_HEIGHT,
+            None => {
+                let height = column_height(&self.noise, &self.config, x, z);
+                height.ceil() as i32 - 1 < self.config.water_level
+            }
+        }
+    }
+
+    /// The biome of the column at a world position. Chunks that aren't in memory are
+    /// worked out from the climate noise.
+    pub fn biome(&self, world_pos: IVec3) -> Biome {
+        match self.chunk(Self::world_to_chunk(world_pos)) {
+            Some(chunk) if !chunk.biomes.is_empty() => chunk.biome(world_pos),
+            _ => self.climate.biome(world_pos.x, world_pos.z),
+        }
+    }
+
+    /// Whether the edit tools may change the block at a world position. Air and unloaded
+    /// chunks count as breakable, `set_block` sorts those out.
+    pub fn can_break(&self, world_pos: IVec3) -> bool {
+        self.get_block(world_pos)
+            .is_none_or(|btype| !self.blocks.get(&btype).unbreakable)
+    }
+
+    /// Hardness of the block at a world position, 0 if its chunk isn't loaded
+    pub fn hardness(&self, world_pos: IVec3) -> f32 {
+        self.get_block(world_pos)
+            .map_or(0.0, |btype| self.blocks.get(&btype).hardness)
+    }
+
+    /// Whether there's a solid block at a world position. Unloaded chunks count as empty.
+    pub fn is_solid(&self, world_pos: IVec3) -> bool {
+        self.chunk(Self::world_to_chunk(world_pos))
+            .is_some_and(|chunk| chunk.is_solid(world_pos))
+    }
+
+    /// Whether rays stop at a world position: any block but air and see-through ones you
+    /// can move through, like water.
+    pub fn is_targetable(&self, world_pos: IVec3) -> bool {
+        self.get_block(world_pos).is_some_and(|btype| {
+            let def = self.blocks.get(&btype);
+            def.solid || !def.transparent
+        })
+    }
+
+    /// The height noise the terrain is built from, as a grayscale image. White is high,
+    /// x runs to the right and z down, with the world origin in the middle.
+    pub fn height_map_image(&self) -> Image {
+        let (width, depth) = (HEIGHT_MAP_IMAGE_SIZE, HEIGHT_MAP_IMAGE_SIZE);
+        let mut data = Vec::with_capacity(width * depth * 4);
+        let half = (HEIGHT_MAP_IMAGE_SIZE / 2) as i32;
+        for z in -half..half {
+            for x in -half..half {
+                let (x, z) = (x * HEIGHT_MAP_IMAGE_STEP, z * HEIGHT_MAP_IMAGE_STEP);
+                let noise = self.noise.get(
+                    x as f64 * self.config.horizontal_scale,
+                    z as f64 * self.config.horizontal_scale,
+                );
+                // Same -1..1 to 0..1 as column_height.
+                let value = (noise + 1.0) / 2.0;
+                let gray = (value.clamp(0.0, 1.0) * 255.0) as u8;
+                data.extend([gray, gray, gray, 255]);
+            }
+        }
+
+        Image::new(
+            Extent3d {
+                width: width as u32,
+                height: depth as u32,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            data,
+            TextureFormat::Rgba8UnormSrgb,
+        )
+    }
+
+    /// Exports a loaded chunk's meshes, see `Chunk::export_obj`.
+    pub fn export_obj(
+        &self,
+        chunk_pos: IVec2,
+        path: &Path,
+        atlas: &TextureAtlas,
+        greedy: bool,
+    ) -> io::Result<()> {
+        let Some(chunk) = self.chunk(chunk_pos) else {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "chunk isn't loaded",
+            ));
+        };
+        let view = ChunkView::from_map(self, chunk_pos);
+        chunk.export_obj(path, atlas, &self.blocks, &view, greedy)
+    }
+
+    /// Marks every chunk for re-meshing, e.g. after changing how meshes are built.
+    pub fn remesh_all(&mut self) {
+        for chunk in self.chunks.values_mut().chain(self.cache.values_mut()) {
+            chunk.dirty = true;
+        }
+    }
+
+    /// Sets the block and marks its chunk, and the neighbouring one if it's on the edge, for
+    /// re-meshing. Setting `Air` removes the block.
+    /// The block and the one above it get queued for an update, in case they now fall, and
+    /// the block for water to flow into or out of.
+    /// Setting a block to what's already there does nothing, and counts as set.
+    /// Returns false if the chunk isn't loaded or the position is out of bounds.
+    pub fn set_block(&mut self, world_pos: IVec3, btype: BlockType) -> bool {
+        self.set_block_along(world_pos, btype, BlockAxis::Y)
+    }
+
+    /// Same as `set_block`, but orientable blocks are turned to run along `axis`.
+    pub fn set_block_along(&mut self, world_pos: IVec3, btype: BlockType, axis: BlockAxis) -> bool {
+        if world_pos.y < 0 || world_pos.y >= CHUNK_HEIGHT {
+            return false;
+        }
+
+        let def = self.blocks.get(&btype);
+        let chunk_pos = Self::world_to_chunk(world_pos);
+        // Not chunk_mut, so the registry can still be borrowed.
+        let Some(chunk) = self
+            .chunks
+            .get_mut(&chunk_pos)
+            .or_else(|| self.cache.get_mut(&chunk_pos))
+        else {
+            return false;
+        };
+        if chunk.get(world_pos) == btype && (!def.orientable || chunk.axis(world_pos) == axis) {
+            return true;
+        }
+        chunk.set(world_pos, btype);
+        if chunk.occupancy.is_empty() {
+            chunk.gen_occupancy(&self.blocks);
+        } else if let Some(i) = Chunk::index(world_pos - chunk.origin()) {
+            chunk.occupancy.set(i, def);
+        }
+        if def.orientable {
+            chunk.set_axis(world_pos, axis);
+        }
+        chunk.modified = true;
+        self.mark_dirty(world_pos);
+        self.updates.insert(world_pos);
+        self.updates.insert(world_pos + IVec3::Y);
+        self.flows.push_back(world_pos);
+        self.update_light(world_pos);
+        true
+    }
+
+    /// Checks up to `limit` of the queued water cells. Air next to water (above or beside
+    /// it) at or below the world's water level fills with water, and water queues the air
+    /// below and beside it, so a flood spreads a ring of cells at a time.
+    ///
+    /// This always ends: water only ever replaces air, never goes above the water level or
+    /// into chunks that aren't loaded, and is never taken away again here. Each cell can
+    /// only fill once, and cells are only queued around ones that just filled, so the
+    /// queue runs dry once the space below the water level is full.
+    pub fn flow_water(&mut self, limit: usize) {
+        const SIDES: [IVec3; 4] = [IVec3::X, IVec3::NEG_X, IVec3::Z, IVec3::NEG_Z];
+        let water_level = self.config.water_level;
+        // Unlike `get_block`, air is only air in a loaded chunk.
+        let block = |map: &Map, pos: IVec3| {
+            map.chunk(Self::world_to_chunk(pos))
+                .filter(|_| (0..CHUNK_HEIGHT).contains(&pos.y))
+                .map(|chunk| chunk.get(pos))
+        };
+
+        for _ in 0..limit {
+            let Some(pos) = self.flows.pop_front() else {
+                return;
+            };
+            match block(self, pos) {
+                Some(BlockType::Water) => {
+                    for next in SIDES.iter().chain([IVec3::NEG_Y].iter()) {
+                        let next = pos + *next;
+                        if next.y <= water_level && block(self, next) == Some(BlockType::Air) {
+                            self.flows.push_back(next);
+                        }
+                    }
+                }
+                Some(BlockType::Air) if pos.y <= water_level => {
+                    let wet = SIDES
+                        .iter()
+                        .chain([IVec3::Y].iter())
+                        .any(|side| self.get_block(pos + *side) == Some(BlockType::Water));
+                    if wet {
+                        // Queues the new water itself, to spread on from.
+                        self.set_block(pos, BlockType::Water);
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+
+    /// Which of the chunks next to one are loaded, along `CHUNK_SIDES`
+    fn loaded_sides(&self, chunk_pos: IVec2) -> [bool; 4] {
+        CHUNK_SIDES.map(|side| self.chunks.contains_key(&(chunk_pos + side)))
+    }
+
+    /// Marks the loaded chunks next to one for meshing, so their edges are culled against
+    /// it once it's there.
+    fn mark_sides_dirty(&mut self, chunk_pos: IVec2) {
+        for side in CHUNK_SIDES {
+            if let Some(chunk) = self.chunks.get_mut(&(chunk_pos + side)) {
+                chunk.dirty = true;
+            }
+        }
+    }
+
+    /// Marks the chunk holding a block for meshing.
+    fn mark_dirty(&mut self, world_pos: IVec3) {
+        let chunk_pos = Self::world_to_chunk(world_pos);
+        if let Some(chunk) = self.chunk_mut(chunk_pos) {
+            chunk.dirty = true;
+        }
+
+        // Blocks on the edge are next to the neighbouring chunk's faces too.
+        let local = world_pos - IVec3::new(chunk_pos.x, 0, chunk_pos.y) * CHUNK_SIZE;
+        for (axis, step) in [(0, IVec2::X), (2, IVec2::Y)] {
+            let neighbour = if local[axis] == 0 {
+                chunk_pos - step
+            } else if local[axis] == CHUNK_SIZE - 1 {
+                chunk_pos + step
+            } else {
+                continue;
+            };
+            if let Some(chunk) = self.chunk_mut(neighbour) {
+                chunk.dirty = true;
+            }
+        }
+    }
+
+    /// Writes every modified chunk in memory to the region files in `dir`, and the seed
+    /// and player to its `LEVEL_FILE`. Untouched chunks are left out, they can be
+    /// generated again from the seed. From then on chunks leaving memory are written
+    /// there as they go.
+    pub fn save(&mut self, dir: &Path, player: Option<PlayerSave>) -> io::Result<()> {
+        let regions_dir = dir.join(REGION_DIR);
+        fs::create_dir_all(&regions_dir)?;
+
+        let mut regions: HashMap<IVec2, Vec<&Chunk>> = HashMap::new();
+        for chunk in self
+            .chunks
+            .values()
+            .chain(self.cache.values())
+            .chain(self.stored.values())
+            .filter(|chunk| chunk.modified)
+        {
+            regions
+                .entry(region_of(chunk.position))
+                .or_default()
+                .push(chunk);
+        }
+        for (region, chunks) in regions.iter() {
+            write_region(&regions_dir, *region, chunks)?;
+        }
+        let saved: Vec<IVec2> = regions
+            .values()
+            .flatten()
+            .map(|chunk| chunk.position)
+            .collect();
+        self.on_disk.extend(saved);
+        // They're read back from the regions when they're needed again.
+        self.stored.clear();
+
+        let data = LevelData {
+            seed: self.seed,
+            water_level: self.config.water_level,
+            player,
+        };
+        let mut file = BufWriter::new(File::create(dir.join(LEVEL_FILE))?);
+        file.write_all(SAVE_MAGIC)?;
+        bincode::serialize_into(file, &data).map_err(io::Error::other)?;
+
+        self.save_dir = Some(dir.to_path_buf());
+        Ok(())
+    }
+
+    /// Top of the sea, which a loaded world takes from its save
+    pub fn water_level(&self) -> i32 {
+        self.config.water_level
+    }
+
+    /// Whether a world has been saved in `dir`
+    pub fn has_save(dir: &Path) -> bool {
+        dir.join(LEVEL_FILE).exists()
+    }
+
+    /// Opens the world saved in `dir`, and returns where the player was if that was saved
+    /// too. The world keeps the water level it was saved with, see `water_level`.
+    /// Chunks are read from the region files as the player gets close, and written back
+    /// as they leave memory, so this has to run before any chunks are generated.
+    pub fn load(&mut self, dir: &Path) -> io::Result<Option<PlayerSave>> {
+        self.save_dir = Some(dir.to_path_buf());
+        self.on_disk = region_index(&dir.join(REGION_DIR))?;
+
+        let level = dir.join(LEVEL_FILE);
+        if !level.exists() {
+            return Ok(None);
+        }
+        let mut file = BufReader::new(File::open(&level)?);
+        let mut magic = [0; SAVE_MAGIC.len()];
+        file.read_exact(&mut magic)?;
+        if magic != *SAVE_MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a level file",
+            ));
+        }
+        let data: LevelData = bincode::deserialize_from(file).map_err(io::Error::other)?;
+
+        // The saved blocks win, only chunks that were never saved use the current seed.
+        if data.seed != self.seed {
+            warn!(
+                "{} was saved with seed {}, not {}. New terrain won't line up with it.",
+                dir.display(),
+                data.seed,
+                self.seed
+            );
+        }
+        // Unlike the seed the sea can follow the save, so it meets the saved shores.
+        self.config.water_level = data.water_level;
+        Ok(data.player)
+    }
+
+    /// A chunk from the region files, if it was ever written to one. Comes back dirty and
+    /// without light, like stored chunks.
+    fn read_chunk(&self, chunk_pos: IVec2) -> Option<Chunk> {
+        if !self.on_disk.contains(&chunk_pos) {
+            return None;
+        }
+        let dir = self.save_dir.as_ref()?.join(REGION_DIR);
+        match read_region_chunk(&dir, chunk_pos) {
+            Ok(chunk) => chunk.map(|mut chunk| {
+                chunk.gen_biomes(&self.climate);
+                chunk.dirty = true;
+                chunk.modified = true;
+                chunk
+            }),
+            Err(e) => {
+                error!("Couldn't read chunk {} from its region: {}", chunk_pos, e);
+                None
+            }
+        }
+    }
+
+    /// Keeps a modified chunk that's leaving memory. It goes to its region file once the
+    /// world has somewhere to be saved, and waits in `stored` until then.
+    fn store(&mut self, chunk: Chunk) {
+        if let Some(dir) = &self.save_dir {
+            let dir = dir.join(REGION_DIR);
+            let written = fs::create_dir_all(&dir)
+                .and_then(|()| write_region(&dir, region_of(chunk.position), &[&chunk]));
+            match written {
+                Ok(()) => {
+                    self.on_disk.insert(chunk.position);
+                    return;
+                }
+                Err(e) => error!(
+                    "Couldn't write chunk {} to its region, keeping it in memory: {}",
+                    chunk.position, e
+                ),
+            }
+        }
+        self.stored.insert(chunk.position, chunk);
+    }
+}
+
+// ---------------------------
+
+// ---------- Regions ----------
+// Saved chunks are grouped into region files of `REGION_SIZE` by `REGION_SIZE` chunks,
+// named after the region's position. Each file is `REGION_MAGIC`, then an offset and a
+// length (little endian u32s) for each of its `REGION_SLOTS` chunks, both zero for
+// chunks that aren't in it, then the chunks themselves. A chunk is a deflated, bincoded
+// `ChunkSave`.
+
+/// A chunk as it's kept in a region file: each kind of block in it once, and which of
+/// those every block is
+#[derive(Serialize, Deserialize)]
+struct ChunkSave {
+    position: IVec2,
+    palette: Vec<BlockType>,
+    indices: Vec<u8>, // Into `palette`, laid out like `Chunk::blocks`. Empty is all one kind
+    axes: Vec<BlockAxis>,
+}
+
+impl From<&Chunk> for ChunkSave {
+    fn from(chunk: &Chunk) -> Self {
+        // There are far fewer than 256 kinds of block, so an index always fits in a byte.
+        let mut palette = Vec::new();
+        let indices = chunk
+            .blocks
+            .iter()
+            .map(
+                |btype| match palette.iter().position(|known| *known == btype) {
+                    Some(i) => i as u8,
+                    None => {
+                        palette.push(btype);
+                        (palette.len() - 1) as u8
+                    }
+                },
+            )
+            .collect();
+
+        Self {
+            position: chunk.position,
+            palette,
+            indices,
+            axes: chunk.axes.clone(),
+        }
+    }
+}
+
+impl From<ChunkSave> for Chunk {
+    fn from(save: ChunkSave) -> Self {
+        let blocks = if save.indices.is_empty() {
+            BlockStorage::filled(save.palette.first().copied().unwrap_or(BlockType::Air))
+        } else {
+            save.indices
+                .iter()
+                .map(|&i| {
+                    save.palette
+                        .get(i as usize)
+                        .copied()
+                        .unwrap_or(BlockType::Air)
+                })
+                .collect()
+        };
+        Self {
+            blocks,
+            axes: save.axes,
+            ..Chunk::new(save.position)
+        }
+    }
+}
+
+/// The region a chunk is saved in
+fn region_of(chunk_pos: IVec2) -> IVec2 {
+    IVec2::new(
+        chunk_pos.x.div_euclid(REGION_SIZE),
+        chunk_pos.y.div_euclid(REGION_SIZE),
+    )
+}
+
+/// Where a chunk goes in its region's offset table
+fn region_slot(chunk_pos: IVec2) -> usize {
+    let local = IVec2::new(
+        chunk_pos.x.rem_euclid(REGION_SIZE),
+        chunk_pos.y.rem_euclid(REGION_SIZE),
+    );
+    (local.x + local.y * REGION_SIZE) as usize
+}
+
+fn region_path(dir: &Path, region: IVec2) -> PathBuf {
+    dir.join(format!("r.{}.{}.bin", region.x, region.y))
+}
+
+/// The region a file is for, from its name. `None` for anything that isn't a region file.
+fn region_from_path(path: &Path) -> Option<IVec2> {
+    let name = path.file_name()?.to_str()?;
+    let (x, z) = name
+        .strip_prefix("r.")?
+        .strip_suffix(".bin")?
+        .split_once('.')?;
+    Some(IVec2::new(x.parse().ok()?, z.parse().ok()?))
+}
+
+/// The offset table at the start of a region file, as (offset, length) pairs
+fn read_region_header(file: &mut impl Read) -> io::Result<Vec<(u32, u32)>> {
+    let mut header = vec![0; REGION_MAGIC.len() + REGION_SLOTS * 8];
+    file.read_exact(&mut header)?;
+    if header[..REGION_MAGIC.len()] != REGION_MAGIC[..] {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "not a region file",
+        ));
+    }
+
+    let entry = |bytes: &[u8]| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    Ok(header[REGION_MAGIC.len()..]
+        .chunks_exact(8)
+        .map(|slot| (entry(&slot[..4]), entry(&slot[4..])))
+        .collect())
+}
+
+/// Every chunk saved in the region files in `dir`. Only the headers are read.
+fn region_index(dir: &Path) -> io::Result<HashSet<IVec2>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(HashSet::new()),
+        Err(e) => return Err(e),
+    };
+
+    let mut chunks = HashSet::new();
+    for entry in entries {
+        let path = entry?.path();
+        let Some(region) = region_from_path(&path) else {
+            continue;
+        };
+        let header = read_region_header(&mut BufReader::new(File::open(&path)?))?;
+        for (slot, (_, length)) in header.into_iter().enumerate() {
+            if length > 0 {
+                let local = IVec2::new(slot as i32 % REGION_SIZE, slot as i32 / REGION_SIZE);
+                chunks.insert(region * REGION_SIZE + local);
+            }
+        }
+    }
+    Ok(chunks)
+}
+
+/// Reads one chunk out of its region file, seeking straight to it. `None` if it was
+/// never saved.
+fn read_region_chunk(dir: &Path, chunk_pos: IVec2) -> io::Result<Option<Chunk>> {
+    let mut file = match File::open(region_path(dir, region_of(chunk_pos))) {
+        Ok(file) => BufReader::new(file),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let (offset, length) = read_region_header(&mut file)?[region_slot(chunk_pos)];
+    if length == 0 {
+        return Ok(None);
+    }
+
+    file.seek(SeekFrom::Start(u64::from(offset)))?;
+    let mut compressed = vec![0; length as usize];
+    file.read_exact(&mut compressed)?;
+    let save: ChunkSave = bincode::deserialize_from(DeflateDecoder::new(&compressed[..]))
+        .map_err(io::Error::other)?;
+    Ok(Some(Chunk::from(save)))
+}
+
+/// Writes chunks into their region file, keeping the other chunks already in it. The
+/// file is written next to the old one and swapped in, so a failed write can't lose it.
+fn write_region(dir: &Path, region: IVec2, chunks: &[&Chunk]) -> io::Result<()> {
+    let path = region_path(dir, region);
+
+    // What's there already, still compressed.
+    let mut slots: Vec<Option<Vec<u8>>> = vec![None; REGION_SLOTS];
+    match File::open(&path) {
+        Ok(file) => {
+            let mut file = BufReader::new(file);
+            let header = read_region_header(&mut file)?;
+            for (slot, (offset, length)) in header.into_iter().enumerate() {
+                if length > 0 {
+                    let mut compressed = vec![0; length as usize];
+                    file.seek(SeekFrom::Start(u64::from(offset)))?;
+                    file.read_exact(&mut compressed)?;
+                    slots[slot] = Some(compressed);
+                }
+            }
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+        Err(e) => return Err(e),
+    }
+
+    for chunk in chunks {
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        bincode::serialize_into(&mut encoder, &ChunkSave::from(*chunk))
+            .map_err(io::Error::other)?;
+        slots[region_slot(chunk.position)] = Some(encoder.finish()?);
+    }
+
+    let mut header = REGION_MAGIC.to_vec();
+    let mut offset = (REGION_MAGIC.len() + REGION_SLOTS * 8) as u32;
+    for slot in slots.iter() {
+        let length = slot
+            .as_ref()
+            .map_or(0, |compressed| compressed.len() as u32);
+        let start = if length > 0 { offset } else { 0 };
+        header.extend_from_slice(&start.to_le_bytes());
+        header.extend_from_slice(&length.to_le_bytes());
+        offset += length;
+    }
+
+    let temp = path.with_extension("tmp");
+    let mut file = BufWriter::new(File::create(&temp)?);
+    file.write_all(&header)?;
+    for compressed in slots.iter().flatten() {
+        file.write_all(compressed)?;
+    }
+    file.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+    fs::rename(temp, path)
+}
+// -----------------------------
+
+// ---------- Lighting ----------
+/// A cell has a level of each, the brighter one lights it.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LightKind {
+    Block, // From light blocks like torches
+    Sky,   // From open sky, taken down at night
+}
+
+/// How bright open sky is right now, up to MAX_LIGHT. The chunk materials take sky light
+/// down by how far this is below MAX_LIGHT, so changing it doesn't remesh anything.
+#[derive(Resource)]
+pub struct Daylight(pub u8);
+
+impl Default for Daylight {
+    fn default() -> Self {
+        Self(MAX_LIGHT)
+    }
+}
+
+/// The blocks in the one block ring around a chunk, from the chunks on either side, so
+/// faces along its edges are culled against them rather than against air. Empty, like
+/// the default, is all air.
+#[derive(Default)]
+pub struct BlockRing {
+    blocks: Vec<(BlockType, bool, bool)>, // Type, solid, opaque. Laid out like the view's levels
+    sides: [bool; 4],                     // Which of the chunks along `CHUNK_SIDES` were there
+}
+
+impl BlockRing {
+    fn from_map(map: &Map, chunk_pos: IVec2) -> Self {
+        let origin = IVec3::new(chunk_pos.x, 0, chunk_pos.y) * CHUNK_SIZE;
+        let air = (BlockType::Air, false, false);
+        let width = ChunkView::WIDTH;
+        let mut blocks = vec![air; (width * width * CHUNK_HEIGHT) as usize];
+        for y in 0..CHUNK_HEIGHT {
+            for z in -1..=CHUNK_SIZE {
+                for x in -1..=CHUNK_SIZE {
+                    let local = IVec3::new(x, y, z);
+                    if Chunk::index(local).is_some() {
+                        continue;
+                    }
+                    let pos = origin + local;
+                    if let Some(chunk) = map.chunks.get(&Map::world_to_chunk(pos)) {
+                        let i = ChunkView::index(local).unwrap();
+                        blocks[i] = (chunk.get(pos), chunk.is_solid(pos), chunk.is_opaque(pos));
+                    }
+                }
+            }
+        }
+
+        Self {
+            blocks,
+            sides: map.loaded_sides(chunk_pos),
+        }
+    }
+
+    fn get(&self, local: IVec3) -> (BlockType, bool, bool) {
+        ChunkView::index(local)
+            .and_then(|i| self.blocks.get(i).copied())
+            .unwrap_or((BlockType::Air, false, false))
+    }
+}
+
+/// Light levels and blocks in and just around a chunk, for shading and culling its faces.
+pub struct ChunkView {
+    origin: IVec3,        // World position of the chunk's first block
+    levels: Vec<[u8; 2]>, // Block and sky light, with a one block ring so edges see their neighbours
+    ring: BlockRing,
+}
+
+impl ChunkView {
+    const WIDTH: i32 = CHUNK_SIZE + 2;
+
+    /// `light` gives the block and sky light at a world position.
+    fn new(origin: IVec3, ring: BlockRing, light: impl Fn(IVec3) -> [u8; 2]) -> Self {
+        let mut levels = vec![[0; 2]; (Self::WIDTH * Self::WIDTH * CHUNK_HEIGHT) as usize];
+        for y in 0..CHUNK_HEIGHT {
+            for z in -1..=CHUNK_SIZE {
+                for x in -1..=CHUNK_SIZE {
+                    let local = IVec3::new(x, y, z);
+                    levels[Self::index(local).unwrap()] = light(origin + local);
+                }
+            }
+        }
+
+        Self {
+            origin,
+            levels,
+            ring,
+        }
+    }
+
+    /// Everything from the map, so the ring holds the neighbouring chunks' light and blocks.
+    fn from_map(map: &Map, chunk_pos: IVec2) -> Self {
+        Self::new(
+            IVec3::new(chunk_pos.x, 0, chunk_pos.y) * CHUNK_SIZE,
+            BlockRing::from_map(map, chunk_pos),
+            |pos| {
+                [
+                    map.light(LightKind::Block, pos),
+                    map.light(LightKind::Sky, pos),
+                ]
+            },
+        )
+    }
+
+    /// A chunk on its own, for chunks meshed before they're in the map. Its edges stand
+    /// in for the neighbours' light, and `ring` for their blocks.
+    fn from_chunk(chunk: &Chunk, ring: BlockRing) -> Self {
+        let origin = chunk.origin();
+        let edge =
+            |pos: IVec3| origin + (pos - origin).clamp(IVec3::ZERO, CHUNK_EXTENT - IVec3::ONE);
+        Self::new(origin, ring, |pos| {
+            [
+                chunk.light(LightKind::Block, edge(pos)),
+                chunk.light(LightKind::Sky, edge(pos)),
+            ]
+        })
+    }
+
+    /// Index into `levels` of a position relative to the chunk, `None` outside the view.
+    fn index(local: IVec3) -> Option<usize> {
+        let (x, z) = (local.x + 1, local.z + 1);
+        if x < 0 || z < 0 || x >= Self::WIDTH || z >= Self::WIDTH {
+            return None;
+        }
+        if local.y < 0 || local.y >= CHUNK_HEIGHT {
+            return None;
+        }
+        Some((x + z * Self::WIDTH + local.y * Self::WIDTH * Self::WIDTH) as usize)
+    }
+
+    /// The block and sky light at a world position. Above the world is open sky.
+    fn light(&self, world_pos: IVec3) -> [u8; 2] {
+        let local = world_pos - self.origin;
+        if local.y >= CHUNK_HEIGHT {
+            return [0, MAX_LIGHT];
+        }
+        Self::index(local).map_or([0; 2], |i| self.levels[i])
+    }
+}
+
+impl Map {
+    /// Light at a world position, 0 outside the chunks in memory. Above the world is
+    /// open sky.
+    pub fn light(&self, kind: LightKind, world_pos: IVec3) -> u8 {
+        if kind == LightKind::Sky && world_pos.y >= CHUNK_HEIGHT {
+            return MAX_LIGHT;
+        }
+        self.chunk(Self::world_to_chunk(world_pos))
+            .map_or(0, |chunk| chunk.light(kind, world_pos))
+    }
+
+    fn set_light(&mut self, kind: LightKind, world_pos: IVec3, level: u8) {
+        let changed = self
+            .chunk_mut(Self::world_to_chunk(world_pos))
+            .is_some_and(|chunk| chunk.set_light(kind, world_pos, level));
+        if changed {
+            self.mark_dirty(world_pos);
+        }
+    }
+
+    /// Light level the block at a world position gives off.
+    fn emission(&self, world_pos: IVec3) -> u8 {
+        self.get_block(world_pos)
+            .map_or(0, |btype| self.blocks.get(&btype).light)
+    }
+
+    /// Whether light can spread into a world position.
+    fn lets_light_through(&self, world_pos: IVec3) -> bool {
+        (0..CHUNK_HEIGHT).contains(&world_pos.y)
+            && self.chunk(Self::world_to_chunk(world_pos)).is_some()
+            && !self.is_solid(world_pos)
+    }
+
+    /// The level light at `level` has after moving one block along `normal`. Full sky
+    /// light goes straight down without fading, anything else loses a level per block.
+    fn next_light(kind: LightKind, level: u8, normal: IVec3) -> u8 {
+        if kind == LightKind::Sky && level == MAX_LIGHT && normal == IVec3::NEG_Y {
+            MAX_LIGHT
+        } else {
+            level.saturating_sub(1)
+        }
+    }
+
+    /// Floods light out from the positions in `queue` until it runs out or meets
+    /// brighter light.
+    fn spread_light(&mut self, kind: LightKind, mut queue: VecDeque<IVec3>) {
+        while let Some(pos) = queue.pop_front() {
+            let level = self.light(kind, pos);
+            for normal in FACE_NORMALS {
+                let next = pos + normal;
+                let next_level = Self::next_light(kind, level, normal);
+                if next_level > self.light(kind, next) && self.lets_light_through(next) {
+                    self.set_light(kind, next, next_level);
+                    queue.push_back(next);
+                }
+            }
+        }
+    }
+
+    /// Takes away the light at a world position and everything that was lit from it.
+    /// Returns the brighter light it ran into, which has to spread back in.
+    fn remove_light(&mut self, kind: LightKind, world_pos: IVec3) -> VecDeque<IVec3> {
+        let mut refill = VecDeque::new();
+        let mut queue = VecDeque::from([(world_pos, self.light(kind, world_pos))]);
+        self.set_light(kind, world_pos, 0);
+
+        while let Some((pos, level)) = queue.pop_front() {
+            for normal in FACE_NORMALS {
+                let next = pos + normal;
+                let next_level = self.light(kind, next);
+                if next_level == 0 {
+                    continue;
+                }
+
+                let lit_from_here = next_level < level
+                    || next_level == Self::next_light(kind, level, normal) && level == MAX_LIGHT;
+                if lit_from_here {
+                    self.set_light(kind, next, 0);
+                    queue.push_back((next, next_level));
+
+                    // Light blocks keep their own light.
+                    let emitted = self.emission(next);
+                    if kind == LightKind::Block && emitted > 0 {
+                        self.set_light(kind, next, emitted);
+                        refill.push_back(next);
+                    }
+                } else {
+                    refill.push_back(next);
+                }
+            }
+        }
+
+        refill
+    }
+
+    /// Fixes the light up around a block that just changed. Placing a block in an open
+    /// column shades the whole column under it, breaking one lets the sky back down.
+    fn update_light(&mut self, world_pos: IVec3) {
+        for kind in [LightKind::Block, LightKind::Sky] {
+            let mut queue = self.remove_light(kind, world_pos);
+
+            let emitted = self.emission(world_pos);
+            if kind == LightKind::Block && emitted > 0 {
+                self.set_light(kind, world_pos, emitted);
+                queue.push_back(world_pos);
+            }
+
+            // If it was opened up, the light around it can spread in.
+            queue.extend(FACE_NORMALS.iter().map(|normal| world_pos + *normal));
+            self.spread_light(kind, queue);
+        }
+    }
+
+    /// Lights a chunk that just came into the map: from the light blocks in it, and
+    /// over the borders in both directions.
+    fn light_chunk(&mut self, chunk_pos: IVec2) {
+        let registry = self.blocks.clone();
+        let Some(chunk) = self.chunks.get_mut(&chunk_pos) else {
+            return;
+        };
+        // Chunks from a save come without either.
+        if chunk.occupancy.is_empty() {
+            chunk.gen_occupancy(&registry);
+        }
+        if chunk.sky_light.is_empty() {
+            chunk.gen_sky_light(&registry);
+        }
+
+        let emitters: Vec<(IVec3, u8)> = chunk
+            .blocks
+            .iter()
+            .enumerate()
+            .map(|(i, btype)| (chunk.position_of(i), registry.get(&btype).light))
+            .filter(|(_, light)| *light > 0)
+            .collect();
+
+        let mut block_queue = VecDeque::new();
+        for (pos, light) in emitters {
+            self.set_light(LightKind::Block, pos, light);
+            block_queue.push_back(pos);
+        }
+
+        // Both sides of every border.
+        let origin = IVec3::new(chunk_pos.x, 0, chunk_pos.y) * CHUNK_SIZE;
+        let mut edges = Vec::new();
+        for y in 0..CHUNK_HEIGHT {
+            for i in 0..CHUNK_SIZE {
+                edges.extend([
+                    IVec3::new(-1, y, i),
+                    IVec3::new(0, y, i),
+                    IVec3::new(CHUNK_SIZE - 1, y, i),
+                    IVec3::new(CHUNK_SIZE, y, i),
+                    IVec3::new(i, y, -1),
+                    IVec3::new(i, y, 0),
+                    IVec3::new(i, y, CHUNK_SIZE - 1),
+                    IVec3::new(i, y, CHUNK_SIZE),
+                ]);
+            }
+        }
+
+        for kind in [LightKind::Block, LightKind::Sky] {
+            let mut queue = if kind == LightKind::Block {
+                std::mem::take(&mut block_queue)
+            } else {
+                VecDeque::new()
+            };
+            queue.extend(
+                edges
+                    .iter()
+                    .map(|edge| origin + *edge)
+                    .filter(|pos| self.light(kind, *pos) > 0),
+            );
+            self.spread_light(kind, queue);
+        }
+    }
+}
+// ------------------------------
+
+// ---------- Systems ----------
+
+// Ray casting and block placement live in edit.rs.
+
+/// Where a chunk that just loaded came from
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ChunkSource {
+    Generated, // Freshly generated from the seed
+    Cache,     // Kept in memory since it went out of range, mesh and all
+    Stored,    // Edited or loaded from a save, and meshed again
+}
+
+/// Sent when a chunk is spawned into the world
+pub struct ChunkLoaded {
+    pub position: IVec2, // In chunks
+    pub source: ChunkSource,
+}
+
+/// Sent when a chunk goes out of range and is despawned
+pub struct ChunkUnloaded(pub IVec2);
+
+/// Send to throw the world away and generate a new one from `seed` around the player
+pub struct RegenerateWorld {
+    pub seed: u32,
+}
+
+/// Whether a chunk is close enough to the player's chunk to be loaded. Loading and
+/// unloading both use this, so chunks don't flicker in and out at the edge.
+fn within_render_distance(chunk_pos: IVec2, player_pos: IVec2, radius: i32) -> bool {
+    let offset = chunk_pos - player_pos;
+    offset.dot(offset) <= radius * radius
+}
+
+/// Spawns the entity that renders a chunk, with its cutout and transparent meshes on
+/// children so they get the masked and blended materials. The entity only carries the
+/// chunk's position, the blocks themselves stay in `Map`. Despawning the chunk is just
+/// despawning that entity.
+fn spawn_chunk(
+    commands: &mut Commands,
+    materials: &ChunkMaterials,
+    registry: &BlockRegistry,
+    chunk: &Chunk,
+) -> Entity {
+    commands
+        .spawn((
+            MaterialMeshBundle {
+                mesh: chunk.mesh.clone(),
+                material: materials.opaque.clone(),
+                transform: Transform::from_translation(chunk.origin().as_vec3()),
+                ..Default::default()
+            },
+            Chunk::new(chunk.position),
+        ))
+        .with_children(|parent| {
+            if let Some(mesh) = &chunk.cutout_mesh {
+                parent.spawn(MaterialMeshBundle {
+                    mesh: mesh.clone(),
+                    material: materials.cutout.clone(),
+                    ..Default::default()
+                });
+            }
+            if let Some(mesh) = &chunk.transparent_mesh {
+                parent.spawn(MaterialMeshBundle {
+                    mesh: mesh.clone(),
+                    material: materials.water.clone(),
+                    ..Default::default()
+                });
+            }
+
+            // Light blocks get a real light as well, the baked light is one level per face.
+            for (i, btype) in chunk.blocks.iter().enumerate() {
+                let def = registry.get(&btype);
+                if def.light == 0 {
+                    continue;
+                }
+
+                let local = (chunk.position_of(i) - chunk.origin()).as_vec3();
+                parent.spawn(PointLightBundle {
+                    point_light: PointLight {
+                        color: LIGHT_COLOR,
+                        intensity: LIGHT_INTENSITY,
+                        range: def.light as f32,
+                        ..Default::default()
+                    },
+                    transform: Transform::from_translation(local + Vec3::new(0.5, 0.75, 0.5)),
+                    ..Default::default()
+                });
+            }
+        })
+        .id()
+}
+
+/// Spawns a loaded chunk with its current meshes, replacing the entity it had before so
+/// there's only ever one per chunk.
+fn respawn_chunk(commands: &mut Commands, map: &mut Map, chunk_pos: IVec2) {
+    let entity = spawn_chunk(
+        commands,
+        &map.materials,
+        &map.blocks,
+        &map.chunks[&chunk_pos],
+    );
+    if let Some(old) = map.entities.insert(chunk_pos, entity) {
+        commands.entity(old).despawn_recursive();
+    }
+}
+
+/// Despawns a chunk's entity, if it has one.
+fn despawn_chunk(commands: &mut Commands, map: &mut Map, chunk_pos: IVec2) {
+    if let Some(entity) = map.entities.remove(&chunk_pos) {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn update_world(
+    mut commands: Commands,
+    mut map: ResMut<Map>,
+    atlas: Res<Assets<TextureAtlas>>,
+    config: Res<WorldConfig>,
+    meshing: Res<Meshing>,
+    budget: Res<ChunkBudget>,
+    render_distance: Res<RenderDistance>,
+    cache_size: Res<ChunkCacheSize>,
+    time: Res<Time>,
+    mut loaded: EventWriter<ChunkLoaded>,
+    mut unloaded: EventWriter<ChunkUnloaded>,
+    player: Query<&Transform, With<Player>>,
+) {
+    // In here, I will use the player's position to determine which chunks to load and unload.
+    let player = player.single();
+    let player_pos = Map::world_to_chunk(player.translation.floor().as_ivec3());
+    let radius = render_distance.0;
+
+    let mut cached_chunks = Vec::new();
+
+    // Remove chunks outside the render distance and add them to the cache.
+    for (chunk_pos, _chunk) in map.chunks.iter() {
+        if !within_render_distance(*chunk_pos, player_pos, radius) {
+            cached_chunks.push(*chunk_pos);
+        }
+    }
+
+    // Add the cached chunks to the cache, and despawn them.
+    let now = time.elapsed_seconds_f64();
+    for chunk_pos in cached_chunks.iter() {
+        let mut chunk = map.chunks.remove(chunk_pos).unwrap();
+        chunk.last_used = now;
+        map.cache.insert(*chunk_pos, chunk);
+        despawn_chunk(&mut commands, &mut map, *chunk_pos);
+        unloaded.send(ChunkUnloaded(*chunk_pos));
+    }
+
+    // Over the limit, drop the chunks that were left behind the longest ago. Modified ones
+    // keep their blocks, or the edits would be lost.
+    while map.cache.len() > cache_size.0 {
+        let oldest = map
+            .cache
+            .iter()
+            .min_by(|a, b| a.1.last_used.total_cmp(&b.1.last_used))
+            .map(|(chunk_pos, _chunk)| *chunk_pos)
+            .unwrap();
+
+        let mut chunk = map.cache.remove(&oldest).unwrap();
+        if chunk.modified {
+            chunk.mesh = Handle::default();
+            chunk.cutout_mesh = None;
+            chunk.transparent_mesh = None;
+            chunk.dirty = true;
+            map.store(chunk);
+        }
+    }
+
+    // Every chunk within the radius around player_pos.
+    let mut new_chunks = Vec::new();
+    for x in -radius..=radius {
+        for z in -radius..=radius {
+            let chunk_pos = player_pos + IVec2::new(x, z);
+            if within_render_distance(chunk_pos, player_pos, radius) {
+                new_chunks.push(chunk_pos);
+            }
+        }
+    }
+
+    // Remove chunks that are already loaded or on their way.
+    new_chunks.retain(|chunk_pos| {
+        !map.chunks.contains_key(chunk_pos) && !map.generating.contains(chunk_pos)
+    });
+
+    // Closest first, and only so many per frame. The rest wait for later frames.
+    new_chunks.sort_by_key(|chunk_pos| {
+        let offset = *chunk_pos - player_pos;
+        offset.dot(offset)
+    });
+    new_chunks.truncate(budget.per_frame);
+
+    // Load the chunks.
+    for chunk_pos in new_chunks.iter() {
+        let source = if let Some(chunk) = map.cache.remove(chunk_pos) {
+            // Moved back as is, so it still has the meshes it went into the cache with.
+            map.chunks.insert(*chunk_pos, chunk);
+            ChunkSource::Cache
+        } else if let Some(chunk) = map
+            .stored
+            .remove(chunk_pos)
+            .or_else(|| map.read_chunk(*chunk_pos))
+        {
+            // Saved or edited earlier, dirty so it gets a mesh below. Chunks from a save
+            // have no light yet.
+            map.chunks.insert(*chunk_pos, chunk);
+            map.light_chunk(*chunk_pos);
+            ChunkSource::Stored
+        } else {
+            // Generated in the background, spawn_generated_chunks picks it up.
+            map.generating.insert(*chunk_pos);
+            let task = gen_chunk(
+                &map,
+                *chunk_pos,
+                &config,
+                atlas.get(&map.texture_atlas).unwrap(),
+                meshing.greedy,
+                meshing.lod(*chunk_pos - player_pos),
+            );
+            commands.spawn(task);
+            continue;
+        };
+
+        // The neighbours were meshed with nothing on this side.
+        map.mark_sides_dirty(*chunk_pos);
+
+        // Chunks edited while they were cached, or coming back from storage without a
+        // mesh, are left to remesh_chunks to mesh and spawn. Anything else from the cache
+        // spawns with its old meshes.
+        if !map.chunks[chunk_pos].dirty {
+            respawn_chunk(&mut commands, &mut map, *chunk_pos);
+        }
+        loaded.send(ChunkLoaded {
+            position: *chunk_pos,
+            source,
+        });
+    }
+}
+
+/// A chunk being generated on the `AsyncComputeTaskPool`, along with its mesh data and
+/// which of its neighbours that was culled against.
+#[derive(Component)]
+pub struct ChunkTask(Task<(Chunk, ChunkMeshData, [bool; 4])>);
+
+/// Starts generating the blocks and mesh data for a chunk off the main thread.
+fn gen_chunk(
+    map: &Map,
+    position: IVec2,
+    config: &WorldConfig,
+    atlas: &TextureAtlas,
+    greedy: bool,
+    lod: u8,
+) -> ChunkTask {
+    let (noise, caves, climate) = (map.noise.clone(), map.caves.clone(), map.climate.clone());
+    let registry = map.blocks.clone();
+    let (config, atlas) = (config.clone(), atlas.clone());
+    // Whichever neighbours are already here. Any that come or go while it generates get
+    // it remeshed when it's done, see spawn_generated_chunks.
+    let ring = BlockRing::from_map(map, position);
+
+    let task = AsyncComputeTaskPool::get().spawn(async move {
+        let mut chunk = Chunk::new(position);
+        chunk.lod = lod;
+        chunk.gen_blocks(&noise, &caves, &climate, &config);
+        chunk.gen_occupancy(&registry);
+        chunk.gen_sky_light(&registry);
+        let sides = ring.sides;
+        let view = ChunkView::from_chunk(&chunk, ring);
+        let data = chunk.mesh_data(&atlas, &registry, &view, greedy);
+        (chunk, data, sides)
+    });
+    ChunkTask(task)
+}
+
+/// Spawns the chunks whose generation tasks have finished.
+#[allow(clippy::too_many_arguments)]
+pub fn spawn_generated_chunks(
+    mut commands: Commands,
+    mut map: ResMut<Map>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    budget: Res<ChunkBudget>,
+    render_distance: Res<RenderDistance>,
+    mut loaded: EventWriter<ChunkLoaded>,
+    player: Query<&Transform, With<Player>>,
+    mut tasks: Query<(Entity, &mut ChunkTask)>,
+) {
+    let player = player.single();
+    let player_pos = Map::world_to_chunk(player.translation.floor().as_ivec3());
+    let mut spawned = 0;
+
+    for (entity, mut task) in tasks.iter_mut() {
+        // Uploading meshes is the slow part, leave the rest of the finished tasks for later.
+        if spawned >= budget.per_frame {
+            break;
+        }
+
+        let Some((mut chunk, data, sides)) = future::block_on(future::poll_once(&mut task.0))
+        else {
+            continue;
+        };
+        commands.entity(entity).despawn();
+        map.generating.remove(&chunk.position);
+
+        // The player may have moved on while it was generating.
+        if !within_render_distance(chunk.position, player_pos, render_distance.0) {
+            continue;
+        }
+
+        // Neighbours that came or went since it started would leave faces on its edges
+        // wrong, so remesh_chunks meshes it again instead.
+        let position = chunk.position;
+        let current = map.loaded_sides(position) == sides;
+        if current {
+            chunk.set_mesh(&mut meshes, data);
+        } else {
+            chunk.dirty = true;
+        }
+        map.chunks.insert(position, chunk);
+        if current {
+            respawn_chunk(&mut commands, &mut map, position);
+        }
+        loaded.send(ChunkLoaded {
+            position,
+            source: ChunkSource::Generated,
+        });
+        // Light from the neighbours spreading in remeshes it if it needs to.
+        map.light_chunk(position);
+        map.mark_sides_dirty(position);
+        spawned += 1;
+    }
+}
+
+/// Loads the block atlas and the chunk materials that use it.
+fn load_textures(
+    asset_server: Res<AssetServer>,
+    mut atlas: ResMut<Assets<TextureAtlas>>,
+    mut materials: ResMut<Assets<ChunkMaterial>>,
+    mut water_materials: ResMut<Assets<WaterMaterial>>,
+    mut map: ResMut<Map>,
+) {
+    let texture: Handle<Image> = asset_server.load("../resources/alpha_atlas.png");
+    // Save the texture handle so we can use it later.
+    // The image is 512x512, so 32x32 tiles of 16 pixels.
+    let texture_atlas =
+        TextureAtlas::from_grid(texture.clone(), Vec2::new(16., 16.), 32, 32, None, None);
+    map.texture_atlas = atlas.add(texture_atlas);
+
+    // Every block shares these materials, the UVs pick the tile.
+    map.materials = ChunkMaterials::new(&mut materials, &mut water_materials, texture);
+}
+
+/// Switches the atlas to nearest filtering once it's loaded. It's pixel art, and linear
+/// filtering blurs it and mixes in the neighbouring tiles at the edges.
+fn sharpen_atlas(
+    mut events: EventReader<AssetEvent<Image>>,
+    map: Res<Map>,
+    atlases: Res<Assets<TextureAtlas>>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    let Some(atlas) = atlases.get(&map.texture_atlas) else {
+        return;
+    };
+
+    for event in events.iter() {
+        if let AssetEvent::Created { handle } = event {
+            if *handle == atlas.texture {
+                if let Some(image) = images.get_mut(handle) {
+                    image.sampler_descriptor = ImageSampler::nearest();
+                }
+            }
+        }
+    }
+}
+
+/// Moves falling blocks with nothing solid under them down a block each tick. Moving
+/// one queues the blocks around it again, so it keeps going until it lands and
+/// anything stacked on top follows it down.
+pub fn fall_blocks(time: Res<Time>, mut tick: ResMut<BlockTick>, mut map: ResMut<Map>) {
+    if !tick.0.tick(time.delta()).just_finished() {
+        return;
+    }
+
+    // Bottom up, so a falling stack moves together instead of one block at a time.
+    let mut updates: Vec<IVec3> = std::mem::take(&mut map.updates).into_iter().collect();
+    updates.sort_by_key(|pos| pos.y);
+
+    for pos in updates {
+        let Some(btype) = map.get_block(pos) else {
+            continue;
+        };
+        let below = pos - IVec3::Y;
+
+        if !map.blocks.get(&btype).falls || map.is_solid(below) {
+            continue;
+        }
+
+        if map.set_block(below, btype) {
+            map.set_block(pos, BlockType::Air);
+        }
+    }
+}
+
+/// Lets water flow into the space around edits on the same ticks blocks fall, a capped
+/// number of cells at a time
+pub fn flow_water(tick: Res<BlockTick>, mut map: ResMut<Map>) {
+    if tick.0.just_finished() {
+        map.flow_water(WATER_FLOWS_PER_TICK);
+    }
+}
+
+/// Meshes and (re)spawns the loaded chunks that are dirty: edited, next to an edit, or
+/// just back from storage. This is the only place loaded chunks get meshed.
+pub fn remesh_chunks(
+    mut commands: Commands,
+    mut map: ResMut<Map>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    atlas: Res<Assets<TextureAtlas>>,
+    meshing: Res<Meshing>,
+) {
+    let atlas_handle = map.texture_atlas.clone();
+    let registry = map.blocks.clone();
+
+    let dirty: Vec<IVec2> = map
+        .chunks
+        .values()
+        .filter(|chunk| chunk.dirty)
+        .map(|chunk| chunk.position)
+        .collect();
+
+    for chunk_pos in dirty {
+        let view = ChunkView::from_map(&map, chunk_pos);
+        let chunk = map.chunks.get_mut(&chunk_pos).unwrap();
+        chunk.gen_meshes(
+            &mut meshes,
+            atlas_handle.clone(),
+            &atlas,
+            &registry,
+            &view,
+            meshing.greedy,
+        );
+        respawn_chunk(&mut commands, &mut map, chunk_pos);
+    }
+}
+
+/// Hides the chunks outside the camera's view, children and all. Tested against the
+/// whole chunk rather than its mesh bounds, so it doesn't change as the chunk is edited.
+pub fn cull_chunks(
+    camera: Query<&Frustum, With<FlyCam>>,
+    mut chunks: Query<(&Transform, &mut Visibility), With<Chunk>>,
+    mut culling: ResMut<ChunkCulling>,
+) {
+    let Ok(frustum) = camera.get_single() else {
+        return;
+    };
+    let bounds = Aabb::from_min_max(
+        Vec3::splat(-CULL_MARGIN),
+        CHUNK_EXTENT.as_vec3() + CULL_MARGIN,
+    );
+
+    let mut counts = ChunkCulling::default();
+    for (transform, mut visibility) in chunks.iter_mut() {
+        let in_view = frustum.intersects_obb(&bounds, &transform.compute_matrix(), true, true);
+        let wanted = if in_view {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+        if *visibility != wanted {
+            *visibility = wanted;
+        }
+
+        counts.total += 1;
+        counts.visible += in_view as usize;
+    }
+
+    if culling.visible != counts.visible || culling.total != counts.total {
+        *culling = counts;
+    }
+}
+
+/// Remeshes the chunks whose level of detail changed as the player moved.
+pub fn update_lod(
+    mut map: ResMut<Map>,
+    meshing: Res<Meshing>,
+    player: Query<&Transform, With<Player>>,
+) {
+    let player = player.single();
+    let player_pos = Map::world_to_chunk(player.translation.floor().as_ivec3());
+
+    for chunk in map.chunks.values_mut() {
+        let lod = meshing.lod(chunk.position - player_pos);
+        if chunk.lod != lod {
+            chunk.lod = lod;
+            chunk.dirty = true;
+        }
+    }
+}
+
+/// Starts the world over with a new seed. Chunks still generating for the old one are
+/// cancelled by despawning their tasks. The world is renamed after the seed, so its
+/// empty save doesn't overwrite the old world's.
+fn regenerate_world(
+    mut commands: Commands,
+    mut events: EventReader<RegenerateWorld>,
+    mut map: ResMut<Map>,
+    mut config: ResMut<WorldConfig>,
+    params: Res<TerrainParams>,
+    mut unloaded: EventWriter<ChunkUnloaded>,
+    tasks: Query<Entity, With<ChunkTask>>,
+) {
+    let Some(event) = events.iter().last() else {
+        return;
+    };
+
+    for entity in tasks.iter() {
+        commands.entity(entity).despawn();
+    }
+    for (chunk_pos, entity) in map.entities.drain() {
+        commands.entity(entity).despawn_recursive();
+        unloaded.send(ChunkUnloaded(chunk_pos));
+    }
+
+    config.seed = event.seed;
+    config.name = WorldConfig::seed_name(event.seed);
+    map.regenerate(&config, &params);
+    info!("Regenerated the world with seed {}", event.seed);
+}
+
+/// Passes the sky light level on to the chunk materials, which light the meshes with it.
+fn update_sky_dimming(
+    daylight: Res<Daylight>,
+    map: Res<Map>,
+    mut materials: ResMut<Assets<ChunkMaterial>>,
+    mut water_materials: ResMut<Assets<WaterMaterial>>,
+) {
+    let dimming = (MAX_LIGHT - daylight.0.min(MAX_LIGHT)) as f32;
+    for handle in [&map.materials.opaque, &map.materials.cutout] {
+        if let Some(material) = materials.get_mut(handle) {
+            material.sky_dimming = dimming;
+        }
+    }
+    if let Some(material) = water_materials.get_mut(&map.materials.water) {
+        material.sky_dimming = dimming;
+    }
+}
+
+/// Terrain generation, chunk loading and meshing
+pub struct WorldPlugin;
+impl Plugin for WorldPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<BlockRegistry>()
+            .init_resource::<Map>()
+            .init_resource::<Meshing>()
+            .init_resource::<ChunkBudget>()
+            .init_resource::<RenderDistance>()
+            .init_resource::<ChunkCacheSize>()
+            .init_resource::<BlockTick>()
+            .init_resource::<Daylight>()
+            .init_resource::<ChunkCulling>()
+            .add_event::<ChunkLoaded>()
+            .add_event::<ChunkUnloaded>()
+            .add_event::<RegenerateWorld>()
+            .add_system(load_textures.on_startup())
+            .add_system(sharpen_atlas)
+            // Before Update, so the despawned tasks are gone before anything polls them.
+            .add_system(regenerate_world.in_base_set(CoreSet::PreUpdate))
+            .add_system(update_world)
+            .add_system(spawn_generated_chunks.after(update_world))
+            .add_system(
+                fall_blocks
+                    .before(remesh_chunks)
+                    .run_if(in_state(GameState::Playing)),
+            )
+            .add_system(
+                flow_water
+                    .after(fall_blocks)
+                    .before(remesh_chunks)
+                    .run_if(in_state(GameState::Playing)),
+            )
+            .add_system(update_sky_dimming.run_if(resource_changed::<Daylight>()))
+            .add_system(update_lod.after(update_world).before(remesh_chunks))
+            .add_system(remesh_chunks.after(update_world))
+            .add_system(cull_chunks.after(remesh_chunks));
+    }
+}
+// -----------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::system::CommandQueue;
+    use bevy::render::mesh::VertexAttributeValues;
+    use std::sync::Mutex;
+
+    #[test]
+    fn gen_blocks_matches_mutex_version() {
+        let config = WorldConfig::default();
+        let noise = HeightNoise::new(config.seed, &TerrainParams::default());
+        let caves = Fbm::<Perlin>::new(config.seed.wrapping_add(1));
+        let climate = Climate::new(config.seed);
+
+        for position in [IVec2::ZERO, IVec2::new(3, -5)] {
+            let offset = IVec3::new(position.x, 0, position.y) * CHUNK_SIZE;
+
+            // How gen_blocks used to collect the terrain, every worker locking one map.
+            let blocks_mutex = Mutex::new(HashMap::new());
+            (0..CHUNK_VOLUME as i32).into_par_iter().for_each(|i| {
+                let x = i % CHUNK_SIZE;
+                let z = (i / CHUNK_SIZE) % CHUNK_SIZE;
+                let y = i / (CHUNK_SIZE * CHUNK_SIZE);
+                let block_pos = IVec3::new(x, y, z) + offset;
+                let biome = climate.biome(block_pos.x, block_pos.z);
+                let btype = terrain_block(&noise, &caves, &config, biome, block_pos);
+                if btype != BlockType::Air {
+                    blocks_mutex.lock().unwrap().insert(block_pos, btype);
+                }
+            });
+            let expected = blocks_mutex.into_inner().unwrap();
+
+            let mut chunk = Chunk::new(position);
+            chunk.gen_blocks(&noise, &caves, &climate, &config);
+
+            // Ores only swap stone for ore, and trees only add blocks on top.
+            let terrain = chunk
+                .blocks
+                .iter()
+                .filter(|btype| {
+                    !matches!(btype, BlockType::Air | BlockType::Wood | BlockType::Leaves)
+                })
+                .count();
+            assert_eq!(terrain, expected.len());
+            for (pos, btype) in expected.iter() {
+                let generated = chunk.get(*pos);
+                if *btype == BlockType::Stone {
+                    assert!(matches!(
+                        generated,
+                        BlockType::Stone | BlockType::CoalOre | BlockType::IronOre
+                    ));
+                } else {
+                    assert_eq!(generated, *btype);
+                }
+            }
+        }
+    }
+
+    /// A chunk generated the way gen_chunk does it, with nothing but a plain World.
+    fn generated_chunk(seed: u32, position: IVec2) -> Chunk {
+        let mut world = World::new();
+        world.insert_resource(WorldConfig { seed, ..default() });
+        let map = Map::from_world(&mut world);
+
+        let mut chunk = Chunk::new(position);
+        chunk.gen_blocks(&map.noise, &map.caves, &map.climate, &map.config);
+        chunk
+    }
+
+    /// A 32 by 32 grid of 16 texel tiles, like the real atlas, without loading an image.
+    fn test_atlas() -> TextureAtlas {
+        TextureAtlas::from_grid(Handle::default(), Vec2::splat(16.), 32, 32, None, None)
+    }
+
+    #[test]
+    fn terrain_goes_on_past_the_old_height_map() {
+        let config = WorldConfig::default();
+        let noise = HeightNoise::new(config.seed, &TerrainParams::default());
+
+        // The map ended 512 blocks out, everything past it was flat.
+        for (x, z) in [(100_000, 3), (-70_000, -90_000), (-600, 40)] {
+            let heights: Vec<f64> = (0..16)
+                .map(|i| column_height(&noise, &config, x + i * 8, z))
+                .collect();
+            assert!(
+                heights.iter().any(|h| *h != heights[0]),
+                "flat around {} {}",
+                x,
+                z
+            );
+            assert!(heights.iter().all(|h| (0.0..=MAX_HEIGHT).contains(h)));
+        }
+    }
+
+    #[test]
+    fn generation_is_deterministic() {
+        for position in [IVec2::ZERO, IVec2::new(3, -5), IVec2::new(-20, 7)] {
+            let first = generated_chunk(42, position);
+            let second = generated_chunk(42, position);
+            assert!(first.blocks == second.blocks, "chunk {} differs", position);
+        }
+
+        // Pinned, so changes to the noise setup or the parallel generation that move
+        // the terrain show up here. Update these if that's on purpose.
+        let chunk = generated_chunk(42, IVec2::ZERO);
+        let solid = chunk.blocks.iter().filter(|b| *b != BlockType::Air).count();
+        assert_eq!(solid, 28227);
+        for (pos, btype) in [
+            (IVec3::new(0, 0, 0), BlockType::Bedrock),
+            (IVec3::new(5, 20, 9), BlockType::Stone),
+            (IVec3::new(5, 33, 9), BlockType::Grass),
+            (IVec3::new(10, 35, 20), BlockType::Dirt),
+            (IVec3::new(16, 35, 16), BlockType::Leaves),
+            (IVec3::new(31, 22, 31), BlockType::Sand),
+            (IVec3::new(31, 24, 31), BlockType::Air),
+        ] {
+            assert_eq!(chunk.get(pos), btype, "at {}", pos);
+        }
+    }
+
+    /// Times both meshers on a few chunks and prints the triangles they make. Run with
+    /// `cargo test --release mesh_timings -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn mesh_timings() {
+        const RUNS: u32 = 20;
+        let registry = BlockRegistry::default();
+        let atlas = test_atlas();
+
+        let filled = |name: &'static str, fill: fn(IVec3) -> BlockType| {
+            let mut chunk = Chunk::new(IVec2::ZERO);
+            chunk.blocks = (0..CHUNK_VOLUME)
+                .map(|i| fill(chunk.position_of(i)))
+                .collect();
+            (name, chunk)
+        };
+        let chunks = [
+            ("generated", generated_chunk(42, IVec2::ZERO)),
+            // Every block has all six faces showing, the worst case.
+            filled("checkerboard", |pos| {
+                if (pos.x + pos.y + pos.z) % 2 == 0 {
+                    BlockType::Stone
+                } else {
+                    BlockType::Air
+                }
+            }),
+            // Solid up to just under the top. With no neighbours in the ring the top and
+            // the four sides show, everything inside is culled.
+            filled("buried", |pos| {
+                if pos.y < CHUNK_HEIGHT - 2 {
+                    BlockType::Stone
+                } else {
+                    BlockType::Air
+                }
+            }),
+        ];
+
+        for (name, mut chunk) in chunks {
+            chunk.gen_occupancy(&registry);
+            chunk.gen_sky_light(&registry);
+            let view = ChunkView::from_chunk(&chunk, BlockRing::default());
+
+            for greedy in [false, true] {
+                let start = std::time::Instant::now();
+                let mut data = ChunkMeshData::default();
+                for _ in 0..RUNS {
+                    data = chunk.mesh_data(&atlas, &registry, &view, greedy);
+                }
+                let elapsed = start.elapsed() / RUNS;
+
+                let triangles = (data.opaque.indices.len()
+                    + data.cutout.indices.len()
+                    + data.transparent.indices.len())
+                    / 3;
+                println!(
+                    "{:<12} {:<9} {:>8.2?} {:>7} triangles",
+                    name,
+                    if greedy { "greedy" } else { "per block" },
+                    elapsed,
+                    triangles
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn chunks_round_trip_through_region_files() {
+        let dir = std::env::temp_dir().join(format!("mc_regions_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        // Two chunks in the same region, below zero, written one at a time.
+        let mut first = Chunk::new(IVec2::new(-1, -16));
+        first.set(IVec3::new(-32, 0, -512), BlockType::Stone);
+        first.set(IVec3::new(-1, 31, -481), BlockType::Wood);
+        first.set_axis(IVec3::new(-1, 31, -481), BlockAxis::X);
+        let second = Chunk::new(IVec2::new(-2, -3));
+        write_region(&dir, region_of(first.position), &[&first]).unwrap();
+        write_region(&dir, region_of(second.position), &[&second]).unwrap();
+
+        let index = region_index(&dir).unwrap();
+        assert_eq!(index, HashSet::from([first.position, second.position]));
+
+        let read = read_region_chunk(&dir, first.position).unwrap().unwrap();
+        assert!(read.blocks == first.blocks);
+        assert_eq!(read.axis(IVec3::new(-1, 31, -481)), BlockAxis::X);
+        let read = read_region_chunk(&dir, second.position).unwrap().unwrap();
+        assert!(read.blocks == BlockStorage::default());
+        assert!(read_region_chunk(&dir, IVec2::new(-3, -3))
+            .unwrap()
+            .is_none());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn every_block_type_is_registered() {
+        let registry = BlockRegistry::load(Path::new(BLOCKS_FILE)).unwrap();
+
+        for btype in [
+            BlockType::Grass,
+            BlockType::Dirt,
+            BlockType::Stone,
+            BlockType::Water,
+            BlockType::Sand,
+            BlockType::Wood,
+            BlockType::Leaves,
+            BlockType::CoalOre,
+            BlockType::IronOre,
+            BlockType::Snow,
+            BlockType::Bedrock,
+            BlockType::Gravel,
+            BlockType::Torch,
+            BlockType::Air,
+        ] {
+            assert!(registry.blocks.contains_key(&btype), "{:?}", btype);
+        }
+        assert!(registry.get(&BlockType::Water).transparent);
+        assert!(!registry.get(&BlockType::Water).solid);
+        assert!(registry.get(&BlockType::Leaves).cutout);
+    }
+
+    #[test]
+    fn tile_uvs_stay_inside_their_tile() {
+        let atlas = test_atlas();
+        let rect = tile_rect(&atlas, [3, 5]);
+        let texel = 1.0 / 512.0;
+
+        assert_eq!(rect.min, Vec2::new(48.5, 80.5) * texel);
+        assert_eq!(rect.max, Vec2::new(63.5, 95.5) * texel);
+    }
+
+    #[test]
+    fn greedy_uvs_wrap_inside_their_tile() {
+        // What chunk.wgsl does to the UVs before sampling.
+        let tile_uv = |uv: Vec2, tile: [f32; 4]| {
+            let (min, size) = (Vec2::new(tile[0], tile[1]), Vec2::new(tile[2], tile[3]));
+            let local = (uv - min) / size;
+            let wrap = |l: f32| {
+                if (0.0..=1.0).contains(&l) {
+                    l
+                } else {
+                    l.fract()
+                }
+            };
+            min + Vec2::new(wrap(local.x), wrap(local.y)) * size
+        };
+
+        let mut chunk = Chunk::new(IVec2::ZERO);
+        for x in 0..4 {
+            for z in 0..4 {
+                chunk.set(IVec3::new(x, 0, z), BlockType::Stone);
+            }
+        }
+        let registry = BlockRegistry::default();
+        chunk.gen_occupancy(&registry);
+        let atlas = test_atlas();
+        let view = ChunkView::from_chunk(&chunk, BlockRing::default());
+        let data = chunk.mesh_data(&atlas, &registry, &view, true).opaque;
+
+        // The top is one quad four tiles across, which would run into the next tiles.
+        let top = registry.get(&BlockType::Stone).faces[4];
+        let rect = tile_rect(&atlas, top);
+        let quad = (0..data.positions.len())
+            .step_by(4)
+            .find(|&i| data.normals[i] == Vec3::Y)
+            .unwrap();
+        let uvs = &data.uvs[quad..quad + 4];
+        assert!(uvs.iter().any(|uv| !rect.contains(*uv)));
+
+        for i in 0..4 {
+            assert_eq!(
+                data.tiles[quad + i],
+                [rect.min.x, rect.min.y, rect.width(), rect.height()]
+            );
+        }
+        // Samples spread over the quad, as the rasterizer would interpolate them.
+        for a in 0..=10 {
+            for b in 0..=10 {
+                let (a, b) = (a as f32 / 10.0, b as f32 / 10.0);
+                let uv = uvs[0].lerp(uvs[1], a).lerp(uvs[3].lerp(uvs[2], a), b);
+                let sampled = tile_uv(uv, data.tiles[quad]);
+                assert!(
+                    (rect.min - 1e-6).cmple(sampled).all() && sampled.cmple(rect.max + 1e-6).all(),
+                    "{} wrapped to {} outside {:?}",
+                    uv,
+                    sampled,
+                    rect
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn greedy_uvs_match_per_block_uvs() {
+        let registry = BlockRegistry::load(Path::new(BLOCKS_FILE)).unwrap();
+        let atlas = test_atlas();
+
+        // Grass has a different tile on its top, bottom and sides, logs are turned.
+        for (btype, axis) in [
+            (BlockType::Grass, BlockAxis::Y),
+            (BlockType::Wood, BlockAxis::X),
+            (BlockType::Wood, BlockAxis::Z),
+        ] {
+            let mut chunk = Chunk::new(IVec2::ZERO);
+            let pos = IVec3::new(5, 5, 5);
+            chunk.set(pos, btype);
+            chunk.set_axis(pos, axis);
+            chunk.gen_occupancy(&registry);
+            let view = ChunkView::from_chunk(&chunk, BlockRing::default());
+
+            // The two meshers order their vertices differently, so match them up by
+            // where they are and which way they face.
+            let uvs = |greedy: bool| {
+                let data = chunk.mesh_data(&atlas, &registry, &view, greedy).opaque;
+                let mut uvs: Vec<_> = (0..data.positions.len())
+                    .map(|i| (data.positions[i], data.normals[i], data.uvs[i]))
+                    .collect();
+                uvs.sort_by(|a, b| {
+                    let key = |(p, n, _): &(Vec3, Vec3, Vec2)| [p.x, p.y, p.z, n.x, n.y, n.z];
+                    key(a).partial_cmp(&key(b)).unwrap()
+                });
+                uvs
+            };
+            let (per_block, greedy) = (uvs(false), uvs(true));
+            assert_eq!(per_block.len(), 24);
+            for (a, b) in per_block.iter().zip(&greedy) {
+                assert_eq!(a.0, b.0);
+                assert_eq!(a.1, b.1);
+                assert!(
+                    a.2.abs_diff_eq(b.2, 1e-6),
+                    "{:?} {:?} at {} facing {}: {} vs {}",
+                    btype,
+                    axis,
+                    a.0,
+                    a.1,
+                    a.2,
+                    b.2
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn block_storage_widens_as_kinds_are_added() {
+        let mut storage = BlockStorage::default();
+        assert_eq!(storage.bits, 0);
+        assert!(storage.words.is_empty());
+
+        let kinds = [
+            BlockType::Stone,
+            BlockType::Dirt,
+            BlockType::Grass,
+            BlockType::Sand,
+            BlockType::Wood,
+        ];
+        for (i, btype) in kinds.iter().enumerate() {
+            storage.set(i * 1000, *btype);
+        }
+        assert_eq!(storage.bits, 3);
+        for (i, btype) in kinds.iter().enumerate() {
+            assert_eq!(storage.get(i * 1000), *btype);
+            assert_eq!(storage.get(i * 1000 + 1), BlockType::Air);
+        }
+        assert_eq!(storage.get(CHUNK_VOLUME - 1), BlockType::Air);
+
+        let packed: BlockStorage = storage.iter().collect();
+        assert!(packed == storage);
+    }
+
+    #[test]
+    fn turned_logs_have_rings_on_their_ends() {
+        let registry = BlockRegistry::load(Path::new(BLOCKS_FILE)).unwrap();
+        let wood = registry.get(&BlockType::Wood);
+        let rings = wood.faces[4];
+
+        for (axis, ends) in [
+            (BlockAxis::Y, [4, 5]),
+            (BlockAxis::X, [2, 3]),
+            (BlockAxis::Z, [0, 1]),
+        ] {
+            for face in 0..6 {
+                let (tile, _) = wood.face_tile(face, axis);
+                assert_eq!(tile == rings, ends.contains(&face), "{:?} {}", axis, face);
+            }
+        }
+    }
+
+    #[test]
+    fn faces_against_a_loaded_neighbour_are_culled() {
+        let mut map = Map::from_world(&mut World::new());
+        for position in [IVec2::ZERO, IVec2::X] {
+            map.chunks.insert(position, Chunk::new(position));
+        }
+        for x in 0..2 * CHUNK_SIZE {
+            for z in 0..CHUNK_SIZE {
+                for y in 0..4 {
+                    map.set_block(IVec3::new(x, y, z), BlockType::Stone);
+                }
+            }
+        }
+        let atlas = test_atlas();
+        let chunk = &map.chunks[&IVec2::ZERO];
+
+        let alone = ChunkView::from_chunk(chunk, BlockRing::default());
+        let beside = ChunkView::from_map(&map, IVec2::ZERO);
+        for greedy in [false, true] {
+            let alone = chunk.mesh_data(&atlas, &map.blocks, &alone, greedy);
+            let beside = chunk.mesh_data(&atlas, &map.blocks, &beside, greedy);
+            let faces = |data: &ChunkMeshData| data.opaque.indices.len() / 6;
+            // The +x side, 4 high and a chunk wide. Greedy meshing merges it into one.
+            let culled = if greedy { 1 } else { 4 * CHUNK_SIZE as usize };
+            assert_eq!(faces(&alone) - faces(&beside), culled);
+        }
+
+        // At half detail the side is 2 cells high and half a chunk wide.
+        map.chunks.get_mut(&IVec2::ZERO).unwrap().lod = 1;
+        let chunk = &map.chunks[&IVec2::ZERO];
+        let alone = ChunkView::from_chunk(chunk, BlockRing::default());
+        let beside = ChunkView::from_map(&map, IVec2::ZERO);
+        let alone = chunk.mesh_data(&atlas, &map.blocks, &alone, false);
+        let beside = chunk.mesh_data(&atlas, &map.blocks, &beside, false);
+        let faces = |data: &ChunkMeshData| data.opaque.indices.len() / 6;
+        assert_eq!(faces(&alone) - faces(&beside), CHUNK_SIZE as usize);
+    }
+
+    #[test]
+    fn faces_are_lit_from_their_own_side() {
+        let mut chunk = Chunk::new(IVec2::ZERO);
+        let pos = IVec3::new(5, 5, 5);
+        chunk.set(pos, BlockType::Stone);
+        let registry = BlockRegistry::default();
+        chunk.gen_occupancy(&registry);
+        let atlas = test_atlas();
+        let view = ChunkView::from_chunk(&chunk, BlockRing::default());
+        let mesh = chunk
+            .mesh_data(&atlas, &registry, &view, false)
+            .opaque
+            .into_mesh();
+
+        let Some(VertexAttributeValues::Float32x3(normals)) =
+            mesh.attribute(Mesh::ATTRIBUTE_NORMAL)
+        else {
+            panic!("no normals");
+        };
+        let Some(VertexAttributeValues::Float32x3(positions)) =
+            mesh.attribute(Mesh::ATTRIBUTE_POSITION)
+        else {
+            panic!("no positions");
+        };
+        assert_eq!(normals.len(), 4 * FACE_NORMALS.len());
+
+        // Each quad's middle is half a block out from the block's middle, along its normal.
+        let middle = (pos.as_vec3() + 0.5) * BLOCK_SIZE;
+        for (quad, quad_normals) in positions.chunks(4).zip(normals.chunks(4)) {
+            let center = quad.iter().map(|p| Vec3::from(*p)).sum::<Vec3>() / 4.0;
+            let facing = ((center - middle) * 2.0).round().as_ivec3();
+            assert!(FACE_NORMALS.contains(&facing));
+            for normal in quad_normals {
+                assert_eq!(Vec3::from(*normal), facing.as_vec3());
+            }
+        }
+    }
+
+    #[test]
+    fn torch_light_spreads_and_goes_away() {
+        let mut map = Map::from_world(&mut World::new());
+        map.chunks.insert(IVec2::ZERO, Chunk::new(IVec2::ZERO));
+        let torch = IVec3::new(16, 16, 16);
+
+        map.set_block(torch, BlockType::Torch);
+        assert_eq!(map.light(LightKind::Block, torch), 14);
+        assert_eq!(map.light(LightKind::Block, torch + IVec3::new(3, 0, 0)), 11);
+        assert_eq!(map.light(LightKind::Block, torch + IVec3::new(2, -2, 2)), 8);
+
+        // A wall across the whole chunk right next to it cuts the far side off.
+        for y in 0..CHUNK_HEIGHT {
+            for z in 0..CHUNK_SIZE {
+                map.set_block(IVec3::new(17, y, z), BlockType::Stone);
+            }
+        }
+        assert_eq!(map.light(LightKind::Block, torch + IVec3::new(2, 0, 0)), 0);
+
+        map.set_block(torch, BlockType::Air);
+        assert!(map.chunks[&IVec2::ZERO]
+            .block_light
+            .iter()
+            .all(|level| *level == 0));
+    }
+
+    #[test]
+    fn water_flows_into_holes_up_to_the_water_level() {
+        let mut map = Map::from_world(&mut World::new());
+        let mut chunk = Chunk::new(IVec2::ZERO);
+        for x in 0..CHUNK_SIZE {
+            for z in 0..CHUNK_SIZE {
+                for y in 0..=WATER_LEVEL {
+                    chunk.set(IVec3::new(x, y, z), BlockType::Stone);
+                }
+            }
+        }
+        let lake = IVec3::new(10, WATER_LEVEL, 10);
+        chunk.set(lake, BlockType::Water);
+        map.chunks.insert(IVec2::ZERO, chunk);
+
+        // Digging a two deep hole next to the lake fills it.
+        let hole = lake + IVec3::X;
+        map.set_block(hole, BlockType::Air);
+        map.set_block(hole - IVec3::Y, BlockType::Air);
+        // Water put above the water level stays where it is.
+        let high = IVec3::new(20, WATER_LEVEL + 3, 20);
+        map.set_block(high, BlockType::Water);
+
+        while !map.flows.is_empty() {
+            map.flow_water(WATER_FLOWS_PER_TICK);
+        }
+        assert_eq!(map.get_block(hole), Some(BlockType::Water));
+        assert_eq!(map.get_block(hole - IVec3::Y), Some(BlockType::Water));
+        assert_eq!(map.get_block(hole + IVec3::Y), None);
+        assert_eq!(map.get_block(high - IVec3::Y), None);
+    }
+
+    #[test]
+    fn surface_height_before_and_after_loading() {
+        let mut world = World::new();
+        world.insert_resource(WorldConfig {
+            gen_mode: GenMode::Flat,
+            ..default()
+        });
+        let mut map = Map::from_world(&mut world);
+        assert_eq!(map.surface_height(5, 5), FLAT_HEIGHT - 1);
+
+        let mut chunk = Chunk::new(IVec2::ZERO);
+        chunk.gen_blocks(&map.noise, &map.caves, &map.climate, &map.config);
+        map.chunks.insert(IVec2::ZERO, chunk);
+        assert_eq!(map.surface_height(5, 5), FLAT_HEIGHT - 1);
+
+        map.set_block(IVec3::new(5, FLAT_HEIGHT - 1, 5), BlockType::Air);
+        assert_eq!(map.surface_height(5, 5), FLAT_HEIGHT - 2);
+        map.set_block(IVec3::new(5, FLAT_HEIGHT + 10, 5), BlockType::Stone);
+        assert_eq!(map.surface_height(5, 5), FLAT_HEIGHT + 10);
+    }
+
+    #[test]
+    fn setting_the_same_block_does_nothing() {
+        let mut map = Map::from_world(&mut World::new());
+        map.chunks.insert(IVec2::ZERO, Chunk::new(IVec2::ZERO));
+        let pos = IVec3::new(3, 40, 3);
+
+        assert!(map.set_block(pos, BlockType::Air));
+        let chunk = &map.chunks[&IVec2::ZERO];
+        assert!(!chunk.modified && !chunk.dirty);
+        assert!(map.updates.is_empty() && map.flows.is_empty());
+
+        assert!(map.set_block(pos, BlockType::Stone));
+        assert!(map.chunks[&IVec2::ZERO].modified);
+    }
+
+    #[test]
+    fn raised_water_level_floods_flat_worlds_and_is_saved() {
+        let mut world = World::new();
+        world.insert_resource(WorldConfig {
+            gen_mode: GenMode::Flat,
+            water_level: FLAT_HEIGHT + 2,
+            ..default()
+        });
+        let mut map = Map::from_world(&mut world);
+        assert_eq!(map.surface_height(5, 5), FLAT_HEIGHT + 2);
+        assert!(map.is_ocean(5, 5));
+
+        let mut chunk = Chunk::new(IVec2::ZERO);
+        chunk.gen_blocks(&map.noise, &map.caves, &map.climate, &map.config);
+        assert_eq!(chunk.get(IVec3::new(5, FLAT_HEIGHT, 5)), BlockType::Water);
+        assert_eq!(
+            chunk.get(IVec3::new(5, FLAT_HEIGHT + 2, 5)),
+            BlockType::Water
+        );
+        assert_eq!(chunk.get(IVec3::new(5, FLAT_HEIGHT + 3, 5)), BlockType::Air);
+
+        // A map made with the default level takes the saved one.
+        let dir = std::env::temp_dir().join(format!("mc_water_{}", std::process::id()));
+        map.save(&dir, None).unwrap();
+        let mut loaded = Map::from_world(&mut World::new());
+        assert_eq!(loaded.water_level(), WATER_LEVEL);
+        loaded.load(&dir).unwrap();
+        assert_eq!(loaded.water_level(), FLAT_HEIGHT + 2);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn respawning_a_chunk_replaces_its_entity() {
+        let mut world = World::new();
+        let mut map = Map::from_world(&mut world);
+        map.chunks.insert(IVec2::ZERO, Chunk::new(IVec2::ZERO));
+
+        let mut queue = CommandQueue::default();
+        let mut commands = Commands::new(&mut queue, &world);
+        respawn_chunk(&mut commands, &mut map, IVec2::ZERO);
+        respawn_chunk(&mut commands, &mut map, IVec2::ZERO);
+        queue.apply(&mut world);
+
+        let spawned: Vec<Entity> = world
+            .query_filtered::<Entity, With<Chunk>>()
+            .iter(&world)
+            .collect();
+        assert_eq!(spawned, vec![map.chunk_entity(IVec2::ZERO).unwrap()]);
+
+        let mut commands = Commands::new(&mut queue, &world);
+        despawn_chunk(&mut commands, &mut map, IVec2::ZERO);
+        queue.apply(&mut world);
+        assert_eq!(map.chunk_entity(IVec2::ZERO), None);
+        assert_eq!(world.query::<&Chunk>().iter(&world).count(), 0);
+    }
+
+    #[test]
+    fn sky_light_follows_overhangs() {
+        let mut map = Map::from_world(&mut World::new());
+        map.chunks.insert(IVec2::ZERO, Chunk::new(IVec2::ZERO));
+        map.light_chunk(IVec2::ZERO);
+        let below = IVec3::new(16, 10, 16);
+        assert_eq!(map.light(LightKind::Sky, below), MAX_LIGHT);
+
+        // A 5x5 roof: the middle of the shade underneath is three blocks in from open sky.
+        for x in -2..=2 {
+            for z in -2..=2 {
+                map.set_block(below + IVec3::new(x, 5, z), BlockType::Stone);
+            }
+        }
+        assert_eq!(map.light(LightKind::Sky, below), MAX_LIGHT - 3);
+        assert_eq!(map.light(LightKind::Sky, below + IVec3::Y * 10), MAX_LIGHT);
+
+        map.set_block(below + IVec3::Y * 5, BlockType::Air);
+        assert_eq!(map.light(LightKind::Sky, below), MAX_LIGHT);
+    }
+}